@@ -2,6 +2,7 @@ import './style.css'
 
 // --- Types ---
 interface Game {
+  id: string;
   name: string;
   path: string;
   relative_path: string;
@@ -12,6 +13,8 @@ interface Game {
   latest_version?: string;
   category: string; // "Base", "Update", "DLC"
   publisher?: string;
+  release_date?: string;
+  added_at?: number;
 }
 
 interface GroupedGame {
@@ -22,6 +25,7 @@ interface GroupedGame {
 
 interface Download {
   id: string;
+  game_id?: string;
   filename: string;
   total_size: number;
   bytes_sent: number;
@@ -44,7 +48,18 @@ interface SyncStatus {
     status: "complete";
 }
 
-type SSEMessage = ScanStatus | DownloadUpdate | SyncStatus;
+interface LibraryUpdate {
+    type: "library";
+    status: "updated";
+    count: number;
+}
+
+interface UpdatesAvailable {
+    type: "updates_available";
+    titles: { base_title_id: string; name: string; owned_version: number; latest_version: number }[];
+}
+
+type SSEMessage = ScanStatus | DownloadUpdate | SyncStatus | LibraryUpdate | UpdatesAvailable;
 
 // --- State ---
 let games: Game[] = [];
@@ -131,9 +146,15 @@ async function fetchInfo() {
 
 async function fetchGames() {
   try {
-    const response = await fetch('/api/games');
-    if (!response.ok) throw new Error('Network response was not ok');
-    games = await response.json();
+    const all: Game[] = [];
+    for (let page = 1; ; page++) {
+      const response = await fetch(`/api/games?per_page=500&page=${page}`);
+      if (!response.ok) throw new Error('Network response was not ok');
+      const body = await response.json();
+      all.push(...body.games);
+      if (body.games.length === 0 || all.length >= body.total) break;
+    }
+    games = all;
     groupedGames = groupGames(games);
     render();
   } catch (error) {
@@ -166,6 +187,11 @@ function setupSSE() {
                     isSyncing = false;
                     fetchGames();
                 }
+            } else if (msg.type === "library") {
+                fetchGames();
+            } else if (msg.type === "updates_available") {
+                console.info(`Updates available for ${msg.titles.map(t => t.name).join(", ")}`);
+                fetchGames();
             }
         } catch (e) {
             console.error("Error parsing SSE", e);
@@ -351,7 +377,7 @@ const GameList = () => {
                                 
                                 <div class="flex items-center gap-3">
                                     <span class="text-xs font-mono text-slate-500">${formatBytes(file.size)}</span>
-                                    <a href="/files/${encodeURIComponent(file.relative_path)}" download 
+                                    <a href="/api/games/${file.id}/download" download 
                                        class="p-1.5 text-slate-400 hover:text-white hover:bg-indigo-600 rounded-md transition-colors"
                                        title="Download">
                                         ${Icons.Download}
@@ -0,0 +1,264 @@
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// How `/api/catalog/search` interprets the query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// The name or title ID starts with the query.
+    Prefix,
+    /// The query appears anywhere in the name or title ID. Queries shorter
+    /// than three characters fall back to `token`.
+    Substring,
+    /// Every word of the query starts a word of the name, in any order.
+    #[default]
+    Token,
+}
+
+/// Lowercase ASCII words separated by single spaces, so "Pokémon™ Sword"
+/// is found by "pokemon sword".
+pub fn fold(text: &str) -> String {
+    let ascii = deunicode::deunicode(&text.replace(['™', '®', '©'], "")).to_lowercase();
+    ascii
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Search index over every known title. Names are folded once; word
+/// starts and IDs are kept sorted for prefix lookups and trigrams map to
+/// the titles containing them for substring lookups.
+#[derive(Default)]
+pub struct CatalogIndex {
+    ids: Vec<String>,
+    names: Vec<String>,
+    /// Suffixes of the folded names starting at each word, as (title, byte
+    /// offset), sorted by the suffix.
+    words: Vec<(u32, u16)>,
+    /// Titles sorted by lowercase title ID.
+    id_order: Vec<u32>,
+    trigrams: HashMap<[u8; 3], Vec<u32>>,
+}
+
+impl CatalogIndex {
+    pub fn build(titles: impl IntoIterator<Item = (String, Option<String>)>) -> Self {
+        let mut index = CatalogIndex::default();
+        for (id, name) in titles {
+            let pos = index.ids.len() as u32;
+            let name = fold(name.as_deref().unwrap_or_default());
+
+            let mut offset = 0;
+            for word in name.split(' ').filter(|w| !w.is_empty()) {
+                if let Ok(start) = u16::try_from(offset) {
+                    index.words.push((pos, start));
+                }
+                offset += word.len() + 1;
+            }
+
+            let id = id.to_lowercase();
+            let mut seen = HashSet::new();
+            for text in [name.as_bytes(), id.as_bytes()] {
+                for gram in text.windows(3) {
+                    let gram = [gram[0], gram[1], gram[2]];
+                    if seen.insert(gram) {
+                        index.trigrams.entry(gram).or_default().push(pos);
+                    }
+                }
+            }
+
+            index.ids.push(id);
+            index.names.push(name);
+        }
+
+        let (names, ids) = (&index.names, &index.ids);
+        index.words.sort_by(|a, b| {
+            names[a.0 as usize][a.1 as usize..].cmp(&names[b.0 as usize][b.1 as usize..])
+        });
+        index.id_order = (0..ids.len() as u32).collect();
+        index
+            .id_order
+            .sort_by(|a, b| ids[*a as usize].cmp(&ids[*b as usize]));
+        index.trigrams.shrink_to_fit();
+        index
+    }
+
+    pub fn title_id(&self, pos: u32) -> String {
+        self.ids[pos as usize].to_uppercase()
+    }
+
+    /// Positions of the titles matching `query`, best match first: exact
+    /// names, then names starting with the query, then the rest by name.
+    pub fn search(&self, query: &str, mode: SearchMode) -> Vec<u32> {
+        let query = fold(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut found: Vec<u32> = match mode {
+            SearchMode::Prefix => {
+                let mut found: Vec<u32> = self
+                    .word_range(&query)
+                    .filter(|(_, offset)| *offset == 0)
+                    .map(|(pos, _)| pos)
+                    .collect();
+                found.extend(self.id_range(&query));
+                found
+            }
+            SearchMode::Substring if query.len() >= 3 => self.substring(&query),
+            SearchMode::Substring | SearchMode::Token => self.tokens(&query),
+        };
+        found.sort_unstable();
+        found.dedup();
+
+        let rank = |pos: u32| {
+            let name = &self.names[pos as usize];
+            if *name == query {
+                0
+            } else if name.starts_with(&query) {
+                1
+            } else {
+                2
+            }
+        };
+        found.sort_by(|a, b| {
+            rank(*a)
+                .cmp(&rank(*b))
+                .then_with(|| self.names[*a as usize].cmp(&self.names[*b as usize]))
+                .then_with(|| self.ids[*a as usize].cmp(&self.ids[*b as usize]))
+        });
+        found
+    }
+
+    /// All titles, sorted by name, for browsing with filters only.
+    pub fn all(&self) -> Vec<u32> {
+        let mut all: Vec<u32> = (0..self.ids.len() as u32).collect();
+        all.sort_by(|a, b| {
+            self.names[*a as usize]
+                .cmp(&self.names[*b as usize])
+                .then_with(|| self.ids[*a as usize].cmp(&self.ids[*b as usize]))
+        });
+        all
+    }
+
+    /// Words (with the rest of their name) starting with `prefix`.
+    fn word_range<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (u32, u16)> + 'a {
+        let suffix = |&(pos, offset): &(u32, u16)| &self.names[pos as usize][offset as usize..];
+        let start = self
+            .words
+            .partition_point(|w| suffix(w).cmp(prefix) == Ordering::Less);
+        self.words[start..]
+            .iter()
+            .take_while(move |w| suffix(w).starts_with(prefix))
+            .copied()
+    }
+
+    fn id_range<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = u32> + 'a {
+        let start = self
+            .id_order
+            .partition_point(|p| self.ids[*p as usize].as_str() < prefix);
+        self.id_order[start..]
+            .iter()
+            .take_while(move |p| self.ids[**p as usize].starts_with(prefix))
+            .copied()
+    }
+
+    fn tokens(&self, query: &str) -> Vec<u32> {
+        let mut result: Option<HashSet<u32>> = None;
+        for token in query.split(' ') {
+            let mut matches: HashSet<u32> = self.word_range(token).map(|(pos, _)| pos).collect();
+            matches.extend(self.id_range(token));
+            result = Some(match result {
+                Some(previous) => previous.intersection(&matches).copied().collect(),
+                None => matches,
+            });
+        }
+        result.unwrap_or_default().into_iter().collect()
+    }
+
+    fn substring(&self, query: &str) -> Vec<u32> {
+        let mut postings: Vec<&Vec<u32>> = Vec::new();
+        for gram in query.as_bytes().windows(3) {
+            match self.trigrams.get(&[gram[0], gram[1], gram[2]]) {
+                Some(list) => postings.push(list),
+                None => return Vec::new(),
+            }
+        }
+        // Postings are in title order; intersect starting from the rarest
+        // trigram, then confirm
+        postings.sort_by_key(|list| list.len());
+        let Some((first, rest)) = postings.split_first() else {
+            return Vec::new();
+        };
+        first
+            .iter()
+            .copied()
+            .filter(|pos| rest.iter().all(|list| list.binary_search(pos).is_ok()))
+            .filter(|pos| {
+                self.names[*pos as usize].contains(query) || self.ids[*pos as usize].contains(query)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> CatalogIndex {
+        CatalogIndex::build([
+            ("01002B30028F6000".to_string(), Some("Celeste".to_string())),
+            (
+                "0100000000010000".to_string(),
+                Some("Celeste Classic".to_string()),
+            ),
+            (
+                "0100ABF008968000".to_string(),
+                Some("Pokémon™ Sword".to_string()),
+            ),
+            (
+                "0100000000020000".to_string(),
+                Some("The Legend of Zelda".to_string()),
+            ),
+            ("0100000000030000".to_string(), None),
+        ])
+    }
+
+    fn ids(index: &CatalogIndex, found: Vec<u32>) -> Vec<String> {
+        found.into_iter().map(|p| index.title_id(p)).collect()
+    }
+
+    #[test]
+    fn test_catalog_search_modes() {
+        let index = index();
+
+        assert_eq!(
+            ids(&index, index.search("cel", SearchMode::Prefix)),
+            vec!["01002B30028F6000", "0100000000010000"]
+        );
+        assert_eq!(
+            ids(&index, index.search("0100ABF", SearchMode::Prefix)),
+            vec!["0100ABF008968000"]
+        );
+        // Prefix matches only the start of the name, token any word
+        assert!(index.search("zelda", SearchMode::Prefix).is_empty());
+        assert_eq!(
+            ids(&index, index.search("zel leg", SearchMode::Token)),
+            vec!["0100000000020000"]
+        );
+        assert_eq!(
+            ids(&index, index.search("pokemon", SearchMode::Token)),
+            vec!["0100ABF008968000"]
+        );
+        assert_eq!(
+            ids(&index, index.search("lass", SearchMode::Substring)),
+            vec!["0100000000010000"]
+        );
+        assert_eq!(
+            ids(&index, index.search("000003", SearchMode::Substring)),
+            vec!["0100000000030000"]
+        );
+        assert!(index.search("xyz", SearchMode::Substring).is_empty());
+    }
+}
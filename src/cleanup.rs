@@ -0,0 +1,325 @@
+use crate::downloads::now;
+use crate::jsonfile;
+use crate::scanner::Game;
+use crate::titles::version_number;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// A file that can go because another owned file makes it redundant.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct CleanupCandidate {
+    pub relative_path: String,
+    pub title_id: Option<String>,
+    pub version: Option<String>,
+    pub size: u64,
+    /// `superseded` for an older update or DLC version, `format` for a copy
+    /// in a less preferred format.
+    pub reason: &'static str,
+    /// The file that replaces it.
+    pub replaced_by: String,
+}
+
+/// Finds updates and DLC for which a newer version is owned and, when
+/// `preferred_formats` is given, files present in several formats, keeping
+/// the format listed first.
+pub fn find_redundant(
+    games: &[Game],
+    preferred_formats: Option<&[String]>,
+) -> Vec<CleanupCandidate> {
+    let mut candidates = Vec::new();
+
+    let mut by_title: BTreeMap<&str, Vec<&Game>> = BTreeMap::new();
+    for game in games {
+        if let Some(tid) = game.title_id.as_deref() {
+            by_title.entry(tid).or_default().push(game);
+        }
+    }
+
+    for files in by_title.values() {
+        // Updates are cumulative and DLC versions replace each other, so
+        // only the newest version of either is needed
+        let versioned: Vec<&&Game> = files
+            .iter()
+            .filter(|g| g.category == "Update" || g.category == "DLC")
+            .collect();
+        if let Some(newest) = versioned.iter().max_by_key(|g| version_number(g)) {
+            let newest_version = version_number(newest);
+            for game in &versioned {
+                if version_number(game) < newest_version {
+                    candidates.push(candidate(game, "superseded", newest));
+                }
+            }
+        }
+
+        let Some(preferred) = preferred_formats else {
+            continue;
+        };
+        let rank = |g: &Game| {
+            preferred
+                .iter()
+                .position(|f| f.eq_ignore_ascii_case(&g.format))
+                .unwrap_or(preferred.len())
+        };
+        let mut by_version: BTreeMap<Option<u64>, Vec<&Game>> = BTreeMap::new();
+        for game in files.iter() {
+            by_version
+                .entry(version_number(game))
+                .or_default()
+                .push(game);
+        }
+        for copies in by_version.values() {
+            let Some(best) = copies
+                .iter()
+                .min_by_key(|g| (rank(g), g.relative_path.clone()))
+            else {
+                continue;
+            };
+            for game in copies {
+                if game.format != best.format
+                    && !candidates
+                        .iter()
+                        .any(|c| c.relative_path == game.relative_path)
+                {
+                    candidates.push(candidate(game, "format", best));
+                }
+            }
+        }
+    }
+    candidates
+}
+
+fn candidate(game: &Game, reason: &'static str, replaced_by: &Game) -> CleanupCandidate {
+    CleanupCandidate {
+        relative_path: game.relative_path.clone(),
+        title_id: game.title_id.clone(),
+        version: game.version.clone(),
+        size: game.size,
+        reason,
+        replaced_by: replaced_by.relative_path.clone(),
+    }
+}
+
+/// A file moved out of the library into the quarantine.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QuarantineEntry {
+    pub id: String,
+    /// Where the file was, relative to `games_dir`.
+    pub relative_path: String,
+    pub size: u64,
+    /// Unix timestamp of when it was quarantined.
+    pub quarantined_at: u64,
+    pub reason: String,
+}
+
+/// Files set aside by the cleanup, kept under `data_dir/quarantine` until
+/// they are restored or purged. The trash for files deleted through the
+/// API works the same way in `data_dir/trash`. The index is persisted in
+/// `index.json`.
+pub struct Quarantine {
+    dir: PathBuf,
+    entries: Vec<QuarantineEntry>,
+}
+
+/// Renames `from` to `to`, copying across filesystems when needed, since
+/// `data_dir` and `games_dir` are often different mounts.
+pub async fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    match tokio::fs::rename(from, to).await {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            tokio::fs::copy(from, to).await?;
+            tokio::fs::remove_file(from).await
+        }
+        result => result,
+    }
+}
+
+impl Quarantine {
+    pub fn new(data_dir: &Path) -> Self {
+        Self::in_dir(data_dir.join("quarantine"))
+    }
+
+    /// The same kind of store somewhere else, e.g. the trash.
+    pub fn in_dir(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            entries: Vec::new(),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        if let Some(entries) = jsonfile::load(&self.dir.join("index.json")).await {
+            self.entries = entries;
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        jsonfile::save_pretty(&self.dir.join("index.json"), &self.entries).await
+    }
+
+    pub fn entries(&self) -> &[QuarantineEntry] {
+        &self.entries
+    }
+
+    fn stored_path(&self, entry: &QuarantineEntry) -> PathBuf {
+        let filename = Path::new(&entry.relative_path)
+            .file_name()
+            .map(|f| f.to_os_string())
+            .unwrap_or_default();
+        self.dir.join("files").join(&entry.id).join(filename)
+    }
+
+    /// Moves `game`'s file into the quarantine.
+    pub async fn add(&mut self, game: &Game, reason: &str) -> std::io::Result<QuarantineEntry> {
+        let entry = QuarantineEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            relative_path: game.relative_path.clone(),
+            size: game.size,
+            quarantined_at: now(),
+            reason: reason.to_string(),
+        };
+        move_file(&game.path, &self.stored_path(&entry)).await?;
+        info!("Quarantined {}", entry.relative_path);
+        self.entries.push(entry.clone());
+        self.save().await?;
+        Ok(entry)
+    }
+
+    /// Moves a quarantined file back to where it was under `games_dir` and
+    /// returns its path there. Refuses to overwrite an existing file.
+    pub async fn restore(&mut self, id: &str, games_dir: &Path) -> std::io::Result<PathBuf> {
+        let Some(idx) = self.entries.iter().position(|e| e.id == id) else {
+            return Err(std::io::ErrorKind::NotFound.into());
+        };
+        let entry = &self.entries[idx];
+        let target = games_dir.join(&entry.relative_path);
+        if target.exists() {
+            return Err(std::io::ErrorKind::AlreadyExists.into());
+        }
+        move_file(&self.stored_path(entry), &target).await?;
+        let _ = tokio::fs::remove_dir(self.dir.join("files").join(id)).await;
+        info!("Restored {}", entry.relative_path);
+        self.entries.remove(idx);
+        self.save().await?;
+        Ok(target)
+    }
+
+    /// Deletes a quarantined file for good.
+    pub async fn purge(&mut self, id: &str) -> std::io::Result<QuarantineEntry> {
+        let Some(idx) = self.entries.iter().position(|e| e.id == id) else {
+            return Err(std::io::ErrorKind::NotFound.into());
+        };
+        let entry = self.entries.remove(idx);
+        match tokio::fs::remove_dir_all(self.dir.join("files").join(&entry.id)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                self.entries.insert(idx, entry);
+                return Err(e);
+            }
+            _ => {}
+        }
+        info!("Purged {} from quarantine", entry.relative_path);
+        self.save().await?;
+        Ok(entry)
+    }
+
+    /// Purges every file quarantined more than `retention_days` ago.
+    pub async fn purge_expired(&mut self, retention_days: u64) -> Vec<QuarantineEntry> {
+        let cutoff = now().saturating_sub(retention_days * 24 * 3600);
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|e| e.quarantined_at < cutoff)
+            .map(|e| e.id.clone())
+            .collect();
+        let mut purged = Vec::new();
+        for id in expired {
+            match self.purge(&id).await {
+                Ok(entry) => purged.push(entry),
+                Err(e) => warn!("Failed to purge quarantined file {}: {}", id, e),
+            }
+        }
+        purged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn game(path: &str, title_id: &str, version: &str, category: &str, size: u64) -> Game {
+        Game {
+            relative_path: path.to_string(),
+            format: path.rsplit('.').next().unwrap().to_string(),
+            title_id: Some(title_id.to_string()),
+            version: Some(version.to_string()),
+            category: category.to_string(),
+            size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_redundant() {
+        let games = vec![
+            game("Base.nsp", "0100000000010000", "v0", "Base", 100),
+            game("Base.nsz", "0100000000010000", "v0", "Base", 60),
+            game("Upd1.nsp", "0100000000010800", "v65536", "Update", 10),
+            game("Upd2.nsp", "0100000000010800", "v131072", "Update", 20),
+            game("Upd3.nsp", "0100000000010800", "v196608", "Update", 30),
+            game("Dlc.nsp", "0100000000011001", "v0", "DLC", 5),
+        ];
+
+        let found = find_redundant(&games, None);
+        assert_eq!(found.len(), 2);
+        assert!(
+            found
+                .iter()
+                .all(|c| c.reason == "superseded" && c.replaced_by == "Upd3.nsp")
+        );
+        assert_eq!(found.iter().map(|c| c.size).sum::<u64>(), 30);
+
+        let formats = vec!["nsp".to_string(), "nsz".to_string()];
+        let found = find_redundant(&games, Some(&formats));
+        let format = found.iter().find(|c| c.reason == "format").unwrap();
+        assert_eq!(format.relative_path, "Base.nsz");
+        assert_eq!(format.replaced_by, "Base.nsp");
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_restore_and_purge() {
+        let tmp = tempdir().unwrap();
+        let games_dir = tmp.path().join("games");
+        std::fs::create_dir_all(games_dir.join("Updates")).unwrap();
+        let path = games_dir.join("Updates").join("Old.nsp");
+        std::fs::write(&path, "old").unwrap();
+        let mut old = game("Updates/Old.nsp", "0100000000010800", "v65536", "Update", 3);
+        old.path = path.clone();
+
+        let mut quarantine = Quarantine::new(tmp.path());
+        let entry = quarantine.add(&old, "superseded").await.unwrap();
+        assert!(!path.exists());
+
+        let mut reloaded = Quarantine::new(tmp.path());
+        reloaded.load().await;
+        assert_eq!(reloaded.entries().len(), 1);
+        assert_eq!(reloaded.entries()[0], entry);
+
+        let restored = reloaded.restore(&entry.id, &games_dir).await.unwrap();
+        assert_eq!(restored, path);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+        assert!(reloaded.entries().is_empty());
+
+        let entry = reloaded.add(&old, "superseded").await.unwrap();
+        assert!(reloaded.purge_expired(1).await.is_empty());
+        reloaded.entries[0].quarantined_at -= 2 * 24 * 3600;
+        let purged = reloaded.purge_expired(1).await;
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].id, entry.id);
+        assert!(reloaded.entries().is_empty());
+        assert!(!tmp.path().join("quarantine/files").join(&entry.id).exists());
+    }
+}
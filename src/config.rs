@@ -1,3 +1,5 @@
+use crate::metadata::Locale;
+use crate::sources::MergeRules;
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
 use std::fmt;
@@ -14,6 +16,23 @@ pub struct Settings {
     pub webdav_enabled: bool,
     pub metadata_region: String,
     pub metadata_language: String,
+    pub metadata_locales: Option<String>,
+    pub metadata_library_only_details: bool,
+    pub title_match_threshold: f64,
+    pub metadata_sources: String,
+    pub metadata_field_sources: Option<String>,
+    pub metadata_remote_url: Option<String>,
+    pub metadata_remote_interval_hours: u64,
+    pub cleanup_preferred_formats: String,
+    pub quarantine_retention_days: u64,
+    pub trash_retention_days: u64,
+    pub organize_template: String,
+    pub inbox_dir: Option<PathBuf>,
+    pub inbox_settle_secs: u64,
+    pub integrity_enabled: bool,
+    pub integrity_verify_days: u64,
+    pub integrity_repr_digest: bool,
+    pub hide_broken_files: bool,
     pub tinfoil_encrypt: bool,
 }
 
@@ -27,6 +46,29 @@ impl fmt::Debug for Settings {
             .field("webdav_enabled", &self.webdav_enabled)
             .field("metadata_region", &self.metadata_region)
             .field("metadata_language", &self.metadata_language)
+            .field("metadata_locales", &self.metadata_locales)
+            .field(
+                "metadata_library_only_details",
+                &self.metadata_library_only_details,
+            )
+            .field("title_match_threshold", &self.title_match_threshold)
+            .field("metadata_sources", &self.metadata_sources)
+            .field("metadata_field_sources", &self.metadata_field_sources)
+            .field("metadata_remote_url", &self.metadata_remote_url)
+            .field(
+                "metadata_remote_interval_hours",
+                &self.metadata_remote_interval_hours,
+            )
+            .field("cleanup_preferred_formats", &self.cleanup_preferred_formats)
+            .field("quarantine_retention_days", &self.quarantine_retention_days)
+            .field("trash_retention_days", &self.trash_retention_days)
+            .field("organize_template", &self.organize_template)
+            .field("inbox_dir", &self.inbox_dir)
+            .field("inbox_settle_secs", &self.inbox_settle_secs)
+            .field("integrity_enabled", &self.integrity_enabled)
+            .field("integrity_verify_days", &self.integrity_verify_days)
+            .field("integrity_repr_digest", &self.integrity_repr_digest)
+            .field("hide_broken_files", &self.hide_broken_files)
             .field(
                 "webdav_username",
                 &self.webdav_username.as_ref().map(|_| "***"),
@@ -52,6 +94,26 @@ impl Settings {
             .set_default("webdav_enabled", true)?
             .set_default("metadata_region", "US")?
             .set_default("metadata_language", "en")?
+            .set_default("metadata_locales", None::<String>)?
+            .set_default("metadata_library_only_details", false)?
+            .set_default("title_match_threshold", 0.9)?
+            .set_default("metadata_sources", "overrides,catalogue,titledb,remote")?
+            .set_default("metadata_field_sources", None::<String>)?
+            .set_default("metadata_remote_url", None::<String>)?
+            .set_default("metadata_remote_interval_hours", 1)?
+            .set_default("cleanup_preferred_formats", "nsp,nsz,xci,xcz")?
+            .set_default("quarantine_retention_days", 30)?
+            .set_default("trash_retention_days", 30)?
+            .set_default(
+                "organize_template",
+                "{category}/{name} [{title_id}][v{version}].{ext}",
+            )?
+            .set_default("inbox_dir", None::<String>)?
+            .set_default("inbox_settle_secs", 10)?
+            .set_default("integrity_enabled", true)?
+            .set_default("integrity_verify_days", 30)?
+            .set_default("integrity_repr_digest", false)?
+            .set_default("hide_broken_files", false)?
             .set_default("tinfoil_encrypt", false)?
             // Config file (optional)
             .add_source(File::with_name("config").required(false))
@@ -60,4 +122,41 @@ impl Settings {
 
         builder.build()?.try_deserialize()
     }
+
+    /// Ordered titledb locales to merge, e.g. `US.en, GB.en, JP.ja`. Falls
+    /// back to `metadata_region`/`metadata_language` when not configured.
+    pub fn locales(&self) -> Vec<Locale> {
+        let parsed: Vec<Locale> = self
+            .metadata_locales
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|s| s.parse().ok())
+            .collect();
+        if parsed.is_empty() {
+            vec![Locale {
+                region: self.metadata_region.clone(),
+                language: self.metadata_language.clone(),
+            }]
+        } else {
+            parsed
+        }
+    }
+
+    /// File formats in order of preference when a game is owned in several.
+    pub fn preferred_formats(&self) -> Vec<String> {
+        self.cleanup_preferred_formats
+            .split(',')
+            .map(|f| f.trim().to_lowercase())
+            .filter(|f| !f.is_empty())
+            .collect()
+    }
+
+    /// Priority order of the metadata sources, with per-field exceptions.
+    pub fn merge_rules(&self) -> MergeRules {
+        MergeRules::parse(
+            &self.metadata_sources,
+            self.metadata_field_sources.as_deref(),
+        )
+    }
 }
@@ -0,0 +1,359 @@
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Upper bounds that keep a corrupt header from triggering huge reads.
+const MAX_ENTRIES: u32 = 4096;
+const MAX_STRING_TABLE: u32 = 1 << 20;
+const MAX_NCZ_SECTIONS: u64 = 0x100;
+
+/// NCZ files keep the NCA header uncompressed, followed by the section
+/// table.
+const NCA_HEADER_LEN: u64 = 0x4000;
+const NCZ_SECTION_LEN: u64 = 0x40;
+
+/// A file inside a container. Offsets are from the start of the file.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ContainerEntry {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// The layout of an NSP/NSZ (a PFS0 archive) or XCI/XCZ (a gamecard image
+/// whose HFS0 partitions hold the content).
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ContainerInfo {
+    /// `pfs0` or `xci`.
+    pub kind: &'static str,
+    /// For XCI, the files of every partition, named `partition/file`.
+    pub entries: Vec<ContainerEntry>,
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_u32(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+/// Reads a PFS0 or HFS0 file table starting at `start`. The two formats
+/// only differ in magic and entry size.
+fn read_table<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    magic: &[u8; 4],
+    entry_size: usize,
+) -> std::io::Result<Vec<ContainerEntry>> {
+    reader.seek(SeekFrom::Start(start))?;
+    let mut header = [0u8; 0x10];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != magic {
+        return Err(invalid("Unexpected container magic"));
+    }
+    let count = read_u32(&header, 4);
+    let strings_size = read_u32(&header, 8);
+    if count > MAX_ENTRIES || strings_size > MAX_STRING_TABLE {
+        return Err(invalid("Container table is too large"));
+    }
+
+    let mut table = vec![0u8; count as usize * entry_size];
+    reader.read_exact(&mut table)?;
+    let mut strings = vec![0u8; strings_size as usize];
+    reader.read_exact(&mut strings)?;
+    let data_start = start + 0x10 + table.len() as u64 + strings.len() as u64;
+
+    table
+        .chunks_exact(entry_size)
+        .map(|entry| {
+            let name_start = read_u32(entry, 16) as usize;
+            let name = strings
+                .get(name_start..)
+                .and_then(|s| s.split(|b| *b == 0).next())
+                .ok_or_else(|| invalid("Entry name is outside the string table"))?;
+            Ok(ContainerEntry {
+                name: String::from_utf8_lossy(name).to_string(),
+                offset: data_start + read_u64(entry, 0),
+                size: read_u64(entry, 8),
+            })
+        })
+        .collect()
+}
+
+/// How much of the start of a file [`has_magic`] needs.
+pub const MAGIC_LEN: usize = 0x104;
+
+/// Whether `header`, the first [`MAGIC_LEN`] bytes of a file or all of a
+/// shorter one, starts like an NSP or XCI.
+pub fn has_magic(header: &[u8]) -> bool {
+    header.starts_with(b"PFS0") || header.get(0x100..0x104) == Some(b"HEAD")
+}
+
+/// The HFS0 partitions of an XCI with the files of each, or `None` for a
+/// PFS0 file.
+type Partitions = Option<Vec<(ContainerEntry, Vec<ContainerEntry>)>>;
+
+fn read_container(file: &mut std::fs::File) -> std::io::Result<(Vec<ContainerEntry>, Partitions)> {
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic)?;
+    if &magic == b"PFS0" {
+        return Ok((read_table(file, 0, b"PFS0", 0x18)?, None));
+    }
+
+    let mut header = [0u8; 0x40];
+    file.seek(SeekFrom::Start(0x100))?;
+    file.read_exact(&mut header)
+        .map_err(|_| invalid("Not an NSP or XCI file"))?;
+    if &header[..4] != b"HEAD" {
+        return Err(invalid("Not an NSP or XCI file"));
+    }
+    let root_offset = read_u64(&header, 0x30);
+    let len = file.metadata()?.len();
+    let mut partitions = Vec::new();
+    let mut entries = Vec::new();
+    for partition in read_table(file, root_offset, b"HFS0", 0x40)? {
+        check_bounds(&partition, len)?;
+        let files = read_table(file, partition.offset, b"HFS0", 0x40)?;
+        for entry in &files {
+            entries.push(ContainerEntry {
+                name: format!("{}/{}", partition.name, entry.name),
+                ..entry.clone()
+            });
+        }
+        partitions.push((partition, files));
+    }
+    Ok((entries, Some(partitions)))
+}
+
+/// Reads the file table of a game file. Only the headers are read.
+pub fn inspect(path: &Path) -> std::io::Result<ContainerInfo> {
+    let mut file = std::fs::File::open(path)?;
+    let (entries, partitions) = read_container(&mut file)?;
+    Ok(ContainerInfo {
+        kind: if partitions.is_some() { "xci" } else { "pfs0" },
+        entries,
+    })
+}
+
+fn end(entry: &ContainerEntry) -> Option<u64> {
+    entry.offset.checked_add(entry.size)
+}
+
+fn check_bounds(entry: &ContainerEntry, len: u64) -> std::io::Result<()> {
+    match end(entry) {
+        Some(end) if end <= len => Ok(()),
+        _ => Err(invalid(&format!(
+            "{} extends past the end of the file",
+            entry.name
+        ))),
+    }
+}
+
+/// Checks the section table that follows the NCA header of an NCZ.
+fn check_ncz(file: &mut std::fs::File, entry: &ContainerEntry) -> std::io::Result<()> {
+    let bad = || invalid(&format!("{} has no valid NCZ section header", entry.name));
+    if entry.size < NCA_HEADER_LEN + 0x10 {
+        return Err(bad());
+    }
+    let mut header = [0u8; 0x10];
+    file.seek(SeekFrom::Start(entry.offset + NCA_HEADER_LEN))?;
+    file.read_exact(&mut header)?;
+    let count = read_u64(&header, 8);
+    if &header[..8] != b"NCZSECTN"
+        || count == 0
+        || count > MAX_NCZ_SECTIONS
+        || NCA_HEADER_LEN + 0x10 + count * NCZ_SECTION_LEN > entry.size
+    {
+        return Err(bad());
+    }
+    Ok(())
+}
+
+/// Checks that the headers of a game file are well formed and that every
+/// file they list lies within it, which catches truncated copies without
+/// reading their content. NCZ files inside NSZ/XCZ must start with a valid
+/// section header.
+pub fn validate(path: &Path) -> std::io::Result<()> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let (entries, partitions) = read_container(&mut file)?;
+    for (partition, files) in partitions.iter().flatten() {
+        if let Some(entry) = files.iter().find(|e| end(e) > end(partition)) {
+            return Err(invalid(&format!(
+                "{}/{} extends past its partition",
+                partition.name, entry.name
+            )));
+        }
+    }
+    for entry in &entries {
+        check_bounds(entry, len)?;
+        if entry.name.to_lowercase().ends_with(".ncz") {
+            check_ncz(&mut file, entry)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether a game file is structurally sound, see [`validate`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Health {
+    /// `ok` or `broken`.
+    pub status: String,
+    /// What is wrong with a broken file.
+    pub problem: Option<String>,
+}
+
+impl Health {
+    /// Validates `path`. `None` if the file couldn't be read at all.
+    pub fn check(path: &Path) -> Option<Self> {
+        let problem = match validate(path) {
+            Ok(()) => None,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Some("File is truncated".to_string()),
+            Err(e) if e.kind() == ErrorKind::InvalidData => Some(e.to_string()),
+            Err(_) => return None,
+        };
+        Some(Self {
+            status: if problem.is_some() { "broken" } else { "ok" }.to_string(),
+            problem,
+        })
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.status == "broken"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Builds a PFS0 or HFS0 archive holding `files`.
+    fn archive(magic: &[u8; 4], entry_size: usize, files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut strings = Vec::new();
+        let mut table = Vec::new();
+        let mut data: Vec<u8> = Vec::new();
+        for (name, content) in files {
+            let mut entry = vec![0u8; entry_size];
+            entry[0..8].copy_from_slice(&(data.len() as u64).to_le_bytes());
+            entry[8..16].copy_from_slice(&(content.len() as u64).to_le_bytes());
+            entry[16..20].copy_from_slice(&(strings.len() as u32).to_le_bytes());
+            table.extend(entry);
+            strings.extend(name.as_bytes());
+            strings.push(0);
+            data.extend(*content);
+        }
+        let mut out = magic.to_vec();
+        out.extend((files.len() as u32).to_le_bytes());
+        out.extend((strings.len() as u32).to_le_bytes());
+        out.extend([0u8; 4]);
+        out.extend(table);
+        out.extend(strings);
+        out.extend(data);
+        out
+    }
+
+    #[test]
+    fn test_inspect_nsp_and_xci() {
+        let tmp = tempdir().unwrap();
+
+        let nsp = tmp.path().join("game.nsp");
+        std::fs::write(
+            &nsp,
+            archive(b"PFS0", 0x18, &[("a.nca", b"aaaa"), ("b.tik", b"bb")]),
+        )
+        .unwrap();
+        let info = inspect(&nsp).unwrap();
+        assert_eq!(info.kind, "pfs0");
+        assert_eq!(info.entries.len(), 2);
+        assert_eq!(info.entries[1].name, "b.tik");
+        assert_eq!(info.entries[1].size, 2);
+        let content = std::fs::read(&nsp).unwrap();
+        let b = &info.entries[1];
+        assert_eq!(
+            &content[b.offset as usize..(b.offset + b.size) as usize],
+            b"bb"
+        );
+
+        let secure = archive(b"HFS0", 0x40, &[("c.nca", b"cccc")]);
+        let root = archive(b"HFS0", 0x40, &[("secure", &secure)]);
+        let mut xci = vec![0u8; 0x200];
+        xci[0x100..0x104].copy_from_slice(b"HEAD");
+        xci[0x130..0x138].copy_from_slice(&0x200u64.to_le_bytes());
+        xci.extend(root);
+        let path = tmp.path().join("game.xci");
+        std::fs::write(&path, xci).unwrap();
+        let info = inspect(&path).unwrap();
+        assert_eq!(info.kind, "xci");
+        assert_eq!(info.entries[0].name, "secure/c.nca");
+        assert_eq!(info.entries[0].size, 4);
+
+        let dummy = tmp.path().join("dummy.nsp");
+        std::fs::write(&dummy, "dummy").unwrap();
+        assert_eq!(inspect(&dummy).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        assert!(has_magic(&std::fs::read(&nsp).unwrap()));
+        assert!(has_magic(&std::fs::read(&path).unwrap()[..MAGIC_LEN]));
+        assert!(!has_magic(b"dummy"));
+    }
+
+    fn ncz(sections: u64) -> Vec<u8> {
+        let mut content = vec![0u8; NCA_HEADER_LEN as usize];
+        content.extend(b"NCZSECTN");
+        content.extend(sections.to_le_bytes());
+        content.extend(vec![0u8; (sections * NCZ_SECTION_LEN) as usize]);
+        content
+    }
+
+    #[test]
+    fn test_validate() {
+        let tmp = tempdir().unwrap();
+        let health = |name: &str, content: &[u8]| {
+            let path = tmp.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            Health::check(&path).unwrap()
+        };
+
+        let nsp = archive(b"PFS0", 0x18, &[("a.nca", b"aaaa"), ("b.tik", b"bb")]);
+        assert_eq!(health("ok.nsp", &nsp).status, "ok");
+        let truncated = health("truncated.nsp", &nsp[..nsp.len() - 1]);
+        assert!(truncated.is_broken());
+        assert_eq!(
+            truncated.problem.as_deref(),
+            Some("b.tik extends past the end of the file")
+        );
+        assert_eq!(
+            health("header.nsp", &nsp[..0x20]).problem.as_deref(),
+            Some("File is truncated")
+        );
+        assert!(health("dummy.nsp", b"dummy").is_broken());
+
+        let nsz = archive(b"PFS0", 0x18, &[("a.ncz", &ncz(1))]);
+        assert_eq!(health("ok.nsz", &nsz).status, "ok");
+        let nsz = archive(b"PFS0", 0x18, &[("a.ncz", &ncz(0))]);
+        assert_eq!(
+            health("empty.nsz", &nsz).problem.as_deref(),
+            Some("a.ncz has no valid NCZ section header")
+        );
+
+        // A file entry running past the end of its partition
+        let mut secure = archive(b"HFS0", 0x40, &[("c.nca", b"cccc")]);
+        secure.pop();
+        let root = archive(b"HFS0", 0x40, &[("secure", &secure)]);
+        let mut xci = vec![0u8; 0x200];
+        xci[0x100..0x104].copy_from_slice(b"HEAD");
+        xci[0x130..0x138].copy_from_slice(&0x200u64.to_le_bytes());
+        xci.extend(root);
+        xci.extend(b"padding");
+        assert_eq!(
+            health("bad.xci", &xci).problem.as_deref(),
+            Some("secure/c.nca extends past its partition")
+        );
+        assert_eq!(Health::check(&tmp.path().join("missing.nsp")), None);
+    }
+}
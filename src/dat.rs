@@ -0,0 +1,468 @@
+use crate::downloads::now;
+use crate::integrity::{IntegrityStore, file_stamp};
+use crate::jsonfile;
+use crate::scanner::Game;
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// A file listed in a DAT, with the size and hashes of a clean dump.
+/// Hashes are lowercase hex.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DatRom {
+    /// The `<game>` the file belongs to.
+    pub game: String,
+    pub name: String,
+    pub size: u64,
+    pub sha256: Option<String>,
+    pub sha1: Option<String>,
+    pub md5: Option<String>,
+    pub crc: Option<String>,
+}
+
+/// An imported DAT file, as published by preservation groups in the
+/// Logiqx XML format.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Dat {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub version: Option<String>,
+    pub imported_at: u64,
+    pub roms: Vec<DatRom>,
+}
+
+/// A DAT without its files, as listed by the API.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DatSummary {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub version: Option<String>,
+    pub imported_at: u64,
+    pub roms: usize,
+    /// Only files with a SHA-256 can verify library files.
+    pub roms_with_sha256: usize,
+}
+
+impl Dat {
+    pub fn summary(&self) -> DatSummary {
+        DatSummary {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            version: self.version.clone(),
+            imported_at: self.imported_at,
+            roms: self.roms.len(),
+            roms_with_sha256: self.roms.iter().filter(|r| r.sha256.is_some()).count(),
+        }
+    }
+}
+
+/// Reads a Logiqx XML DAT. Files may be listed under `<game>` or
+/// `<machine>` entries.
+pub fn parse(xml: &str) -> Result<Dat, &'static str> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let doc = roxmltree::Document::parse_with_options(xml, options).map_err(|_| "Invalid XML")?;
+    let root = doc.root_element();
+    if !root.has_tag_name("datafile") {
+        return Err("Not a DAT file");
+    }
+    let header = root.children().find(|n| n.has_tag_name("header"));
+    let header_text = |tag: &str| {
+        header
+            .and_then(|h| h.children().find(|n| n.has_tag_name(tag)))
+            .and_then(|n| n.text())
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+    };
+    let hash = |node: roxmltree::Node, attr: &str| node.attribute(attr).map(str::to_lowercase);
+
+    let mut roms = Vec::new();
+    for game in root
+        .children()
+        .filter(|n| n.has_tag_name("game") || n.has_tag_name("machine"))
+    {
+        let game_name = game.attribute("name").unwrap_or_default();
+        for rom in game.children().filter(|n| n.has_tag_name("rom")) {
+            let (Some(name), Some(size)) = (
+                rom.attribute("name"),
+                rom.attribute("size").and_then(|s| s.parse().ok()),
+            ) else {
+                continue;
+            };
+            roms.push(DatRom {
+                game: game_name.to_string(),
+                name: name.to_string(),
+                size,
+                sha256: hash(rom, "sha256"),
+                sha1: hash(rom, "sha1"),
+                md5: hash(rom, "md5"),
+                crc: hash(rom, "crc"),
+            });
+        }
+    }
+    if roms.is_empty() {
+        return Err("DAT lists no files");
+    }
+    Ok(Dat {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: header_text("name").unwrap_or_else(|| "Unnamed DAT".to_string()),
+        description: header_text("description"),
+        version: header_text("version"),
+        imported_at: now(),
+        roms,
+    })
+}
+
+/// Imported DATs, persisted in `data_dir/dats.json`. Changes publish a
+/// new [`DatSet`], so games can be checked without waiting on an import.
+pub struct DatStore {
+    path: PathBuf,
+    current: RwLock<Arc<DatSet>>,
+    save_lock: tokio::sync::Mutex<()>,
+}
+
+impl DatStore {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join("dats.json"),
+            current: RwLock::default(),
+            save_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        if let Some(dats) = jsonfile::load(&self.path).await {
+            *self.current.get_mut().unwrap() = Arc::new(DatSet::new(dats));
+        }
+    }
+
+    async fn save(&self, dats: &[Dat]) -> std::io::Result<()> {
+        jsonfile::save(&self.path, dats).await
+    }
+
+    pub fn snapshot(&self) -> Arc<DatSet> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub async fn add(&self, dat: Dat) -> std::io::Result<()> {
+        let _guard = self.save_lock.lock().await;
+        let mut dats = self.snapshot().dats.clone();
+        dats.push(dat);
+        self.save(&dats).await?;
+        *self.current.write().unwrap() = Arc::new(DatSet::new(dats));
+        Ok(())
+    }
+
+    pub async fn remove(&self, id: &str) -> std::io::Result<Option<Dat>> {
+        let _guard = self.save_lock.lock().await;
+        let mut dats = self.snapshot().dats.clone();
+        let Some(pos) = dats.iter().position(|d| d.id == id) else {
+            return Ok(None);
+        };
+        let dat = dats.remove(pos);
+        self.save(&dats).await?;
+        *self.current.write().unwrap() = Arc::new(DatSet::new(dats));
+        Ok(Some(dat))
+    }
+}
+
+/// The imported DATs with their files indexed by hash and name.
+#[derive(Default)]
+pub struct DatSet {
+    dats: Vec<Dat>,
+    index: DatIndex,
+}
+
+impl DatSet {
+    pub fn new(dats: Vec<Dat>) -> Self {
+        let mut index = DatIndex::default();
+        for rom in dats.iter().flat_map(|d| &d.roms) {
+            if let Some(sha256) = &rom.sha256 {
+                index.by_sha256.insert(sha256.clone(), rom.clone());
+            }
+            index
+                .by_name
+                .entry(rom.name.to_lowercase())
+                .or_default()
+                .push(rom.clone());
+        }
+        Self { dats, index }
+    }
+
+    pub fn dats(&self) -> &[Dat] {
+        &self.dats
+    }
+
+    pub fn index(&self) -> &DatIndex {
+        &self.index
+    }
+}
+
+#[derive(Default)]
+pub struct DatIndex {
+    by_sha256: HashMap<String, DatRom>,
+    by_name: HashMap<String, Vec<DatRom>>,
+}
+
+/// The outcome of checking a file against the imported DATs.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Verification {
+    /// A DAT entry has the file's size and SHA-256.
+    Verified,
+    /// No DAT entry says anything about the file.
+    Unknown,
+    /// The file contradicts the DAT entries for its name, or rotted.
+    Bad,
+}
+
+/// How a library file compares to the imported DATs.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Verdict<'a> {
+    pub status: Verification,
+    /// Why a file is bad: `size_mismatch`, `hash_mismatch` or `corrupt`,
+    /// when a verified file no longer matches its hash.
+    pub reason: Option<&'static str>,
+    /// The DAT entry the file matched or should have matched.
+    pub expected: Option<&'a DatRom>,
+}
+
+impl<'a> Verdict<'a> {
+    fn new(
+        status: Verification,
+        reason: Option<&'static str>,
+        expected: Option<&'a DatRom>,
+    ) -> Self {
+        Self {
+            status,
+            reason,
+            expected,
+        }
+    }
+}
+
+impl DatIndex {
+    /// Matches a file by size and SHA-256. Without a match, it is bad when
+    /// DAT entries share its file name but none of them has its size, or
+    /// none of those with its size has its hash. `corrupt_from` is the
+    /// hash a file had before it rotted, if it did.
+    pub fn check(
+        &self,
+        file_name: &str,
+        size: u64,
+        sha256: Option<&str>,
+        corrupt_from: Option<&str>,
+    ) -> Verdict<'_> {
+        if let Some(original) = corrupt_from
+            && let Some(rom) = self.by_sha256.get(original)
+        {
+            return Verdict::new(Verification::Bad, Some("corrupt"), Some(rom));
+        }
+        if corrupt_from.is_none()
+            && let Some(rom) = sha256.and_then(|h| self.by_sha256.get(h))
+            && rom.size == size
+        {
+            return Verdict::new(Verification::Verified, None, Some(rom));
+        }
+        let named = self
+            .by_name
+            .get(&file_name.to_lowercase())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let sized: Vec<&DatRom> = named.iter().filter(|r| r.size == size).collect();
+        if let Some(rom) = named.first()
+            && sized.is_empty()
+        {
+            return Verdict::new(Verification::Bad, Some("size_mismatch"), Some(rom));
+        }
+        let hashed: Vec<&DatRom> = sized.into_iter().filter(|r| r.sha256.is_some()).collect();
+        if let Some(sha256) = sha256
+            && let Some(rom) = hashed.first()
+            && hashed.iter().all(|r| r.sha256.as_deref() != Some(sha256))
+        {
+            return Verdict::new(Verification::Bad, Some("hash_mismatch"), Some(rom));
+        }
+        Verdict::new(Verification::Unknown, None, None)
+    }
+}
+
+/// How a game compares to the imported DATs, using the stored hash of its
+/// file, along with that hash. `None` when no DATs are imported.
+fn verdict<'a>(
+    dats: &'a DatSet,
+    integrity: &IntegrityStore,
+    game: &Game,
+) -> Option<(Verdict<'a>, Option<String>)> {
+    if dats.dats.is_empty() {
+        return None;
+    }
+    let record = file_stamp(&game.path)
+        .ok()
+        .and_then(|(size, modified)| integrity.stamped(&game.relative_path, size, modified));
+    let sha256 = record
+        .as_ref()
+        .map(|r| r.corrupt.as_ref().map_or(&r.sha256, |c| &c.sha256).clone());
+    let corrupt_from = record
+        .as_ref()
+        .filter(|r| r.corrupt.is_some())
+        .map(|r| r.sha256.as_str());
+    let file_name = Path::new(&game.relative_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let verdict = dats
+        .index
+        .check(&file_name, game.size, sha256.as_deref(), corrupt_from);
+    Some((verdict, sha256))
+}
+
+/// The value of `Game.verification` for a game about to be added.
+pub fn verification(
+    dats: &DatSet,
+    integrity: &IntegrityStore,
+    game: &Game,
+) -> Option<Verification> {
+    verdict(dats, integrity, game).map(|(v, _)| v.status)
+}
+
+/// Sets `Game.verification` of every game again, after DATs or hashes
+/// changed.
+pub fn reverify_library(state: &AppState) {
+    let dats = state.dats.snapshot();
+    let mut games = state.games.lock().unwrap();
+    for game in games.iter_mut() {
+        game.verification = verification(&dats, &state.integrity, game);
+    }
+}
+
+/// Checks every library file against the imported DATs and reports the
+/// bad ones with what was expected of them.
+pub fn verification_report(state: &AppState) -> serde_json::Value {
+    let dats = state.dats.snapshot();
+    let games = state.games.lock().unwrap();
+
+    let mut counts: HashMap<Verification, usize> = HashMap::new();
+    let mut mismatches = Vec::new();
+    for game in games.iter() {
+        let Some((verdict, sha256)) = verdict(&dats, &state.integrity, game) else {
+            continue;
+        };
+        *counts.entry(verdict.status).or_default() += 1;
+        if verdict.status == Verification::Bad {
+            mismatches.push(serde_json::json!({
+                "id": game.id,
+                "relative_path": game.relative_path,
+                "size": game.size,
+                "sha256": sha256,
+                "reason": verdict.reason,
+                "expected": verdict.expected,
+            }));
+        }
+    }
+    serde_json::json!({
+        "dats": dats.dats().iter().map(Dat::summary).collect::<Vec<_>>(),
+        "verified": counts.get(&Verification::Verified).copied().unwrap_or(0),
+        "unknown": counts.get(&Verification::Unknown).copied().unwrap_or(0),
+        "bad": counts.get(&Verification::Bad).copied().unwrap_or(0),
+        "mismatches": mismatches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAT: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/Dats/datafile.dtd">
+<datafile>
+  <header>
+    <name>Nintendo - Switch</name>
+    <version>20261001</version>
+  </header>
+  <game name="Good Game">
+    <description>Good Game</description>
+    <rom name="Good Game.nsp" size="5" crc="0A1B2C3D" sha256="AAAA"/>
+  </game>
+  <game name="Other Game">
+    <rom name="Other Game.nsp" size="10" sha256="bbbb"/>
+    <rom name="broken" size="x"/>
+  </game>
+</datafile>"#;
+
+    #[test]
+    fn test_parse() {
+        let dat = parse(DAT).unwrap();
+        assert_eq!(dat.name, "Nintendo - Switch");
+        assert_eq!(dat.version.as_deref(), Some("20261001"));
+        assert_eq!(dat.roms.len(), 2);
+        assert_eq!(dat.roms[0].game, "Good Game");
+        assert_eq!(dat.roms[0].sha256.as_deref(), Some("aaaa"));
+        assert_eq!(dat.roms[0].crc.as_deref(), Some("0a1b2c3d"));
+        assert_eq!(dat.summary().roms_with_sha256, 2);
+
+        assert_eq!(parse("<nope/>").unwrap_err(), "Not a DAT file");
+        assert_eq!(parse("<datafile>").unwrap_err(), "Invalid XML");
+        assert_eq!(
+            parse("<datafile><header/></datafile>").unwrap_err(),
+            "DAT lists no files"
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let dats = DatSet::new(vec![parse(DAT).unwrap()]);
+        let index = dats.index();
+        let status = |name, size, sha256, corrupt_from| {
+            let verdict = index.check(name, size, sha256, corrupt_from);
+            (verdict.status, verdict.reason)
+        };
+
+        // Matched by content, whatever the file is called
+        assert_eq!(
+            status("Renamed.nsp", 5, Some("aaaa"), None),
+            (Verification::Verified, None)
+        );
+        assert_eq!(
+            status("Renamed.nsp", 5, Some("cccc"), None),
+            (Verification::Unknown, None)
+        );
+        assert_eq!(
+            status("Renamed.nsp", 5, None, None),
+            (Verification::Unknown, None)
+        );
+        assert_eq!(
+            status("good game.NSP", 4, None, None),
+            (Verification::Bad, Some("size_mismatch"))
+        );
+        assert_eq!(
+            status("Good Game.nsp", 5, Some("cccc"), None),
+            (Verification::Bad, Some("hash_mismatch"))
+        );
+        assert_eq!(
+            status("Renamed.nsp", 5, Some("cccc"), Some("aaaa")),
+            (Verification::Bad, Some("corrupt"))
+        );
+
+        // Another revision of the same file name can match
+        let mut revision = parse(DAT).unwrap();
+        revision.roms[0].size = 6;
+        revision.roms[0].sha256 = Some("dddd".to_string());
+        let dats = DatSet::new(vec![parse(DAT).unwrap(), revision]);
+        let index = dats.index();
+        let status = |name, size, sha256| index.check(name, size, sha256, None).status;
+        assert_eq!(status("Good Game.nsp", 6, None), Verification::Unknown);
+        assert_eq!(status("Good Game.nsp", 5, None), Verification::Unknown);
+        assert_eq!(
+            status("Good Game.nsp", 6, Some("dddd")),
+            Verification::Verified
+        );
+        assert_eq!(status("Good Game.nsp", 4, None), Verification::Bad);
+    }
+}
@@ -1,13 +1,173 @@
+use crate::jsonfile;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// How many finished downloads are remembered.
+const HISTORY_LIMIT: usize = 1000;
 
 #[derive(Clone, Debug, serde::Serialize)]
 pub struct DownloadState {
     pub id: String,
+    /// The library file being sent, when it is one.
+    pub game_id: Option<String>,
     pub filename: String,
     pub total_size: u64,
     pub bytes_sent: u64,
     pub speed: u64, // bytes per second
+    pub started_at: u64,
 }
 
 pub type Downloads = Arc<Mutex<HashMap<String, DownloadState>>>;
+
+/// A download that has ended, whether the client got the whole file or not.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DownloadRecord {
+    pub id: String,
+    pub game_id: Option<String>,
+    pub filename: String,
+    pub total_size: u64,
+    pub bytes_sent: u64,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub completed: bool,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The most recent finished downloads, persisted in
+/// `data_dir/download_history.json`.
+pub struct DownloadHistory {
+    path: PathBuf,
+    records: Vec<DownloadRecord>,
+}
+
+impl DownloadHistory {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join("download_history.json"),
+            records: Vec::new(),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        if let Some(records) = jsonfile::load(&self.path).await {
+            self.records = records;
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        jsonfile::save(&self.path, &self.records).await
+    }
+
+    pub async fn record(&mut self, record: DownloadRecord) {
+        self.records.push(record);
+        if self.records.len() > HISTORY_LIMIT {
+            let excess = self.records.len() - HISTORY_LIMIT;
+            self.records.drain(..excess);
+        }
+        if let Err(e) = self.save().await {
+            warn!("Failed to save download history: {}", e);
+        }
+    }
+
+    /// Downloads of one library file, oldest first.
+    pub fn for_game(&self, game_id: &str) -> Vec<DownloadRecord> {
+        self.records
+            .iter()
+            .filter(|r| r.game_id.as_deref() == Some(game_id))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Tracks a download while its body is streamed. When the body is dropped,
+/// finished or aborted, the download leaves the active list and is added to
+/// the history.
+pub struct DownloadGuard {
+    pub id: String,
+    pub downloads: Downloads,
+    pub history: Arc<tokio::sync::Mutex<DownloadHistory>>,
+}
+
+impl Drop for DownloadGuard {
+    fn drop(&mut self) {
+        let Some(download) = self
+            .downloads
+            .lock()
+            .ok()
+            .and_then(|mut downloads| downloads.remove(&self.id))
+        else {
+            return;
+        };
+        let record = DownloadRecord {
+            completed: download.bytes_sent >= download.total_size,
+            id: download.id,
+            game_id: download.game_id,
+            filename: download.filename,
+            total_size: download.total_size,
+            bytes_sent: download.bytes_sent,
+            started_at: download.started_at,
+            finished_at: now(),
+        };
+        let history = self.history.clone();
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move { history.lock().await.record(record).await });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_download_guard_records_history() {
+        let tmp = tempdir().unwrap();
+        let downloads: Downloads = Arc::new(Mutex::new(HashMap::new()));
+        let history = Arc::new(tokio::sync::Mutex::new(DownloadHistory::new(tmp.path())));
+        downloads.lock().unwrap().insert(
+            "d1".to_string(),
+            DownloadState {
+                id: "d1".to_string(),
+                game_id: Some("abc".to_string()),
+                filename: "Game.nsp".to_string(),
+                total_size: 10,
+                bytes_sent: 4,
+                speed: 0,
+                started_at: now(),
+            },
+        );
+
+        drop(DownloadGuard {
+            id: "d1".to_string(),
+            downloads: downloads.clone(),
+            history: history.clone(),
+        });
+        assert!(downloads.lock().unwrap().is_empty());
+
+        // Recording happens on a spawned task
+        for _ in 0..50 {
+            if !history.lock().await.for_game("abc").is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let records = history.lock().await.for_game("abc");
+        assert_eq!(records.len(), 1);
+        assert!(!records[0].completed);
+
+        let mut reloaded = DownloadHistory::new(tmp.path());
+        reloaded.load().await;
+        assert_eq!(reloaded.for_game("abc"), records);
+    }
+}
@@ -0,0 +1,400 @@
+use crate::config::Settings;
+use crate::dat::{DatStore, verification};
+use crate::integrity::IntegrityStore;
+use crate::metadata::MetadataStore;
+use crate::query::GameQuery;
+use crate::scanner::{Game, discover, enrich};
+use serde::Deserialize;
+use walkdir::WalkDir;
+
+/// Exported when no field selection is given, in this order.
+const DEFAULT_FIELDS: &[&str] = &[
+    "name",
+    "title_id",
+    "category",
+    "version",
+    "latest_version",
+    "size",
+    "format",
+    "path",
+    "publisher",
+];
+
+/// Further fields that can be selected.
+const EXTRA_FIELDS: &[&str] = &["base_title_id", "release_date", "added_at", "id"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+/// Export options besides the library filters, which are the same as for
+/// `/api/games`. Since `format` names the export format here, the file
+/// format filter is called `file_format`.
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Comma separated fields, see [`DEFAULT_FIELDS`] and [`EXTRA_FIELDS`].
+    pub fields: Option<String>,
+    pub file_format: Option<String>,
+}
+
+impl ExportQuery {
+    /// The selected fields, or the name of the first unknown one.
+    pub fn columns(&self) -> Result<Vec<&'static str>, String> {
+        let Some(fields) = self.fields.as_deref() else {
+            return Ok(DEFAULT_FIELDS.to_vec());
+        };
+        fields
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(|f| {
+                DEFAULT_FIELDS
+                    .iter()
+                    .chain(EXTRA_FIELDS)
+                    .find(|known| **known == f)
+                    .copied()
+                    .ok_or_else(|| f.to_string())
+            })
+            .collect()
+    }
+}
+
+fn value(game: &Game, field: &str) -> serde_json::Value {
+    use serde_json::json;
+    match field {
+        "name" => json!(game.name),
+        "title_id" => json!(game.title_id),
+        "category" => json!(game.category),
+        "version" => json!(game.version),
+        "latest_version" => json!(game.latest_version),
+        "size" => json!(game.size),
+        "format" => json!(game.format),
+        "path" => json!(game.relative_path),
+        "publisher" => json!(game.publisher),
+        "base_title_id" => json!(game.base_title_id),
+        "release_date" => json!(game.release_date),
+        "added_at" => json!(game.added_at),
+        "id" => json!(game.id),
+        _ => serde_json::Value::Null,
+    }
+}
+
+fn text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Renders `games` with the given columns.
+pub fn render(games: &[Game], format: ExportFormat, columns: &[&str]) -> String {
+    match format {
+        ExportFormat::Json => {
+            let rows: Vec<serde_json::Map<String, serde_json::Value>> = games
+                .iter()
+                .map(|g| {
+                    columns
+                        .iter()
+                        .map(|c| (c.to_string(), value(g, c)))
+                        .collect()
+                })
+                .collect();
+            serde_json::to_string_pretty(&rows).unwrap_or_default()
+        }
+        ExportFormat::Csv => {
+            let mut out = columns.join(",");
+            out.push_str("\r\n");
+            for game in games {
+                let row: Vec<String> = columns
+                    .iter()
+                    .map(|c| csv_field(&text(&value(game, c))))
+                    .collect();
+                out.push_str(&row.join(","));
+                out.push_str("\r\n");
+            }
+            out
+        }
+        ExportFormat::Html => render_html(games, columns),
+    }
+}
+
+/// A standalone page: styles and the filter script are inline, so the file
+/// works offline.
+fn render_html(games: &[Game], columns: &[&str]) -> String {
+    let total: u64 = games.iter().map(|g| g.size).sum();
+    let header: String = columns
+        .iter()
+        .map(|c| format!("<th>{}</th>", html_escape(&c.replace('_', " "))))
+        .collect();
+    let rows: String = games
+        .iter()
+        .map(|game| {
+            let cells: String = columns
+                .iter()
+                .map(|c| {
+                    let cell = match *c {
+                        "size" => human_size(game.size),
+                        _ => text(&value(game, c)),
+                    };
+                    format!("<td>{}</td>", html_escape(&cell))
+                })
+                .collect();
+            format!("<tr>{}</tr>\n", cells)
+        })
+        .collect();
+
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Switcheroo library</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2rem; color: #1f2937; }}
+h1 {{ margin-bottom: 0.25rem; }}
+p {{ color: #6b7280; margin-top: 0; }}
+input {{ padding: 0.5rem; width: 100%; max-width: 24rem; margin-bottom: 1rem; }}
+table {{ border-collapse: collapse; width: 100%; font-size: 0.9rem; }}
+th, td {{ text-align: left; padding: 0.4rem 0.6rem; border-bottom: 1px solid #e5e7eb; }}
+th {{ background: #f3f4f6; text-transform: capitalize; position: sticky; top: 0; }}
+tr:hover td {{ background: #f9fafb; }}
+</style>
+</head>
+<body>
+<h1>Switcheroo library</h1>
+<p>{count} files, {size}, exported {date}</p>
+<input id="filter" type="search" placeholder="Filter" autofocus>
+<table>
+<thead><tr>{header}</tr></thead>
+<tbody id="rows">
+{rows}</tbody>
+</table>
+<script>
+document.getElementById("filter").addEventListener("input", function (e) {{
+  var q = e.target.value.toLowerCase();
+  document.querySelectorAll("#rows tr").forEach(function (row) {{
+    row.style.display = row.textContent.toLowerCase().includes(q) ? "" : "none";
+  }});
+}});
+</script>
+</body>
+</html>
+"##,
+        count = games.len(),
+        size = human_size(total),
+        date = chrono::Utc::now().format("%Y-%m-%d"),
+        header = header,
+        rows = rows,
+    )
+}
+
+/// `switcheroo export [--format csv|json|html] [--output FILE] [--FILTER VALUE]...`
+///
+/// Scans the games directory, applies the metadata, hashes and DATs in
+/// `data_dir` and writes the export to `--output` or stdout. Filters and `--fields` take
+/// the same values as the query parameters of `/api/export`.
+pub async fn run_cli(settings: Settings, args: &[String]) -> Result<(), String> {
+    let mut output = None;
+    let mut pairs = Vec::new();
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(format!("Unexpected argument '{}'", arg));
+        };
+        let (key, value) = match flag.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => {
+                let value = match args.peek() {
+                    Some(next) if !next.starts_with("--") => args.next().unwrap().clone(),
+                    // Flags without a value, like --outdated
+                    _ => "true".to_string(),
+                };
+                (flag.to_string(), value)
+            }
+        };
+        if key == "output" {
+            output = Some(value);
+        } else {
+            pairs.push((key.replace('-', "_"), value));
+        }
+    }
+
+    let query_string: Vec<String> = pairs
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                "{}={}",
+                k,
+                percent_encoding::utf8_percent_encode(v, percent_encoding::NON_ALPHANUMERIC)
+            )
+        })
+        .collect();
+    let uri: axum::http::Uri = format!("/?{}", query_string.join("&"))
+        .parse()
+        .map_err(|e| format!("Invalid arguments: {}", e))?;
+    let axum::extract::Query(export) =
+        axum::extract::Query::<ExportQuery>::try_from_uri(&uri).map_err(|e| e.body_text())?;
+    let axum::extract::Query(mut query) =
+        axum::extract::Query::<GameQuery>::try_from_uri(&uri).map_err(|e| e.body_text())?;
+    query.format = export.file_format.clone();
+    let columns = export
+        .columns()
+        .map_err(|f| format!("Unknown field '{}'", f))?;
+
+    let metadata = MetadataStore::new(settings.data_dir.clone(), settings.locales())
+        .with_match_threshold(settings.title_match_threshold)
+        .with_sources(settings.merge_rules());
+    metadata.init().await;
+    let meta = metadata.snapshot();
+
+    let mut integrity = IntegrityStore::new(&settings.data_dir);
+    integrity.load().await;
+    let mut dats = DatStore::new(&settings.data_dir);
+    dats.load().await;
+    let dats = dats.snapshot();
+
+    let games_dir = settings.games_dir.clone();
+    let games: Vec<Game> = tokio::task::spawn_blocking(move || {
+        WalkDir::new(&games_dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| discover(e.path(), &games_dir))
+            .collect::<Vec<_>>()
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .into_iter()
+    .map(|mut game| {
+        enrich(&mut game, &meta);
+        game.verification = verification(&dats, &integrity, &game);
+        game
+    })
+    .collect();
+    let mut user_library = crate::userdata::UserLibrary::new(&settings.data_dir);
+    user_library.load().await;
+    let mut games: Vec<Game> = query
+        .apply(&games, &meta, &user_library)
+        .into_iter()
+        .cloned()
+        .collect();
+    user_library.join(&mut games);
+
+    let rendered = render(&games, export.format, &columns);
+    match output {
+        Some(path) => tokio::fs::write(&path, rendered)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", path, e)),
+        None => {
+            print!("{}", rendered);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn games() -> Vec<Game> {
+        vec![Game {
+            name: "Tom & \"Jerry\", <Deluxe>".to_string(),
+            relative_path: "Tom.nsp".to_string(),
+            title_id: Some("0100000000010000".to_string()),
+            category: "Base".to_string(),
+            format: "nsp".to_string(),
+            size: 3 * 1024 * 1024,
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let query = ExportQuery {
+            fields: Some("name, size,title_id".to_string()),
+            ..Default::default()
+        };
+        let columns = query.columns().unwrap();
+        assert_eq!(columns, vec!["name", "size", "title_id"]);
+
+        let csv = render(&games(), ExportFormat::Csv, &columns);
+        assert_eq!(
+            csv,
+            "name,size,title_id\r\n\"Tom & \"\"Jerry\"\", <Deluxe>\",3145728,0100000000010000\r\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&games(), ExportFormat::Json, &columns)).unwrap();
+        assert_eq!(json[0]["size"], 3145728);
+        assert_eq!(json[0].as_object().unwrap().len(), 3);
+
+        let html = render(&games(), ExportFormat::Html, &columns);
+        assert!(html.contains("<td>Tom &amp; &quot;Jerry&quot;, &lt;Deluxe&gt;</td>"));
+        assert!(html.contains("<td>3.0 MiB</td>"));
+        assert!(!html.contains("http"));
+
+        let bad = ExportQuery {
+            fields: Some("name,bogus".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(bad.columns().unwrap_err(), "bogus");
+        assert_eq!(ExportQuery::default().columns().unwrap().len(), 9);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::cleanup::move_file;
+use crate::library::{replace_game, with_user_data};
+use crate::scanner::{Game, discover};
+use crate::state::AppState;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How long after an operation the watcher keeps ignoring its paths, as
+/// notify may deliver the events late.
+const GRACE: Duration = Duration::from_secs(5);
+
+/// Paths the server is moving or deleting itself. It updates the library
+/// as part of the operation, so the watcher skips events for them instead
+/// of applying the change a second time, possibly half-way through a copy.
+#[derive(Default)]
+pub struct WatchSuppression {
+    /// `None` while the operation runs, then when to stop ignoring the path.
+    paths: Mutex<HashMap<PathBuf, Option<Instant>>>,
+}
+
+impl WatchSuppression {
+    /// Ignores events for `paths` until the returned guard is dropped, and
+    /// for a short while after.
+    pub fn hold(&self, paths: Vec<PathBuf>) -> Held<'_> {
+        let mut held = self.paths.lock().unwrap();
+        for path in &paths {
+            held.insert(path.clone(), None);
+        }
+        Held {
+            suppression: self,
+            paths,
+        }
+    }
+
+    pub fn is_held(&self, path: &Path) -> bool {
+        let now = Instant::now();
+        let mut held = self.paths.lock().unwrap();
+        held.retain(|_, until| until.is_none_or(|until| until > now));
+        held.contains_key(path)
+    }
+}
+
+pub struct Held<'a> {
+    suppression: &'a WatchSuppression,
+    paths: Vec<PathBuf>,
+}
+
+impl Drop for Held<'_> {
+    fn drop(&mut self) {
+        let until = Instant::now() + GRACE;
+        let mut held = self.suppression.paths.lock().unwrap();
+        for path in self.paths.drain(..) {
+            held.insert(path, Some(until));
+        }
+    }
+}
+
+/// Resolves a folder given relative to `games_dir`, refusing anything that
+/// could point outside of it. An empty path is `games_dir` itself.
+pub fn library_dir(games_dir: &Path, relative: &str) -> Option<PathBuf> {
+    let relative = Path::new(relative.trim_matches('/'));
+    relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        .then(|| games_dir.join(relative))
+}
+
+/// Whether `path` is inside `games_dir` once symlinks are resolved. For a
+/// path that doesn't exist yet, its closest existing ancestor is checked.
+pub fn is_inside(games_dir: &Path, path: &Path) -> bool {
+    let Ok(root) = games_dir.canonicalize() else {
+        return false;
+    };
+    path.ancestors()
+        .find_map(|p| p.canonicalize().ok())
+        .is_some_and(|p| p.starts_with(root))
+}
+
+/// Checks a new file name: a single path component keeping a game
+/// extension.
+pub fn valid_file_name(name: &str) -> bool {
+    let path = Path::new(name);
+    let mut components = path.components();
+    matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['/', '\\'])
+        && path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| crate::scanner::GAME_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Moves a library file to `to`, inside `games_dir`, and updates the
+/// library in one step, sending the events the watcher would have.
+/// Refuses to overwrite an existing file; callers hold `state.file_ops`.
+pub async fn relocate(state: &AppState, from: &Path, to: &Path) -> std::io::Result<Game> {
+    if to.exists() {
+        return Err(ErrorKind::AlreadyExists.into());
+    }
+    let _held = state
+        .watch_suppression
+        .hold(vec![from.to_path_buf(), to.to_path_buf()]);
+    move_file(from, to).await?;
+    info!("Moved {:?} to {:?}", from, to);
+
+    let game = discover(to, &state.settings.games_dir)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Moved file is not a game"))?;
+    let old = {
+        let games = state.games.lock().unwrap();
+        games
+            .iter()
+            .find(|g| g.path == from)
+            .map(|g| (g.id.clone(), g.relative_path.clone()))
+    };
+    let (old_id, old_relative_path) = old.unzip();
+    if let Some(old_relative_path) = old_relative_path {
+        state
+            .integrity
+            .rename(&old_relative_path, &game.relative_path)
+            .await;
+        if let Err(e) = state
+            .metadata
+            .rename_path(&old_relative_path, &game.relative_path)
+            .await
+        {
+            warn!(
+                "Failed to move the metadata of {} to {}: {}",
+                old_relative_path, game.relative_path, e
+            );
+        }
+    }
+    let game = with_user_data(state, replace_game(state, from, game)).await;
+    let _ = state.tx.send(
+        serde_json::json!({ "type": "scan", "status": "remove", "id": old_id, "path": from })
+            .to_string(),
+    );
+    let _ = state
+        .tx
+        .send(serde_json::json!({ "type": "scan", "status": "update", "game": game }).to_string());
+    Ok(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_paths_stay_in_games_dir() {
+        let games = Path::new("/games");
+        assert_eq!(library_dir(games, "A/B"), Some(PathBuf::from("/games/A/B")));
+        assert_eq!(library_dir(games, ""), Some(PathBuf::from("/games")));
+        assert_eq!(library_dir(games, "../etc"), None);
+        assert_eq!(library_dir(games, "A/../../etc"), None);
+        assert_eq!(library_dir(games, "./A"), None);
+
+        assert!(valid_file_name("Game [0100000000010000].nsp"));
+        assert!(!valid_file_name("Game.txt"));
+        assert!(!valid_file_name("../Game.nsp"));
+        assert!(!valid_file_name("A\\Game.nsp"));
+        assert!(!valid_file_name(".."));
+
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("games");
+        std::fs::create_dir_all(root.join("A")).unwrap();
+        assert!(is_inside(&root, &root.join("A")));
+        assert!(is_inside(&root, &root.join("A/New")));
+        assert!(!is_inside(&root, tmp.path()));
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(tmp.path(), root.join("link")).unwrap();
+            assert!(!is_inside(&root, &root.join("link/New")));
+        }
+    }
+
+    #[test]
+    fn test_watch_suppression() {
+        let suppression = WatchSuppression::default();
+        let path = Path::new("/games/Game.nsp");
+        assert!(!suppression.is_held(path));
+        let held = suppression.hold(vec![path.to_path_buf()]);
+        assert!(suppression.is_held(path));
+        drop(held);
+        assert!(suppression.is_held(path));
+        suppression.paths.lock().unwrap().insert(
+            path.to_path_buf(),
+            Some(Instant::now() - Duration::from_secs(1)),
+        );
+        assert!(!suppression.is_held(path));
+    }
+}
@@ -1,13 +1,31 @@
-use crate::scanner::process_entry;
+use crate::catalog::SearchMode;
+use crate::export::ExportQuery;
+use crate::library::notify_new_versions;
+use crate::query::GameQuery;
+use crate::scanner::Game;
 use crate::state::AppState;
+use crate::titles::{
+    OutdatedTitle, TitleFamily, group_titles, outdated_titles, title_family, version_number,
+};
 use axum::{
     Json,
-    extract::State,
-    response::sse::{Event, Sse},
+    extract::{Path, Query, State},
+    http::{
+        StatusCode,
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    },
+    response::{
+        IntoResponse,
+        sse::{Event, Sse},
+    },
 };
 use futures::stream::{Stream, StreamExt};
+use serde::Deserialize;
+use std::collections::HashSet;
 use tracing::{error, info};
-use walkdir::WalkDir;
+
+const CATALOG_PAGE_SIZE: usize = 50;
+const CATALOG_MAX_PAGE_SIZE: usize = 200;
 
 pub async fn server_info(State(state): State<AppState>) -> Json<serde_json::Value> {
     let ips = local_ip_address::list_afinet_netifas()
@@ -30,56 +48,362 @@ pub async fn server_info(State(state): State<AppState>) -> Json<serde_json::Valu
     }))
 }
 
-pub async fn list_games(State(state): State<AppState>) -> Json<Vec<crate::scanner::Game>> {
-    let games = state.games.lock().unwrap();
-    Json(games.clone())
+/// Games per page of `/api/games` when the client doesn't ask for a size.
+const DEFAULT_PER_PAGE: usize = 100;
+
+#[derive(Deserialize)]
+pub struct Pagination {
+    pub page: Option<usize>,
+    /// [`DEFAULT_PER_PAGE`] when not set.
+    pub per_page: Option<usize>,
+}
+
+/// The library, filtered and sorted as requested, one page at a time.
+pub async fn list_games(
+    Query(query): Query<GameQuery>,
+    Query(pagination): Query<Pagination>,
+    State(state): State<AppState>,
+) -> Json<serde_json::Value> {
+    let meta = state.metadata.snapshot();
+    let page = pagination.page.unwrap_or(1).max(1);
+    let per_page = pagination.per_page.unwrap_or(DEFAULT_PER_PAGE).max(1);
+    let user_library = state.user_library.lock().await;
+    let (total, mut games) = {
+        let games = state.games.lock().unwrap();
+        let found = query.apply(&games, &meta, &user_library);
+        let page: Vec<Game> = found
+            .iter()
+            .skip((page - 1).saturating_mul(per_page))
+            .take(per_page)
+            .map(|&g| g.clone())
+            .collect();
+        (found.len(), page)
+    };
+    user_library.join(&mut games);
+    drop(user_library);
+
+    Json(serde_json::json!({
+        "total": total,
+        "page": page,
+        "per_page": per_page,
+        "games": games,
+    }))
+}
+
+/// The library, filtered like `/api/games`, as a CSV, JSON or standalone
+/// HTML file.
+pub async fn export_library(
+    Query(mut query): Query<GameQuery>,
+    Query(export): Query<ExportQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, &'static str)> {
+    query.format = export.file_format.clone();
+    let columns = export
+        .columns()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Unknown export field"))?;
+
+    let meta = state.metadata.snapshot();
+    let user_library = state.user_library.lock().await;
+    let mut games: Vec<Game> = query
+        .apply(&state.games.lock().unwrap(), &meta, &user_library)
+        .into_iter()
+        .cloned()
+        .collect();
+    user_library.join(&mut games);
+    drop(user_library);
+    let body = crate::export::render(&games, export.format, &columns);
+    let disposition = format!(
+        "attachment; filename=\"switcheroo-library.{}\"",
+        export.format.extension()
+    );
+    Ok((
+        [
+            (CONTENT_TYPE, export.format.content_type().to_string()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    ))
+}
+
+/// Library breakdowns, disk usage of the library roots and the daily
+/// size history.
+pub async fn library_stats(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let meta = state.metadata.snapshot();
+    let roots = vec![state.settings.games_dir.clone()];
+    let stats = {
+        let games = state.games.lock().unwrap();
+        crate::stats::library_stats(&games, &meta, &roots)
+    };
+    let disks = tokio::task::spawn_blocking(move || crate::stats::disk_usage(&roots))
+        .await
+        .unwrap_or_default();
+    let history = state.stats_history.lock().await.snapshots().to_vec();
+
+    let mut body = serde_json::to_value(stats).unwrap_or_default();
+    body["disks"] = serde_json::json!(disks);
+    body["history"] = serde_json::json!(history);
+    Json(body)
+}
+
+/// Everything known about one library file: its metadata, file stats,
+/// container layout and download history.
+pub async fn game_details(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, &'static str)> {
+    let mut game = {
+        let games = state.games.lock().unwrap();
+        games.iter().find(|g| g.id == id).cloned()
+    }
+    .ok_or((StatusCode::NOT_FOUND, "Game not found"))?;
+    state
+        .user_library
+        .lock()
+        .await
+        .join(std::slice::from_mut(&mut game));
+
+    let meta = state.metadata.snapshot();
+    let info = meta.resolve_title_info(game.title_id.as_deref(), &game.relative_path);
+
+    let modified = tokio::fs::metadata(&game.path)
+        .await
+        .ok()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+
+    let path = game.path.clone();
+    let (container, container_error) =
+        match tokio::task::spawn_blocking(move || crate::container::inspect(&path)).await {
+            Ok(Ok(container)) => (Some(container), None),
+            Ok(Err(e)) => (None, Some(e.to_string())),
+            Err(_) => (None, Some("Failed to read the file".to_string())),
+        };
+
+    let active: Vec<crate::downloads::DownloadState> = {
+        let downloads = state.downloads.lock().unwrap();
+        downloads
+            .values()
+            .filter(|d| d.game_id.as_deref() == Some(id.as_str()))
+            .cloned()
+            .collect()
+    };
+    let history = state.download_history.lock().await.for_game(&id);
+
+    Ok(Json(serde_json::json!({
+        "game": game,
+        "metadata": info,
+        "file": {
+            "size": game.size,
+            "modified": modified,
+            "added_at": game.added_at,
+        },
+        "container": container,
+        "container_error": container_error,
+        "downloads": {
+            "active": active,
+            "history": history,
+        },
+    })))
+}
+
+/// Full titledb details for a single title, including its version history,
+/// DLC and the files of it present in the library.
+pub async fn title_details(
+    Path(title_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, &'static str)> {
+    let title_id = title_id.to_uppercase();
+    let meta = state.metadata.snapshot();
+    let info = meta.get_title_info(&title_id);
+
+    let owned: Vec<crate::scanner::Game> = {
+        let games = state.games.lock().unwrap();
+        games
+            .iter()
+            .filter(|g| g.title_id.as_deref() == Some(title_id.as_str()))
+            .cloned()
+            .collect()
+    };
+
+    if info.is_none() && owned.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Title not found"));
+    }
+
+    let versions: Vec<serde_json::Value> = meta
+        .get_versions(&title_id)
+        .into_iter()
+        .map(|(version, date)| serde_json::json!({ "version": version, "release_date": date }))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "title_id": title_id,
+        "kind": meta.get_title_kind(&title_id),
+        "base_title_id": meta.get_base_title_id(&title_id),
+        "info": info,
+        "latest_version": meta.get_latest_version(&title_id),
+        "versions": versions,
+        "dlc": meta.get_dlc_ids(&title_id),
+        "owned": owned,
+    })))
+}
+
+/// The library grouped by base title.
+pub async fn list_titles(State(state): State<AppState>) -> Json<Vec<TitleFamily>> {
+    let meta = state.metadata.snapshot();
+    let mut families = group_titles(&state.games.lock().unwrap(), &meta);
+    let user_library = state.user_library.lock().await;
+    for family in families.iter_mut() {
+        family.user_data = user_library.get(&family.base_title_id).cloned();
+    }
+    Json(families)
+}
+
+/// One base title with every known version and the DLC not owned yet.
+pub async fn title_family_details(
+    Path(base_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, &'static str)> {
+    let meta = state.metadata.snapshot();
+    let base_id = meta
+        .get_base_title_id(&base_id)
+        .unwrap_or_else(|| base_id.to_uppercase());
+    let games = state.games.lock().unwrap().clone();
+    let mut family =
+        title_family(&games, &meta, &base_id).ok_or((StatusCode::NOT_FOUND, "Title not owned"))?;
+    family.user_data = state.user_library.lock().await.get(&base_id).cloned();
+
+    let owned_versions: HashSet<u64> = family
+        .base
+        .iter()
+        .chain(&family.updates)
+        .filter_map(version_number)
+        .collect();
+    let versions: Vec<serde_json::Value> = meta
+        .get_versions(&base_id)
+        .into_iter()
+        .map(|(version, date)| {
+            serde_json::json!({
+                "version": version,
+                "release_date": date,
+                "owned": owned_versions.contains(&version),
+            })
+        })
+        .collect();
+    let owned_dlc: HashSet<&str> = family
+        .dlc
+        .iter()
+        .filter_map(|g| g.title_id.as_deref())
+        .collect();
+    let missing_dlc: Vec<serde_json::Value> = meta
+        .get_dlc_ids(&base_id)
+        .into_iter()
+        .filter(|id| !owned_dlc.contains(id.as_str()))
+        .map(|id| {
+            let name = meta.get_title_info(&id).and_then(|i| i.name);
+            serde_json::json!({ "title_id": id, "name": name })
+        })
+        .collect();
+
+    let mut body = serde_json::to_value(&family).unwrap_or_default();
+    body["versions"] = serde_json::Value::Array(versions);
+    body["missing_dlc_titles"] = serde_json::Value::Array(missing_dlc);
+    Ok(Json(body))
+}
+
+#[derive(Deserialize)]
+pub struct CatalogQuery {
+    #[serde(default)]
+    pub q: String,
+    #[serde(default)]
+    pub mode: SearchMode,
+    pub category: Option<String>,
+    pub publisher: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+/// Searches every title known to titledb, owned or not, by name or ID.
+pub async fn catalog_search(
+    Query(query): Query<CatalogQuery>,
+    State(state): State<AppState>,
+) -> Json<serde_json::Value> {
+    let meta = state.metadata.snapshot();
+    let owned: HashSet<String> = {
+        let games = state.games.lock().unwrap();
+        games.iter().filter_map(|g| g.title_id.clone()).collect()
+    };
+
+    let found = meta.search_catalog(
+        &query.q,
+        query.mode,
+        query.category.as_deref(),
+        query.publisher.as_deref(),
+    );
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(CATALOG_PAGE_SIZE)
+        .clamp(1, CATALOG_MAX_PAGE_SIZE);
+
+    let results: Vec<serde_json::Value> = found
+        .iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .map(|id| {
+            let info = meta.get_title_info(id).unwrap_or_default();
+            serde_json::json!({
+                "title_id": id,
+                "name": info.name,
+                "publisher": info.publisher,
+                "category": info.category,
+                "release_date": info.release_date,
+                "icon_url": info.icon_url,
+                "kind": meta.get_title_kind(id),
+                "owned": owned.contains(id),
+            })
+        })
+        .collect();
+
+    Json(serde_json::json!({
+        "total": found.len(),
+        "page": page,
+        "per_page": per_page,
+        "results": results,
+    }))
 }
 
 pub async fn sync_metadata(State(state): State<AppState>) -> Json<serde_json::Value> {
     info!("Manual metadata sync requested.");
-    let metadata = state.metadata.clone();
-    let tx = state.tx.clone();
-    let games_dir = state.settings.games_dir.clone();
-    let data_dir = state.settings.data_dir.clone();
-    let games = state.games.clone();
 
+    // The library is re-enriched in memory once the new snapshot is published
     tokio::spawn(async move {
-        {
-            let mut meta = metadata.lock().await;
-            if let Err(e) = meta.sync().await {
-                error!("Manual sync failed: {}", e);
-                return;
-            }
+        let before = state.metadata.snapshot();
+        if let Err(e) = state.metadata.sync().await {
+            error!("Manual sync failed: {}", e);
+            return;
         }
 
-        // Trigger re-scan
-        info!("Metadata synced, starting full re-scan...");
-        let meta_provider = metadata.lock().await;
-        let mut new_games = Vec::new();
-        for entry in WalkDir::new(&games_dir).into_iter().filter_map(|e| e.ok()) {
-            if let Some(game) =
-                process_entry(entry.path(), &games_dir, &data_dir, Some(&meta_provider))
-            {
-                new_games.push(game);
-            }
-        }
-        let mut g_lock = games.lock().unwrap();
-        *g_lock = new_games;
-        drop(g_lock);
-
-        let _ = tx.send(
+        let _ = state.tx.send(
             serde_json::json!({
-                "type": "scan",
-                "status": "complete",
-                "count": 0
+                "type": "sync",
+                "status": "complete"
             })
             .to_string(),
         );
+        notify_new_versions(&state, &before);
     });
 
     Json(serde_json::json!({ "status": "started" }))
 }
 
+/// Owned games with a newer update available than the newest one owned.
+pub async fn list_outdated(State(state): State<AppState>) -> Json<Vec<OutdatedTitle>> {
+    let meta = state.metadata.snapshot();
+    let games = state.games.lock().unwrap().clone();
+    Json(outdated_titles(&games, &meta))
+}
+
 pub async fn sse_handler(
     State(state): State<AppState>,
 ) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
@@ -91,3 +415,56 @@ pub async fn sse_handler(
 
     Sse::new(stream).keep_alive(axum::response::sse::KeepAlive::default())
 }
+
+/// What happened to the files dropped into the inbox, newest first.
+pub async fn inbox_log(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let log = state.ingest_log.lock().await;
+    let entries: Vec<_> = log.entries().iter().rev().collect();
+    Json(serde_json::json!({
+        "enabled": state.settings.inbox_dir.is_some(),
+        "entries": entries,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct IntegrityQuery {
+    /// Include the record of every hashed file, not only corrupt ones.
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// Hashing progress and the files whose content changed on disk without
+/// a new size or modification time.
+pub async fn library_integrity(
+    Query(query): Query<IntegrityQuery>,
+    State(state): State<AppState>,
+) -> Json<serde_json::Value> {
+    let games: Vec<(String, u64)> = {
+        let games = state.games.lock().unwrap();
+        games
+            .iter()
+            .map(|g| (g.relative_path.clone(), g.size))
+            .collect()
+    };
+    let records = state.integrity.records();
+    let hashed = games
+        .iter()
+        .filter(|(path, size)| records.get(path).is_some_and(|r| r.size == *size))
+        .count();
+    let verified = records.values().filter(|r| r.verified_at.is_some()).count();
+    let corrupt: Vec<_> = records
+        .iter()
+        .filter(|(_, r)| r.corrupt.is_some())
+        .map(|(path, r)| serde_json::json!({ "path": path, "record": r }))
+        .collect();
+    Json(serde_json::json!({
+        "enabled": state.settings.integrity_enabled,
+        "verify_days": state.settings.integrity_verify_days,
+        "files": games.len(),
+        "hashed": hashed,
+        "pending": games.len() - hashed,
+        "verified": verified,
+        "corrupt": corrupt,
+        "records": query.all.then_some(&*records),
+    }))
+}
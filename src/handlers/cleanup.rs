@@ -0,0 +1,186 @@
+use crate::cleanup::{CleanupCandidate, Quarantine, QuarantineEntry, find_redundant};
+use crate::library::{upsert_game, with_user_data};
+use crate::scanner::{Game, discover};
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use tracing::{error, warn};
+
+#[derive(Deserialize)]
+pub struct CleanupQuery {
+    /// Also report copies of a file in a less preferred format.
+    #[serde(default)]
+    pub formats: bool,
+}
+
+fn candidates(state: &AppState, formats: bool) -> Vec<CleanupCandidate> {
+    let preferred = state.settings.preferred_formats();
+    let games = state.games.lock().unwrap();
+    find_redundant(&games, formats.then_some(preferred.as_slice()))
+}
+
+/// Files made redundant by other owned files and the space they take.
+pub async fn analyze(
+    Query(query): Query<CleanupQuery>,
+    State(state): State<AppState>,
+) -> Json<serde_json::Value> {
+    let candidates = candidates(&state, query.formats);
+    let reclaimable: u64 = candidates.iter().map(|c| c.size).sum();
+    Json(serde_json::json!({
+        "candidates": candidates,
+        "reclaimable": reclaimable,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct QuarantineRequest {
+    /// Paths relative to `games_dir`.
+    pub paths: Vec<String>,
+}
+
+/// Moves the selected library files into the quarantine.
+pub async fn quarantine_files(
+    State(state): State<AppState>,
+    Json(request): Json<QuarantineRequest>,
+) -> Json<serde_json::Value> {
+    let reasons = candidates(&state, true);
+    let _ops = state.file_ops.lock().await;
+    let mut quarantine = state.quarantine.lock().await;
+    let mut moved = Vec::new();
+    let mut failed = Vec::new();
+
+    for path in request.paths {
+        let game = {
+            let games = state.games.lock().unwrap();
+            games.iter().find(|g| g.relative_path == path).cloned()
+        };
+        let Some(game) = game else {
+            failed.push(serde_json::json!({ "path": path, "error": "Game not found" }));
+            continue;
+        };
+        let reason = reasons
+            .iter()
+            .find(|c| c.relative_path == path)
+            .map_or("manual", |c| c.reason);
+
+        let _held = state.watch_suppression.hold(vec![game.path.clone()]);
+        match quarantine.add(&game, reason).await {
+            Ok(entry) => {
+                state.games.lock().unwrap().retain(|g| g.path != game.path);
+                let _ = state.tx.send(
+                    serde_json::json!({ "type": "scan", "status": "remove", "id": game.id, "path": game.path })
+                        .to_string(),
+                );
+                moved.push(entry);
+            }
+            Err(e) => {
+                error!("Failed to quarantine {}: {}", path, e);
+                failed.push(serde_json::json!({ "path": path, "error": e.to_string() }));
+            }
+        }
+    }
+
+    Json(serde_json::json!({ "quarantined": moved, "failed": failed }))
+}
+
+pub async fn list_quarantine(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let quarantine = state.quarantine.lock().await;
+    let total: u64 = quarantine.entries().iter().map(|e| e.size).sum();
+    Json(serde_json::json!({
+        "entries": quarantine.entries(),
+        "total_size": total,
+        "retention_days": state.settings.quarantine_retention_days,
+    }))
+}
+
+pub async fn restore(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Game>, (StatusCode, &'static str)> {
+    restore_from(&state, &state.quarantine, &id, "Quarantined file not found").await
+}
+
+/// Moves a file from the quarantine or the trash back into the library.
+pub async fn restore_from(
+    state: &AppState,
+    store: &tokio::sync::Mutex<Quarantine>,
+    id: &str,
+    not_found: &'static str,
+) -> Result<Json<Game>, (StatusCode, &'static str)> {
+    let _ops = state.file_ops.lock().await;
+    let mut store = store.lock().await;
+    let target = store
+        .entries()
+        .iter()
+        .find(|e| e.id == id)
+        .map(|e| state.settings.games_dir.join(&e.relative_path))
+        .ok_or((StatusCode::NOT_FOUND, not_found))?;
+    let _held = state.watch_suppression.hold(vec![target]);
+    let path = match store.restore(id, &state.settings.games_dir).await {
+        Ok(path) => path,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err((StatusCode::NOT_FOUND, not_found));
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err((
+                StatusCode::CONFLICT,
+                "A file already exists at the original path",
+            ));
+        }
+        Err(e) => {
+            error!("Failed to restore {}: {}", id, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to restore file"));
+        }
+    };
+    drop(store);
+
+    let game = discover(&path, &state.settings.games_dir).ok_or((
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Restored file is not a game",
+    ))?;
+    let game = with_user_data(state, upsert_game(state, game)).await;
+    let _ = state
+        .tx
+        .send(serde_json::json!({ "type": "scan", "status": "update", "game": game }).to_string());
+    Ok(Json(game))
+}
+
+pub async fn purge(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    purge_from(&state.quarantine, &id, "Quarantined file not found").await
+}
+
+/// Deletes a file from the quarantine or the trash for good.
+pub async fn purge_from(
+    store: &tokio::sync::Mutex<Quarantine>,
+    id: &str,
+    not_found: &'static str,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    match store.lock().await.purge(id).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err((StatusCode::NOT_FOUND, not_found))
+        }
+        Err(e) => {
+            warn!("Failed to purge {}: {}", id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to purge file"))
+        }
+    }
+}
+
+/// Purges everything older than the retention period now instead of
+/// waiting for the daily task.
+pub async fn purge_expired(State(state): State<AppState>) -> Json<Vec<QuarantineEntry>> {
+    let mut quarantine = state.quarantine.lock().await;
+    Json(
+        quarantine
+            .purge_expired(state.settings.quarantine_retention_days)
+            .await,
+    )
+}
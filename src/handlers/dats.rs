@@ -0,0 +1,62 @@
+use crate::dat::{DatSummary, parse, reverify_library, verification_report};
+use crate::state::AppState;
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use tracing::{error, info};
+
+pub async fn list_dats(State(state): State<AppState>) -> Json<Vec<DatSummary>> {
+    Json(
+        state
+            .dats
+            .snapshot()
+            .dats()
+            .iter()
+            .map(|d| d.summary())
+            .collect(),
+    )
+}
+
+/// Imports the Logiqx XML DAT in the request body and checks the library
+/// against it.
+pub async fn import_dat(
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<DatSummary>), (StatusCode, &'static str)> {
+    let xml = std::str::from_utf8(&body).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid XML"))?;
+    let dat = parse(xml).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let summary = dat.summary();
+    if let Err(e) = state.dats.add(dat).await {
+        error!("Failed to save DAT {}: {}", summary.name, e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to save DAT"));
+    }
+    info!("Imported DAT {} with {} files", summary.name, summary.roms);
+    reverify_library(&state);
+    Ok((StatusCode::CREATED, Json(summary)))
+}
+
+pub async fn delete_dat(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    match state.dats.remove(&id).await {
+        Ok(Some(_)) => {
+            reverify_library(&state);
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(None) => Err((StatusCode::NOT_FOUND, "DAT not found")),
+        Err(e) => {
+            error!("Failed to remove DAT {}: {}", id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to save DATs"))
+        }
+    }
+}
+
+/// Checks the library against the imported DATs and reports the files
+/// that don't match.
+pub async fn library_verification(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(verification_report(&state))
+}
@@ -9,7 +9,10 @@ pub async fn dbi_index(State(state): State<AppState>) -> axum::response::Html<St
         "<!DOCTYPE html><html><head><title>DBI Index</title></head><body><h1>Index of /</h1><ul>",
     );
 
-    for game in games.iter() {
+    for game in games
+        .iter()
+        .filter(|g| !(state.settings.hide_broken_files && g.is_broken()))
+    {
         let url = encode_path(&game.relative_path);
         let name = game.name.clone();
 
@@ -1,4 +1,5 @@
-use crate::downloads::DownloadState;
+use crate::downloads::{DownloadGuard, DownloadState, now};
+use crate::integrity::{repr_digest, stamp};
 use crate::state::AppState;
 use axum::{
     body::{Body, Bytes},
@@ -11,7 +12,7 @@ use axum::{
 };
 use futures::stream::StreamExt;
 use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
-use std::path::Path as StdPath;
+use std::path::{Path as StdPath, PathBuf};
 use tokio::fs::File;
 use tokio_util::io::ReaderStream;
 use tracing::{error, info};
@@ -27,6 +28,28 @@ pub async fn download_file(
         return Err((axum::http::StatusCode::FORBIDDEN, "Forbidden"));
     }
 
+    serve_file(&state, file_path).await
+}
+
+/// Downloads a library file by its game ID.
+pub async fn download_game(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let path = {
+        let games = state.games.lock().unwrap();
+        games.iter().find(|g| g.id == id).map(|g| g.path.clone())
+    };
+    match path {
+        Some(path) => serve_file(&state, path).await,
+        None => Err((axum::http::StatusCode::NOT_FOUND, "Game not found")),
+    }
+}
+
+async fn serve_file(
+    state: &AppState,
+    file_path: PathBuf,
+) -> Result<(HeaderMap, Body), (axum::http::StatusCode, &'static str)> {
     let file = match File::open(&file_path).await {
         Ok(f) => f,
         Err(e) => {
@@ -38,6 +61,13 @@ pub async fn download_file(
     let metadata = file.metadata().await.unwrap();
     let total_size = metadata.len();
     let filename = file_path.file_name().unwrap().to_string_lossy().to_string();
+    let game_id = {
+        let games = state.games.lock().unwrap();
+        games
+            .iter()
+            .find(|g| g.path == file_path)
+            .map(|g| g.id.clone())
+    };
 
     let download_id = Uuid::new_v4().to_string();
     info!("Starting download: {} (ID: {})", filename, download_id);
@@ -48,23 +78,29 @@ pub async fn download_file(
             download_id.clone(),
             DownloadState {
                 id: download_id.clone(),
+                game_id,
                 filename: filename.clone(),
                 total_size,
                 bytes_sent: 0,
                 speed: 0,
+                started_at: now(),
             },
         );
     }
 
     let stream = ReaderStream::new(file);
-    let downloads_clone = state.downloads.clone();
-    let id_clone = download_id.clone();
+    // Moves the download to the history once the body is dropped
+    let guard = DownloadGuard {
+        id: download_id,
+        downloads: state.downloads.clone(),
+        history: state.download_history.clone(),
+    };
 
     let stream = stream.map(move |chunk: Result<Bytes, std::io::Error>| {
         if let Ok(bytes) = &chunk {
             let len = bytes.len() as u64;
-            if let Ok(mut downloads) = downloads_clone.lock()
-                && let Some(download) = downloads.get_mut(&id_clone)
+            if let Ok(mut downloads) = guard.downloads.lock()
+                && let Some(download) = downloads.get_mut(&guard.id)
             {
                 download.bytes_sent += len;
             }
@@ -103,6 +139,20 @@ pub async fn download_file(
         headers.insert(CONTENT_LENGTH, val);
     }
 
+    if state.settings.integrity_repr_digest
+        && let Ok(relative) = file_path.strip_prefix(&state.settings.games_dir)
+    {
+        let (size, modified) = stamp(&metadata);
+        if let Some(digest) = state
+            .integrity
+            .current(&relative.to_string_lossy(), size, modified)
+            .and_then(|r| repr_digest(&r.sha256))
+            && let Ok(val) = HeaderValue::from_str(&digest)
+        {
+            headers.insert("Repr-Digest", val);
+        }
+    }
+
     Ok((headers, body))
 }
 
@@ -0,0 +1,157 @@
+use crate::cleanup::QuarantineEntry;
+use crate::fileops::{self, is_inside, library_dir, valid_file_name};
+use crate::handlers::cleanup::{purge_from, restore_from};
+use crate::scanner::Game;
+use crate::state::AppState;
+use crate::webdav::{check_credentials, unauthorized};
+use axum::{
+    Json,
+    extract::{Path, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::path::PathBuf;
+use tracing::error;
+
+/// File management uses the WebDAV credentials and is disabled when none
+/// are configured.
+pub async fn require_credentials(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    match check_credentials(&state.settings, req.headers()) {
+        None => (
+            StatusCode::FORBIDDEN,
+            "File management requires WebDAV credentials to be configured",
+        )
+            .into_response(),
+        Some(false) => unauthorized().into_response(),
+        Some(true) => next.run(req).await,
+    }
+}
+
+fn find_game(state: &AppState, id: &str) -> Result<Game, (StatusCode, &'static str)> {
+    let games = state.games.lock().unwrap();
+    games
+        .iter()
+        .find(|g| g.id == id)
+        .cloned()
+        .ok_or((StatusCode::NOT_FOUND, "Game not found"))
+}
+
+async fn relocate(
+    state: &AppState,
+    game: Game,
+    target: PathBuf,
+) -> Result<Json<Game>, (StatusCode, &'static str)> {
+    let _ops = state.file_ops.lock().await;
+    match fileops::relocate(state, &game.path, &target).await {
+        Ok(game) => Ok(Json(game)),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err((
+            StatusCode::CONFLICT,
+            "A file already exists at the target path",
+        )),
+        Err(e) => {
+            error!("Failed to move {:?} to {:?}: {}", game.path, target, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to move file"))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RenameRequest {
+    /// The new file name, keeping a game extension.
+    pub name: String,
+}
+
+/// Renames a file within its folder. The game's id changes with its path.
+pub async fn rename_game(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<RenameRequest>,
+) -> Result<Json<Game>, (StatusCode, &'static str)> {
+    let game = find_game(&state, &id)?;
+    if !valid_file_name(&request.name) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid file name"));
+    }
+    let target = game.path.with_file_name(&request.name);
+    relocate(&state, game, target).await
+}
+
+#[derive(Deserialize)]
+pub struct MoveRequest {
+    /// Relative to `games_dir`; empty for the top level.
+    pub folder: String,
+}
+
+/// Moves a file to another folder of the library, creating it if needed.
+pub async fn move_game(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<MoveRequest>,
+) -> Result<Json<Game>, (StatusCode, &'static str)> {
+    let game = find_game(&state, &id)?;
+    let games_dir = &state.settings.games_dir;
+    let folder = library_dir(games_dir, &request.folder)
+        .filter(|dir| is_inside(games_dir, dir))
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "Folder is outside the games directory",
+        ))?;
+    let Some(filename) = game.path.file_name() else {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Game has no file name"));
+    };
+    let target = folder.join(filename);
+    relocate(&state, game, target).await
+}
+
+/// Moves a file to the trash in `data_dir/trash`.
+pub async fn delete_game(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<QuarantineEntry>, (StatusCode, &'static str)> {
+    let game = find_game(&state, &id)?;
+    let _ops = state.file_ops.lock().await;
+    let mut trash = state.trash.lock().await;
+    let _held = state.watch_suppression.hold(vec![game.path.clone()]);
+    let entry = trash.add(&game, "deleted").await.map_err(|e| {
+        error!("Failed to move {:?} to the trash: {}", game.path, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to move file to the trash",
+        )
+    })?;
+    state.games.lock().unwrap().retain(|g| g.path != game.path);
+    let _ = state.tx.send(
+        serde_json::json!({ "type": "scan", "status": "remove", "id": game.id, "path": game.path })
+            .to_string(),
+    );
+    Ok(Json(entry))
+}
+
+pub async fn list_trash(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let trash = state.trash.lock().await;
+    let total: u64 = trash.entries().iter().map(|e| e.size).sum();
+    Json(serde_json::json!({
+        "entries": trash.entries(),
+        "total_size": total,
+        "retention_days": state.settings.trash_retention_days,
+    }))
+}
+
+pub async fn restore_trash(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Game>, (StatusCode, &'static str)> {
+    restore_from(&state, &state.trash, &id, "File not found in the trash").await
+}
+
+pub async fn purge_trash(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    purge_from(&state.trash, &id, "File not found in the trash").await
+}
@@ -0,0 +1,137 @@
+use crate::matcher::MatchCandidate;
+use crate::metadata::MetadataProvider;
+use crate::overrides::{is_title_id, normalize_key};
+use crate::scanner::Game;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+const CANDIDATE_LIMIT: usize = 5;
+
+/// A file whose title ID does not come from its name, with what the matcher
+/// made of it.
+#[derive(Serialize)]
+pub struct MatchEntry {
+    pub relative_path: String,
+    pub name: String,
+    pub title_id: Option<String>,
+    pub match_confidence: Option<f64>,
+    /// Whether the current title ID (or its absence) was confirmed by the user.
+    pub confirmed: bool,
+    pub candidates: Vec<MatchCandidate>,
+}
+
+#[derive(Deserialize)]
+pub struct CandidatesQuery {
+    /// Search for this name instead of the one parsed from the filename.
+    pub q: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MatchDecision {
+    /// The confirmed title ID, or `null` if the file has none.
+    pub title_id: Option<String>,
+}
+
+fn match_entry(meta: &MetadataProvider, game: &Game, query: Option<&str>) -> MatchEntry {
+    // `game.name` may already be the matched title's name, so search with
+    // the one from the filename
+    let file_name = crate::scanner::name_from_path(&game.relative_path);
+    MatchEntry {
+        confirmed: meta.title_matches().get(&game.relative_path).is_some(),
+        candidates: meta.match_candidates(query.unwrap_or(&file_name), CANDIDATE_LIMIT),
+        relative_path: game.relative_path.clone(),
+        name: file_name,
+        title_id: game.title_id.clone(),
+        match_confidence: game.match_confidence,
+    }
+}
+
+fn is_unidentified(game: &Game) -> bool {
+    game.title_id.is_none() || game.match_confidence.is_some()
+}
+
+/// Files without a title ID in their name: unmatched ones and the ones
+/// matched by name, so the guesses can be reviewed.
+pub async fn list_matches(State(state): State<AppState>) -> Json<Vec<MatchEntry>> {
+    let meta = state.metadata.snapshot();
+    let games: Vec<Game> = state
+        .games
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|g| is_unidentified(g))
+        .cloned()
+        .collect();
+    Json(games.iter().map(|g| match_entry(&meta, g, None)).collect())
+}
+
+pub async fn get_match(
+    Path(path): Path<String>,
+    Query(query): Query<CandidatesQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<MatchEntry>, (StatusCode, &'static str)> {
+    let meta = state.metadata.snapshot();
+    let key = normalize_key(&path);
+    let game = state
+        .games
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|g| normalize_key(&g.relative_path) == key)
+        .cloned()
+        .ok_or((StatusCode::NOT_FOUND, "Game not found"))?;
+    Ok(Json(match_entry(&meta, &game, query.q.as_deref())))
+}
+
+pub async fn put_match(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+    Json(decision): Json<MatchDecision>,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    if decision
+        .title_id
+        .as_deref()
+        .is_some_and(|id| !is_title_id(id))
+    {
+        return Err((StatusCode::BAD_REQUEST, "Invalid title ID"));
+    }
+    if let Err(e) = state
+        .metadata
+        .set_title_match(&path, decision.title_id.clone())
+        .await
+    {
+        error!("Failed to save title match for {}: {}", path, e);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to save title match",
+        ));
+    }
+    info!("Title match for {} set to {:?}", path, decision.title_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn delete_match(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    match state.metadata.remove_title_match(&path).await {
+        Ok(true) => {
+            info!("Title match for {} removed", path);
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err((StatusCode::NOT_FOUND, "Title match not found")),
+        Err(e) => {
+            error!("Failed to remove title match for {}: {}", path, e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to save title match",
+            ))
+        }
+    }
+}
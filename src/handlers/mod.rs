@@ -1,5 +1,13 @@
 pub mod api;
+pub mod cleanup;
+pub mod dats;
 pub mod dbi;
 pub mod files;
+pub mod manage;
+pub mod matches;
+pub mod organize;
+pub mod overrides;
 pub mod tinfoil;
+pub mod uploads;
+pub mod userdata;
 pub mod web;
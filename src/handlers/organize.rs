@@ -0,0 +1,166 @@
+use crate::downloads::now;
+use crate::fileops::{is_inside, relocate};
+use crate::organizer::{Move, OnConflict, OrganizeRun, Plan, Template, plan, remove_empty_parents};
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use tracing::warn;
+
+#[derive(Deserialize, Default)]
+pub struct OrganizeQuery {
+    /// Defaults to the configured `organize_template`.
+    pub template: Option<String>,
+    #[serde(default)]
+    pub on_conflict: OnConflict,
+}
+
+fn build_plan(
+    state: &AppState,
+    query: &OrganizeQuery,
+) -> Result<(String, Plan), (StatusCode, &'static str)> {
+    let template = query
+        .template
+        .clone()
+        .unwrap_or_else(|| state.settings.organize_template.clone());
+    let parsed = Template::parse(&template)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid organize template"))?;
+    let games = state.games.lock().unwrap().clone();
+    let meta = state.metadata.snapshot();
+    let plan = plan(
+        &games,
+        &meta,
+        &parsed,
+        &state.settings.games_dir,
+        query.on_conflict,
+    );
+    Ok((template, plan))
+}
+
+/// The moves the template calls for, without making them.
+pub async fn preview(
+    Query(query): Query<OrganizeQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Plan>, (StatusCode, &'static str)> {
+    build_plan(&state, &query).map(|(_, plan)| Json(plan))
+}
+
+#[derive(Deserialize)]
+pub struct ApplyRequest {
+    #[serde(flatten)]
+    pub query: OrganizeQuery,
+    /// Only move these games; all of the plan by default.
+    pub ids: Option<Vec<String>>,
+}
+
+/// Computes the plan again and makes its moves, recording them so the run
+/// can be undone.
+pub async fn apply(
+    State(state): State<AppState>,
+    Json(request): Json<ApplyRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, &'static str)> {
+    let _ops = state.file_ops.lock().await;
+    let (template, plan) = build_plan(&state, &request.query)?;
+    let games_dir = &state.settings.games_dir;
+
+    let mut moves = Vec::new();
+    let mut failed = Vec::new();
+    for planned in plan
+        .moves
+        .into_iter()
+        .filter(|m| request.ids.as_ref().is_none_or(|ids| ids.contains(&m.id)))
+    {
+        let from = games_dir.join(&planned.from);
+        let to = games_dir.join(&planned.to);
+        // A folder on the way could be a symlink out of the library
+        let result = if is_inside(games_dir, &to) {
+            relocate(&state, &from, &to).await
+        } else {
+            Err(std::io::ErrorKind::PermissionDenied.into())
+        };
+        match result {
+            Ok(_) => {
+                remove_empty_parents(&from, games_dir).await;
+                moves.push(Move {
+                    from: planned.from,
+                    to: planned.to,
+                });
+            }
+            Err(e) => {
+                warn!("Failed to move {} to {}: {}", planned.from, planned.to, e);
+                failed.push(serde_json::json!({
+                    "id": planned.id,
+                    "from": planned.from,
+                    "to": planned.to,
+                    "error": e.to_string(),
+                }));
+            }
+        }
+    }
+
+    let run = OrganizeRun {
+        id: uuid::Uuid::new_v4().to_string(),
+        applied_at: now(),
+        template,
+        moves,
+    };
+    if !run.moves.is_empty() {
+        state.organize_log.lock().await.record(run.clone()).await;
+    }
+    Ok(Json(serde_json::json!({
+        "run": run,
+        "failed": failed,
+        "conflicts": plan.conflicts,
+        "skipped": plan.skipped,
+    })))
+}
+
+pub async fn list_runs(State(state): State<AppState>) -> Json<Vec<OrganizeRun>> {
+    Json(state.organize_log.lock().await.runs().to_vec())
+}
+
+/// Moves the files of a run back, newest first. Moves that can't be undone,
+/// e.g. because the original path is taken again, stay in the log.
+pub async fn undo(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, &'static str)> {
+    let _ops = state.file_ops.lock().await;
+    let mut log = state.organize_log.lock().await;
+    let run = log
+        .get(&id)
+        .cloned()
+        .ok_or((StatusCode::NOT_FOUND, "Organize run not found"))?;
+    let games_dir = &state.settings.games_dir;
+
+    let mut restored = Vec::new();
+    let mut remaining = Vec::new();
+    let mut failed = Vec::new();
+    for m in run.moves.into_iter().rev() {
+        let to = games_dir.join(&m.to);
+        match relocate(&state, &to, &games_dir.join(&m.from)).await {
+            Ok(_) => {
+                remove_empty_parents(&to, games_dir).await;
+                restored.push(m);
+            }
+            Err(e) => {
+                warn!("Failed to move {} back to {}: {}", m.to, m.from, e);
+                failed.push(serde_json::json!({
+                    "from": m.to,
+                    "to": m.from,
+                    "error": e.to_string(),
+                }));
+                remaining.push(m);
+            }
+        }
+    }
+    remaining.reverse();
+    log.set_remaining(&id, remaining).await;
+    Ok(Json(serde_json::json!({
+        "restored": restored,
+        "failed": failed,
+    })))
+}
@@ -0,0 +1,172 @@
+use crate::overrides::{TitleOverride, is_title_id, normalize_key};
+use crate::state::AppState;
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header::CONTENT_TYPE},
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::{error, info};
+
+const CUSTOM_ICON_PREFIX: &str = "/images/custom/";
+
+pub async fn list_overrides(State(state): State<AppState>) -> Json<HashMap<String, TitleOverride>> {
+    let meta = state.metadata.snapshot();
+    Json(meta.overrides().all().clone())
+}
+
+pub async fn get_override(
+    Path(key): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<TitleOverride>, (StatusCode, &'static str)> {
+    let meta = state.metadata.snapshot();
+    meta.overrides()
+        .get(&key)
+        .cloned()
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "Override not found"))
+}
+
+pub async fn put_override(
+    Path(key): Path<String>,
+    State(state): State<AppState>,
+    Json(value): Json<TitleOverride>,
+) -> Result<Json<TitleOverride>, (StatusCode, &'static str)> {
+    if let Err(e) = state
+        .metadata
+        .update_override(&key, |ov| *ov = value.clone())
+        .await
+    {
+        error!("Failed to save override for {}: {}", key, e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to save override"));
+    }
+    info!("Metadata override updated for {}", key);
+    Ok(Json(value))
+}
+
+pub async fn delete_override(
+    Path(key): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    match state.metadata.remove_override(&key).await {
+        Ok(Some(removed)) => {
+            remove_custom_icon(&state, removed.icon_url.as_deref()).await;
+            info!("Metadata override removed for {}", key);
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Override not found")),
+        Err(e) => {
+            error!("Failed to remove override for {}: {}", key, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to save override"))
+        }
+    }
+}
+
+/// Stores the request body as a custom icon and points the override's
+/// `icon_url` at it. The image is served from `/images/custom/`.
+pub async fn put_icon(
+    Path(key): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<TitleOverride>, (StatusCode, &'static str)> {
+    let ext = match headers.get(CONTENT_TYPE).and_then(|h| h.to_str().ok()) {
+        Some("image/png") => "png",
+        Some("image/jpeg") => "jpg",
+        Some("image/webp") => "webp",
+        Some("image/gif") => "gif",
+        _ => {
+            return Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Icon must be a PNG, JPEG, WebP or GIF image",
+            ));
+        }
+    };
+    if body.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Empty icon"));
+    }
+
+    let key = normalize_key(&key);
+    let stem = if is_title_id(&key) {
+        key.clone()
+    } else {
+        let digest = Sha256::digest(key.as_bytes());
+        digest
+            .iter()
+            .take(8)
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    };
+    let filename = format!("{}.{}", stem, ext);
+    let icons_dir = state.settings.data_dir.join("images").join("custom");
+
+    if let Err(e) = tokio::fs::create_dir_all(&icons_dir).await {
+        error!("Failed to create {:?}: {}", icons_dir, e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to store icon"));
+    }
+    if let Err(e) = tokio::fs::write(icons_dir.join(&filename), &body).await {
+        error!("Failed to write custom icon {}: {}", filename, e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to store icon"));
+    }
+
+    let icon_url = format!("{}{}", CUSTOM_ICON_PREFIX, filename);
+    let mut previous = None;
+    let value = match state
+        .metadata
+        .update_override(&key, |ov| previous = ov.icon_url.replace(icon_url.clone()))
+        .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to save override for {}: {}", key, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to save override"));
+        }
+    };
+    if previous.as_deref() != Some(icon_url.as_str()) {
+        remove_custom_icon(&state, previous.as_deref()).await;
+    }
+    info!("Custom icon stored for {}", key);
+    Ok(Json(value))
+}
+
+pub async fn delete_icon(
+    Path(key): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    if state
+        .metadata
+        .snapshot()
+        .overrides()
+        .get(&key)
+        .is_none_or(|ov| ov.icon_url.is_none())
+    {
+        return Err((StatusCode::NOT_FOUND, "Override not found"));
+    }
+    let mut previous = None;
+    if let Err(e) = state
+        .metadata
+        .update_override(&key, |ov| previous = ov.icon_url.take())
+        .await
+    {
+        error!("Failed to save override for {}: {}", key, e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to save override"));
+    }
+    remove_custom_icon(&state, previous.as_deref()).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_custom_icon(state: &AppState, icon_url: Option<&str>) {
+    if let Some(file) = icon_url.and_then(|u| u.strip_prefix(CUSTOM_ICON_PREFIX))
+        && !file.contains('/')
+    {
+        let path = state
+            .settings
+            .data_dir
+            .join("images")
+            .join("custom")
+            .join(file);
+        let _ = tokio::fs::remove_file(path).await;
+    }
+}
@@ -11,6 +11,7 @@ use axum::{
 use tracing::error;
 
 pub async fn tinfoil_index(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    let meta = state.metadata.snapshot();
     let games = state.games.lock().unwrap();
 
     // Determine host from header or fallback to internal config
@@ -22,6 +23,7 @@ pub async fn tinfoil_index(State(state): State<AppState>, headers: HeaderMap) ->
 
     let files: Vec<serde_json::Value> = games
         .iter()
+        .filter(|g| !(state.settings.hide_broken_files && g.is_broken()))
         .map(|game| {
             let encoded_path = encode_path(&game.relative_path);
             let url = format!("{}/files/{}", host, encoded_path);
@@ -33,10 +35,51 @@ pub async fn tinfoil_index(State(state): State<AppState>, headers: HeaderMap) ->
         })
         .collect();
 
-    let shop_json = serde_json::json!({
+    // Tinfoil accepts custom title info in the index; use it to publish the
+    // user's metadata overrides and other non-titledb sources so they show
+    // up on the console too.
+    let mut titledb = serde_json::Map::new();
+    for game in games.iter() {
+        let Some(tid) = game.title_id.as_deref() else {
+            continue;
+        };
+        if !meta.has_custom_info(tid) && meta.overrides().get(&game.relative_path).is_none() {
+            continue;
+        }
+        if let Some(info) = meta.resolve_title_info(Some(tid), &game.relative_path) {
+            let absolute = |url: Option<String>| {
+                url.map(|u| {
+                    if u.starts_with('/') {
+                        format!("{}{}", host, u)
+                    } else {
+                        u
+                    }
+                })
+            };
+            titledb.insert(
+                tid.to_string(),
+                serde_json::json!({
+                    "id": tid,
+                    "name": info.name,
+                    "publisher": info.publisher,
+                    "iconUrl": absolute(info.icon_url),
+                    "bannerUrl": absolute(info.banner_url),
+                    "description": info.description,
+                    "category": info.category,
+                }),
+            );
+        }
+    }
+    drop(games);
+    drop(meta);
+
+    let mut shop_json = serde_json::json!({
         "files": files,
         "success": "The index was generated successfully.",
     });
+    if !titledb.is_empty() {
+        shop_json["titledb"] = serde_json::Value::Object(titledb);
+    }
 
     if state.settings.tinfoil_encrypt {
         let json_bytes = serde_json::to_vec(&shop_json).unwrap();
@@ -0,0 +1,320 @@
+use crate::cleanup::move_file;
+use crate::container::{MAGIC_LEN, has_magic, inspect};
+use crate::downloads::now;
+use crate::fileops::{is_inside, library_dir, valid_file_name};
+use crate::library::{upsert_game, with_user_data};
+use crate::scanner::discover;
+use crate::state::AppState;
+use crate::uploads::Upload;
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::stream::StreamExt;
+use serde::Deserialize;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{error, info, warn};
+
+/// Sent with every chunk: where its data starts. Returned with the state
+/// of an upload: how much was received.
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+
+type UploadError = (StatusCode, &'static str);
+
+#[derive(Deserialize)]
+pub struct CreateUpload {
+    /// The file name, keeping a game extension.
+    pub name: String,
+    pub size: u64,
+    /// Relative to `games_dir`; the top level by default.
+    #[serde(default)]
+    pub folder: String,
+}
+
+/// Whether the filesystems of `dirs` each have `needed` bytes free. Ones
+/// whose free space can't be read don't block the upload.
+fn has_space(dirs: &[&std::path::Path], needed: u64) -> bool {
+    dirs.iter().all(|dir| match fs4::available_space(dir) {
+        Ok(available) => available >= needed,
+        Err(e) => {
+            warn!("Failed to read free space of {:?}: {}", dir, e);
+            true
+        }
+    })
+}
+
+fn send_event(state: &AppState, event: serde_json::Value) {
+    let _ = state.tx.send(event.to_string());
+}
+
+/// Starts an upload. The data is then sent with `PUT` in one or more
+/// chunks.
+pub async fn create_upload(
+    State(state): State<AppState>,
+    Json(request): Json<CreateUpload>,
+) -> Result<(StatusCode, Json<Upload>), UploadError> {
+    let games_dir = &state.settings.games_dir;
+    if !valid_file_name(&request.name) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid file name"));
+    }
+    let folder = library_dir(games_dir, &request.folder)
+        .filter(|dir| is_inside(games_dir, dir))
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "Folder is outside the games directory",
+        ))?;
+    let target = folder.join(&request.name);
+    if target.exists() {
+        return Err((
+            StatusCode::CONFLICT,
+            "A file already exists at the target path",
+        ));
+    }
+    let relative_path = target
+        .strip_prefix(games_dir)
+        .unwrap_or(&target)
+        .to_string_lossy()
+        .to_string();
+
+    let mut uploads = state.uploads.lock().await;
+    if uploads
+        .uploads()
+        .iter()
+        .any(|u| u.relative_path == relative_path)
+    {
+        return Err((StatusCode::CONFLICT, "This file is already being uploaded"));
+    }
+    // Space promised to unfinished uploads is taken already
+    let pending: u64 = uploads.uploads().iter().map(Upload::remaining).sum();
+    if !has_space(
+        &[&state.settings.data_dir, games_dir],
+        request.size + pending,
+    ) {
+        return Err((StatusCode::INSUFFICIENT_STORAGE, "Not enough free space"));
+    }
+
+    let upload = uploads
+        .create(Upload {
+            id: uuid::Uuid::new_v4().to_string(),
+            relative_path,
+            size: request.size,
+            offset: 0,
+            created_at: now(),
+        })
+        .await
+        .map_err(|e| {
+            error!("Failed to start upload: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start upload")
+        })?;
+    Ok((StatusCode::CREATED, Json(upload)))
+}
+
+pub async fn list_uploads(State(state): State<AppState>) -> Json<Vec<Upload>> {
+    Json(state.uploads.lock().await.uploads().to_vec())
+}
+
+/// The state of an upload. The offset is also sent as a header, so a
+/// client can resume after a `HEAD`.
+pub async fn upload_status(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, UploadError> {
+    let upload = state
+        .uploads
+        .lock()
+        .await
+        .get(&id)
+        .cloned()
+        .ok_or((StatusCode::NOT_FOUND, "Upload not found"))?;
+    Ok(([(UPLOAD_OFFSET, upload.offset.to_string())], Json(upload)))
+}
+
+pub async fn cancel_upload(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, UploadError> {
+    match state.uploads.lock().await.remove(&id).await {
+        Ok(Some(_)) => Ok(StatusCode::NO_CONTENT),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Upload not found")),
+        Err(e) => {
+            warn!("Failed to cancel upload {}: {}", id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to cancel upload"))
+        }
+    }
+}
+
+/// Drops an upload whose data turned out not to be a game.
+async fn reject(state: &AppState, id: &str, reason: &'static str) -> UploadError {
+    if let Err(e) = state.uploads.lock().await.remove(id).await {
+        warn!("Failed to remove upload {}: {}", id, e);
+    }
+    send_event(
+        state,
+        serde_json::json!({ "type": "upload", "status": "failed", "id": id, "error": reason }),
+    );
+    (StatusCode::UNPROCESSABLE_ENTITY, reason)
+}
+
+/// Appends a chunk at the offset given in the `Upload-Offset` header. If
+/// the connection drops, whatever arrived is kept and the client resumes
+/// from the offset the upload reports. The last chunk moves the file into
+/// the library and returns the indexed game.
+pub async fn upload_chunk(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, UploadError> {
+    let offset: u64 = headers
+        .get(UPLOAD_OFFSET)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .ok_or((StatusCode::BAD_REQUEST, "Missing Upload-Offset header"))?;
+
+    let lock = state.uploads.lock().await.lock(&id);
+    let Ok(_writing) = lock.try_lock() else {
+        return Err((StatusCode::CONFLICT, "Upload is already receiving data"));
+    };
+    let (upload, part) = {
+        let uploads = state.uploads.lock().await;
+        let upload = uploads
+            .get(&id)
+            .cloned()
+            .ok_or((StatusCode::NOT_FOUND, "Upload not found"))?;
+        (upload, uploads.part_path(&id))
+    };
+    if offset != upload.offset {
+        return Err((StatusCode::CONFLICT, "Offset does not match the upload"));
+    }
+
+    let write_error = |e: std::io::Error| {
+        error!("Failed to write upload {}: {}", id, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to write upload")
+    };
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(&part)
+        .await
+        .map_err(write_error)?;
+    // Drop anything past the offset, e.g. from a write cut short
+    file.set_len(upload.offset).await.map_err(write_error)?;
+
+    let mut received = upload.offset;
+    let mut result = Ok(());
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                info!("Upload {} interrupted at {}: {}", id, received, e);
+                break;
+            }
+        };
+        if received + chunk.len() as u64 > upload.size {
+            result = Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Data exceeds the upload size",
+            ));
+            break;
+        }
+        if let Err(e) = file.write_all(&chunk).await {
+            result = Err(write_error(e));
+            break;
+        }
+        received += chunk.len() as u64;
+    }
+    file.flush().await.map_err(write_error)?;
+    drop(file);
+
+    // Fail early on anything that isn't a game, once its header is in
+    if upload.offset < MAGIC_LEN as u64 && (received >= MAGIC_LEN as u64 || received == upload.size)
+    {
+        let mut header = Vec::with_capacity(MAGIC_LEN);
+        let read = match tokio::fs::File::open(&part).await {
+            Ok(file) => file.take(MAGIC_LEN as u64).read_to_end(&mut header).await,
+            Err(e) => Err(e),
+        };
+        read.map_err(write_error)?;
+        if !has_magic(&header) {
+            return Err(reject(&state, &id, "Not an NSP or XCI file").await);
+        }
+    }
+
+    let upload = Upload {
+        offset: received,
+        ..upload
+    };
+    if let Err(e) = state.uploads.lock().await.set_offset(&id, received).await {
+        warn!("Failed to save upload {}: {}", id, e);
+    }
+    send_event(
+        &state,
+        serde_json::json!({ "type": "upload", "status": "progress", "upload": upload }),
+    );
+    result?;
+
+    if received < upload.size {
+        return Ok((
+            StatusCode::NO_CONTENT,
+            [(UPLOAD_OFFSET, received.to_string())],
+        )
+            .into_response());
+    }
+    complete(&state, upload, part).await
+}
+
+/// Validates a fully received file, moves it into the library and indexes
+/// it right away.
+async fn complete(
+    state: &AppState,
+    upload: Upload,
+    part: PathBuf,
+) -> Result<Response, UploadError> {
+    let check = part.clone();
+    if !matches!(
+        tokio::task::spawn_blocking(move || inspect(&check)).await,
+        Ok(Ok(_))
+    ) {
+        return Err(reject(state, &upload.id, "Not a valid NSP or XCI file").await);
+    }
+
+    let games_dir = &state.settings.games_dir;
+    let target = games_dir.join(&upload.relative_path);
+    // The upload is kept, so it can be completed once the path is free or
+    // cancelled. Nothing else can move a file there until this is done.
+    let _ops = state.file_ops.lock().await;
+    if target.exists() {
+        return Err((
+            StatusCode::CONFLICT,
+            "A file already exists at the target path",
+        ));
+    }
+    let _held = state.watch_suppression.hold(vec![target.clone()]);
+    if let Err(e) = move_file(&part, &target).await {
+        error!("Failed to move upload {} to {:?}: {}", upload.id, target, e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to move upload"));
+    }
+    if let Err(e) = state.uploads.lock().await.remove(&upload.id).await {
+        warn!("Failed to remove upload {}: {}", upload.id, e);
+    }
+    info!("Upload of {} complete", upload.relative_path);
+
+    let game = discover(&target, games_dir).ok_or((
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Uploaded file is not a game",
+    ))?;
+    let game = with_user_data(state, upsert_game(state, game)).await;
+    send_event(
+        state,
+        serde_json::json!({ "type": "scan", "status": "update", "game": game }),
+    );
+    send_event(
+        state,
+        serde_json::json!({ "type": "upload", "status": "complete", "id": upload.id, "game": game }),
+    );
+    Ok((StatusCode::CREATED, Json(game)).into_response())
+}
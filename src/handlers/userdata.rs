@@ -0,0 +1,104 @@
+use crate::overrides::is_title_id;
+use crate::state::AppState;
+use crate::userdata::{MAX_NOTE_LEN, UserData};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use std::collections::BTreeMap;
+use tracing::error;
+
+type UserDataError = (StatusCode, &'static str);
+
+/// The base game of `title_id`, which user data is kept under.
+fn title_key(state: &AppState, title_id: &str) -> Result<String, UserDataError> {
+    let title_id = title_id.trim();
+    if !is_title_id(title_id) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid title ID"));
+    }
+    Ok(state
+        .metadata
+        .snapshot()
+        .get_base_title_id(title_id)
+        .unwrap_or_else(|| title_id.to_uppercase()))
+}
+
+fn save_error(e: std::io::Error) -> UserDataError {
+    error!("Failed to save user data: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Failed to save user data",
+    )
+}
+
+pub async fn list_user_data(State(state): State<AppState>) -> Json<BTreeMap<String, UserData>> {
+    Json(state.user_library.lock().await.titles().clone())
+}
+
+pub async fn get_user_data(
+    Path(title_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<UserData>, UserDataError> {
+    let key = title_key(&state, &title_id)?;
+    state
+        .user_library
+        .lock()
+        .await
+        .get(&key)
+        .cloned()
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "No user data for this title"))
+}
+
+/// Replaces the user data of a title. Updates and DLC resolve to their
+/// base game.
+pub async fn put_user_data(
+    Path(title_id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<UserData>,
+) -> Result<Json<UserData>, UserDataError> {
+    let key = title_key(&state, &title_id)?;
+    if data.note.as_ref().is_some_and(|n| n.len() > MAX_NOTE_LEN) {
+        return Err((StatusCode::BAD_REQUEST, "Note is too long"));
+    }
+    let mut library = state.user_library.lock().await;
+    library.set(&key, data).await.map(Json).map_err(save_error)
+}
+
+pub async fn delete_user_data(
+    Path(title_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, UserDataError> {
+    let key = title_key(&state, &title_id)?;
+    match state.user_library.lock().await.remove(&key).await {
+        Ok(Some(_)) => Ok(StatusCode::NO_CONTENT),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "No user data for this title")),
+        Err(e) => Err(save_error(e)),
+    }
+}
+
+/// Collection names with the title IDs in each.
+pub async fn list_collections(
+    State(state): State<AppState>,
+) -> Json<BTreeMap<String, Vec<String>>> {
+    Json(state.user_library.lock().await.collections())
+}
+
+/// Takes every title out of a collection.
+pub async fn delete_collection(
+    Path(name): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, UserDataError> {
+    match state
+        .user_library
+        .lock()
+        .await
+        .remove_collection(&name)
+        .await
+    {
+        Ok(0) => Err((StatusCode::NOT_FOUND, "Collection not found")),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(save_error(e)),
+    }
+}
@@ -0,0 +1,350 @@
+use crate::cleanup::move_file;
+use crate::container::inspect;
+use crate::downloads::now;
+use crate::jsonfile;
+use crate::library::{upsert_game, with_user_data};
+use crate::organizer::{Template, remove_empty_parents, with_suffix};
+use crate::scanner::{GAME_EXTENSIONS, discover, enrich};
+use crate::state::AppState;
+use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, warn};
+use walkdir::WalkDir;
+
+/// How many ingested files are remembered.
+const LOG_LIMIT: usize = 1000;
+/// How often files waiting in the inbox are checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Files that failed go to this folder of the inbox.
+pub const REJECTED_DIR: &str = "rejected";
+
+/// What happened to a file dropped into the inbox.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct IngestEntry {
+    pub id: String,
+    /// Relative to the inbox.
+    pub source: String,
+    pub size: u64,
+    pub ingested_at: u64,
+    /// `ingested` or `rejected`.
+    pub status: String,
+    /// Why a file was rejected: `unsupported_extension`, `invalid_container`,
+    /// `unidentified`, `invalid_path`, `duplicate` or `move_failed`.
+    pub reason: Option<String>,
+    pub title_id: Option<String>,
+    /// Where the file went: relative to `games_dir`, or to the inbox for a
+    /// rejected one.
+    pub target: Option<String>,
+}
+
+/// The most recent ingested files, persisted in `data_dir/ingest_log.json`.
+pub struct IngestLog {
+    path: PathBuf,
+    entries: Vec<IngestEntry>,
+}
+
+impl IngestLog {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join("ingest_log.json"),
+            entries: Vec::new(),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        if let Some(entries) = jsonfile::load(&self.path).await {
+            self.entries = entries;
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        jsonfile::save(&self.path, &self.entries).await
+    }
+
+    /// Oldest first.
+    pub fn entries(&self) -> &[IngestEntry] {
+        &self.entries
+    }
+
+    pub async fn record(&mut self, entry: IngestEntry) {
+        self.entries.push(entry);
+        if self.entries.len() > LOG_LIMIT {
+            let excess = self.entries.len() - LOG_LIMIT;
+            self.entries.drain(..excess);
+        }
+        if let Err(e) = self.save().await {
+            warn!("Failed to save ingest log: {}", e);
+        }
+    }
+}
+
+/// Waits for files to stop growing: a file is ready once its size and
+/// modification time stayed the same for the settle time.
+#[derive(Default)]
+pub struct Settle {
+    files: HashMap<PathBuf, (u64, Option<SystemTime>, Instant)>,
+}
+
+impl Settle {
+    /// Records the current state of a file seen in the inbox.
+    pub fn observe(
+        &mut self,
+        path: PathBuf,
+        size: u64,
+        modified: Option<SystemTime>,
+        now: Instant,
+    ) {
+        let entry = self.files.entry(path).or_insert((size, modified, now));
+        if (entry.0, entry.1) != (size, modified) {
+            *entry = (size, modified, now);
+        }
+    }
+
+    pub fn forget(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.keys().cloned().collect()
+    }
+
+    /// Files unchanged for `settle`, which are no longer tracked.
+    pub fn ready(&mut self, settle: Duration, now: Instant) -> Vec<PathBuf> {
+        let ready: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|(_, (_, _, since))| now.duration_since(*since) >= settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in &ready {
+            self.files.remove(path);
+        }
+        ready
+    }
+}
+
+/// Whether the inbox should look at `path`: not a rejected file and not a
+/// hidden one, like `.DS_Store` or a partial copy.
+fn is_candidate(inbox: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(inbox) else {
+        return false;
+    };
+    !relative.starts_with(REJECTED_DIR)
+        && !relative
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// Moves `path` into the rejected folder, next to a file explaining why.
+async fn reject(inbox: &Path, path: &Path, reason: &str) -> Option<String> {
+    let rejected = inbox.join(REJECTED_DIR);
+    let name = path.file_name()?.to_string_lossy().to_string();
+    let mut target = name.clone();
+    let mut n = 1;
+    while rejected.join(&target).exists() {
+        n += 1;
+        target = with_suffix(&name, n);
+    }
+    if let Err(e) = move_file(path, &rejected.join(&target)).await {
+        error!("Failed to move {:?} to the rejected folder: {}", path, e);
+        return None;
+    }
+    let note = rejected.join(format!("{}.reason.txt", target));
+    if let Err(e) = tokio::fs::write(&note, format!("{}\n", reason)).await {
+        warn!("Failed to write {:?}: {}", note, e);
+    }
+    Some(format!("{}/{}", REJECTED_DIR, target))
+}
+
+/// Validates, identifies and files away one settled file of the inbox.
+pub async fn ingest(state: &AppState, inbox: &Path, path: &Path) -> IngestEntry {
+    let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let mut entry = IngestEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        source: path
+            .strip_prefix(inbox)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string(),
+        size,
+        ingested_at: now(),
+        status: "ingested".to_string(),
+        reason: None,
+        title_id: None,
+        target: None,
+    };
+
+    let result = place(state, inbox, path, &mut entry).await;
+    if let Err(reason) = result {
+        info!("Rejected {} from the inbox: {}", entry.source, reason);
+        entry.status = "rejected".to_string();
+        entry.reason = Some(reason.to_string());
+        entry.target = reject(inbox, path, reason).await;
+    }
+    remove_empty_parents(path, inbox).await;
+
+    state.ingest_log.lock().await.record(entry.clone()).await;
+    let _ = state.tx.send(
+        serde_json::json!({ "type": "ingest", "status": entry.status, "entry": entry }).to_string(),
+    );
+    entry
+}
+
+/// Moves a file into the library, returning why it can't be.
+async fn place(
+    state: &AppState,
+    inbox: &Path,
+    path: &Path,
+    entry: &mut IngestEntry,
+) -> Result<(), &'static str> {
+    let supported = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| GAME_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+    if !supported {
+        return Err("unsupported_extension");
+    }
+    let check = path.to_path_buf();
+    if !matches!(
+        tokio::task::spawn_blocking(move || inspect(&check)).await,
+        Ok(Ok(_))
+    ) {
+        return Err("invalid_container");
+    }
+
+    let mut game = discover(path, inbox).ok_or("unsupported_extension")?;
+    let meta = state.metadata.snapshot();
+    enrich(&mut game, &meta);
+    entry.title_id = game.title_id.clone();
+    if game.title_id.is_none() {
+        return Err("unidentified");
+    }
+    let games_dir = &state.settings.games_dir;
+    let template =
+        Template::parse(&state.settings.organize_template).map_err(|_| "invalid_path")?;
+    let relative = template
+        .target(&game, &meta, games_dir)
+        .map_err(|reason| match reason {
+            "no_title_id" => "unidentified",
+            other => other,
+        })?;
+
+    let target = games_dir.join(&relative);
+    let _ops = state.file_ops.lock().await;
+    if target.exists() {
+        return Err("duplicate");
+    }
+    let _held = state.watch_suppression.hold(vec![target.clone()]);
+    if let Err(e) = move_file(path, &target).await {
+        error!("Failed to move {:?} into the library: {}", path, e);
+        return Err("move_failed");
+    }
+    info!("Ingested {} as {}", entry.source, relative);
+    entry.target = Some(relative);
+
+    if let Some(game) = discover(&target, games_dir) {
+        let game = with_user_data(state, upsert_game(state, game)).await;
+        let _ = state.tx.send(
+            serde_json::json!({ "type": "scan", "status": "update", "game": game }).to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Watches the inbox and ingests files once they stop growing. Files
+/// already there at startup are picked up too.
+pub fn start(state: AppState, inbox: PathBuf) {
+    if let Err(e) = std::fs::create_dir_all(inbox.join(REJECTED_DIR)) {
+        error!("Failed to create inbox {:?}: {}", inbox, e);
+        return;
+    }
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<PathBuf>();
+
+    let watched = inbox.clone();
+    let events = tx.clone();
+    tokio::task::spawn_blocking(move || {
+        let (std_tx, std_rx) = std::sync::mpsc::channel();
+        let mut watcher = match RecommendedWatcher::new(std_tx, Config::default()) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                error!("Failed to create inbox watcher: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = watcher.watch(&watched, RecursiveMode::Recursive) {
+            error!("Failed to watch inbox {:?}: {}", watched, e);
+            return;
+        }
+        info!("Inbox watcher started for: {:?}", watched);
+        for event in std_rx.into_iter().flatten() {
+            for path in event.paths {
+                let _ = events.send(path);
+            }
+        }
+    });
+    for entry in WalkDir::new(&inbox).into_iter().filter_map(|e| e.ok()) {
+        let _ = tx.send(entry.into_path());
+    }
+
+    let settle_time = Duration::from_secs(state.settings.inbox_settle_secs);
+    tokio::spawn(async move {
+        let mut settle = Settle::default();
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let mut seen = settle.paths();
+            while let Ok(path) = rx.try_recv() {
+                seen.push(path);
+            }
+            let now = Instant::now();
+            for path in seen {
+                if !is_candidate(&inbox, &path) {
+                    continue;
+                }
+                match std::fs::metadata(&path) {
+                    Ok(meta) if meta.is_file() => {
+                        settle.observe(path, meta.len(), meta.modified().ok(), now)
+                    }
+                    _ => settle.forget(&path),
+                }
+            }
+            for path in settle.ready(settle_time, now) {
+                ingest(&state, &inbox, &path).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settle_waits_for_files_to_stop_growing() {
+        let mut settle = Settle::default();
+        let path = PathBuf::from("/inbox/Game.nsp");
+        let start = Instant::now();
+        let secs = Duration::from_secs;
+
+        settle.observe(path.clone(), 10, None, start);
+        settle.observe(path.clone(), 20, None, start + secs(5));
+        assert!(settle.ready(secs(10), start + secs(10)).is_empty());
+        settle.observe(path.clone(), 20, None, start + secs(12));
+        assert_eq!(settle.ready(secs(10), start + secs(15)), vec![path]);
+        assert!(settle.paths().is_empty());
+    }
+
+    #[test]
+    fn test_is_candidate() {
+        let inbox = Path::new("/inbox");
+        assert!(is_candidate(inbox, Path::new("/inbox/Game.nsp")));
+        assert!(is_candidate(inbox, Path::new("/inbox/Folder/Game.nsp")));
+        assert!(!is_candidate(inbox, Path::new("/inbox/rejected/Game.nsp")));
+        assert!(!is_candidate(inbox, Path::new("/inbox/.Game.nsp.part")));
+        assert!(!is_candidate(inbox, Path::new("/games/Game.nsp")));
+    }
+}
@@ -0,0 +1,462 @@
+use crate::dat::reverify_library;
+use crate::downloads::now;
+use crate::jsonfile;
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock, RwLockReadGuard};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// How often the library is checked for files to hash or verify.
+const PASS_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// During a pass, records are saved after this many changes or this long
+/// after the last save, whichever comes first, and once at the end.
+const SAVE_EVERY: usize = 50;
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A content hash found on re-verify that differs from the stored one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Corruption {
+    pub sha256: String,
+    /// When a re-verify last found the file corrupt.
+    pub detected_at: u64,
+}
+
+/// The hash of a file as it was when hashed. Size and modification time
+/// tell a file that was replaced from one that rotted.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HashRecord {
+    pub size: u64,
+    /// Milliseconds since the Unix epoch.
+    pub modified: u64,
+    /// Hex encoded.
+    pub sha256: String,
+    pub hashed_at: u64,
+    /// When the content was last hashed again and matched.
+    pub verified_at: Option<u64>,
+    /// Set when the content changed but size and modification time did not.
+    pub corrupt: Option<Corruption>,
+}
+
+/// What a pass does with a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// New or changed file: hash it and store the result.
+    Hash,
+    /// Hash it again and compare.
+    Verify,
+}
+
+/// Hashes keyed by path relative to `games_dir`, persisted in
+/// `data_dir/integrity.json`. Records can be read without waiting on a
+/// save, so games can be checked against them as they are added.
+pub struct IntegrityStore {
+    path: PathBuf,
+    records: RwLock<BTreeMap<String, HashRecord>>,
+    pending: Mutex<Pending>,
+    save_lock: tokio::sync::Mutex<()>,
+}
+
+/// Changes not written to disk yet.
+struct Pending {
+    unsaved: usize,
+    last_save: Instant,
+}
+
+impl IntegrityStore {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join("integrity.json"),
+            records: RwLock::new(BTreeMap::new()),
+            pending: Mutex::new(Pending {
+                unsaved: 0,
+                last_save: Instant::now(),
+            }),
+            save_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        if let Some(records) = jsonfile::load(&self.path).await {
+            *self.records.get_mut().unwrap() = records;
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        let _guard = self.save_lock.lock().await;
+        let json = serde_json::to_vec(&*self.records.read().unwrap())?;
+        jsonfile::write(&self.path, json).await
+    }
+
+    pub fn records(&self) -> RwLockReadGuard<'_, BTreeMap<String, HashRecord>> {
+        self.records.read().unwrap()
+    }
+
+    pub fn get(&self, relative_path: &str) -> Option<HashRecord> {
+        self.records.read().unwrap().get(relative_path).cloned()
+    }
+
+    /// The stored record of a file, if it still applies to its current
+    /// size and modification time, whether or not it was found corrupt.
+    pub fn stamped(&self, relative_path: &str, size: u64, modified: u64) -> Option<HashRecord> {
+        self.get(relative_path)
+            .filter(|r| r.size == size && r.modified == modified)
+    }
+
+    /// The stored hash of a file, if it still applies to its current size
+    /// and modification time and the content was not found corrupt.
+    pub fn current(&self, relative_path: &str, size: u64, modified: u64) -> Option<HashRecord> {
+        self.stamped(relative_path, size, modified)
+            .filter(|r| r.corrupt.is_none())
+    }
+
+    /// Counts `changes` towards the next save. Returns whether one is due.
+    fn changed(&self, changes: usize) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        pending.unsaved += changes;
+        pending.unsaved >= SAVE_EVERY || pending.last_save.elapsed() >= SAVE_INTERVAL
+    }
+
+    /// Stores a record, saving once enough changes piled up. Call
+    /// [`flush`](Self::flush) when done.
+    pub async fn insert(&self, relative_path: String, record: HashRecord) {
+        self.records.write().unwrap().insert(relative_path, record);
+        if self.changed(1) {
+            self.flush().await;
+        }
+    }
+
+    /// Saves the records if anything changed since the last save.
+    pub async fn flush(&self) {
+        let unsaved = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.last_save = Instant::now();
+            std::mem::take(&mut pending.unsaved)
+        };
+        if unsaved == 0 {
+            return;
+        }
+        if let Err(e) = self.save().await {
+            warn!("Failed to save integrity records: {}", e);
+            self.pending.lock().unwrap().unsaved += unsaved;
+        }
+    }
+
+    /// Moves the record of a file along with it, so a rename or move
+    /// doesn't mean hashing it again.
+    pub async fn rename(&self, from: &str, to: &str) {
+        let moved = {
+            let mut records = self.records.write().unwrap();
+            match records.remove(from) {
+                Some(record) => {
+                    records.insert(to.to_string(), record);
+                    true
+                }
+                None => false,
+            }
+        };
+        if moved {
+            self.changed(1);
+            self.flush().await;
+        }
+    }
+
+    /// Drops the records of files that left the library and saves what
+    /// is still pending.
+    pub async fn retain(&self, paths: &HashSet<String>) {
+        let removed = {
+            let mut records = self.records.write().unwrap();
+            let before = records.len();
+            records.retain(|path, _| paths.contains(path));
+            before - records.len()
+        };
+        self.changed(removed);
+        self.flush().await;
+    }
+}
+
+/// Size and modification time in milliseconds.
+pub fn stamp(meta: &std::fs::Metadata) -> (u64, u64) {
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    (meta.len(), modified)
+}
+
+pub fn file_stamp(path: &Path) -> std::io::Result<(u64, u64)> {
+    std::fs::metadata(path).map(|meta| stamp(&meta))
+}
+
+/// A `Repr-Digest` header value (RFC 9530) for a hex encoded SHA-256.
+pub fn repr_digest(sha256: &str) -> Option<String> {
+    use base64::Engine;
+    let bytes = (0..sha256.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(sha256.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    (bytes.len() == 32).then(|| {
+        format!(
+            "sha-256=:{}:",
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        )
+    })
+}
+
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    use sha2::{Digest, Sha256};
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Decides what to do with a file given its stored record. Files are
+/// verified again `verify_days` after they were last hashed or verified,
+/// or found corrupt; 0 turns verification off.
+pub fn action(
+    record: Option<&HashRecord>,
+    size: u64,
+    modified: u64,
+    now: u64,
+    verify_days: u64,
+) -> Option<Action> {
+    let Some(record) = record else {
+        return Some(Action::Hash);
+    };
+    if record.size != size || record.modified != modified {
+        return Some(Action::Hash);
+    }
+    let last = record
+        .corrupt
+        .as_ref()
+        .map(|c| c.detected_at)
+        .or(record.verified_at)
+        .unwrap_or(record.hashed_at);
+    (verify_days > 0 && now.saturating_sub(last) >= verify_days * 24 * 3600)
+        .then_some(Action::Verify)
+}
+
+#[derive(Debug, Serialize, Default, Clone, PartialEq)]
+pub struct PassSummary {
+    pub hashed: usize,
+    pub verified: usize,
+    pub corrupt: Vec<String>,
+}
+
+/// Hashes new and changed library files and verifies the ones that are
+/// due. One file is read at a time.
+pub async fn run_pass(state: &AppState) -> PassSummary {
+    let games_dir = state.settings.games_dir.clone();
+    let verify_days = state.settings.integrity_verify_days;
+    let paths: Vec<String> = {
+        let games = state.games.lock().unwrap();
+        games.iter().map(|g| g.relative_path.clone()).collect()
+    };
+    let mut summary = PassSummary::default();
+
+    for relative_path in &paths {
+        let path = games_dir.join(relative_path);
+        let Ok((size, modified)) = file_stamp(&path) else {
+            continue;
+        };
+        let record = state.integrity.get(relative_path);
+        let Some(action) = action(record.as_ref(), size, modified, now(), verify_days) else {
+            continue;
+        };
+
+        let hashed_path = path.clone();
+        let sha256 = match tokio::task::spawn_blocking(move || hash_file(&hashed_path)).await {
+            Ok(Ok(sha256)) => sha256,
+            Ok(Err(e)) => {
+                warn!("Failed to hash {:?}: {}", path, e);
+                continue;
+            }
+            Err(_) => continue,
+        };
+        // The file may have been replaced while it was read
+        if file_stamp(&path).ok() != Some((size, modified)) {
+            continue;
+        }
+
+        let record = match (action, record) {
+            (Action::Verify, Some(mut record)) => {
+                if sha256 == record.sha256 {
+                    record.verified_at = Some(now());
+                    record.corrupt = None;
+                } else {
+                    warn!(
+                        "Content of {} changed without a new size or mtime",
+                        relative_path
+                    );
+                    if record.corrupt.as_ref().map(|c| &c.sha256) != Some(&sha256) {
+                        let _ = state.tx.send(
+                            serde_json::json!({
+                                "type": "integrity",
+                                "status": "corrupt",
+                                "path": relative_path,
+                            })
+                            .to_string(),
+                        );
+                    }
+                    record.corrupt = Some(Corruption {
+                        sha256,
+                        detected_at: now(),
+                    });
+                    summary.corrupt.push(relative_path.clone());
+                }
+                summary.verified += 1;
+                record
+            }
+            _ => {
+                summary.hashed += 1;
+                HashRecord {
+                    size,
+                    modified,
+                    sha256,
+                    hashed_at: now(),
+                    verified_at: None,
+                    corrupt: None,
+                }
+            }
+        };
+        state.integrity.insert(relative_path.clone(), record).await;
+    }
+
+    state.integrity.flush().await;
+    reverify_library(state);
+    summary
+}
+
+/// Runs a pass every hour. Started once the initial scan is complete, the
+/// only time the library is known to be whole, so records of files that
+/// are gone are dropped first.
+pub fn start(state: AppState) {
+    tokio::spawn(async move {
+        let paths: HashSet<String> = {
+            let games = state.games.lock().unwrap();
+            games.iter().map(|g| g.relative_path.clone()).collect()
+        };
+        state.integrity.retain(&paths).await;
+
+        let mut interval = tokio::time::interval(PASS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let summary = run_pass(&state).await;
+            if summary.hashed > 0 || summary.verified > 0 {
+                info!(
+                    "Integrity pass: hashed {}, verified {}, corrupt {}",
+                    summary.hashed,
+                    summary.verified,
+                    summary.corrupt.len()
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn record(size: u64, modified: u64, hashed_at: u64) -> HashRecord {
+        HashRecord {
+            size,
+            modified,
+            sha256: String::new(),
+            hashed_at,
+            verified_at: None,
+            corrupt: None,
+        }
+    }
+
+    #[test]
+    fn test_action() {
+        let day = 24 * 3600;
+        assert_eq!(action(None, 1, 1, 0, 30), Some(Action::Hash));
+        let stored = record(1, 1, 0);
+        assert_eq!(action(Some(&stored), 2, 1, 0, 30), Some(Action::Hash));
+        assert_eq!(action(Some(&stored), 1, 2, 0, 30), Some(Action::Hash));
+        assert_eq!(action(Some(&stored), 1, 1, 29 * day, 30), None);
+        assert_eq!(
+            action(Some(&stored), 1, 1, 30 * day, 30),
+            Some(Action::Verify)
+        );
+        assert_eq!(action(Some(&stored), 1, 1, 30 * day, 0), None);
+        let verified = HashRecord {
+            verified_at: Some(20 * day),
+            ..stored
+        };
+        assert_eq!(action(Some(&verified), 1, 1, 30 * day, 30), None);
+        // Corrupt files wait as long before being read again
+        let corrupt = HashRecord {
+            corrupt: Some(Corruption {
+                sha256: String::new(),
+                detected_at: 25 * day,
+            }),
+            ..verified
+        };
+        assert_eq!(action(Some(&corrupt), 1, 1, 30 * day, 30), None);
+        assert_eq!(
+            action(Some(&corrupt), 1, 1, 55 * day, 30),
+            Some(Action::Verify)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hash_and_store() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("Game.nsp");
+        std::fs::write(&path, "abc").unwrap();
+        assert_eq!(
+            hash_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let (size, modified) = file_stamp(&path).unwrap();
+        let store = IntegrityStore::new(tmp.path());
+        store
+            .insert("Game.nsp".to_string(), record(size, modified, 0))
+            .await;
+        // Saved in batches
+        let mut reloaded = IntegrityStore::new(tmp.path());
+        reloaded.load().await;
+        assert!(reloaded.records().is_empty());
+        store.flush().await;
+        let mut reloaded = IntegrityStore::new(tmp.path());
+        reloaded.load().await;
+        assert!(reloaded.current("Game.nsp", size, modified).is_some());
+        assert!(reloaded.current("Game.nsp", size + 1, modified).is_none());
+
+        assert_eq!(
+            repr_digest(&hash_file(&path).unwrap()).as_deref(),
+            Some("sha-256=:ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=:")
+        );
+        assert_eq!(repr_digest("abc"), None);
+
+        reloaded.rename("Game.nsp", "Renamed.nsp").await;
+        assert!(reloaded.current("Renamed.nsp", size, modified).is_some());
+        assert!(reloaded.current("Game.nsp", size, modified).is_none());
+
+        reloaded.retain(&HashSet::new()).await;
+        assert!(reloaded.records().is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+use tracing::warn;
+
+/// Reads the state a store persisted in `path`. `None` when there is none
+/// yet, or when the file can't be read or parsed, which is logged.
+pub async fn load<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    if !path.exists() {
+        return None;
+    }
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => match serde_json::from_str(&content) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!("Failed to parse {:?}: {}", path, e);
+                None
+            }
+        },
+        Err(e) => {
+            warn!("Failed to read {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Persists `value` in `path` through a temporary file, so a crash never
+/// leaves a half-written file behind.
+pub async fn save<T: Serialize + ?Sized>(path: &Path, value: &T) -> std::io::Result<()> {
+    write(path, serde_json::to_vec(value)?).await
+}
+
+/// Like [`save`], indented for files people are expected to read or edit.
+pub async fn save_pretty<T: Serialize + ?Sized>(path: &Path, value: &T) -> std::io::Result<()> {
+    write(path, serde_json::to_vec_pretty(value)?).await
+}
+
+/// Like [`save`], for stores that have to serialize under a lock and
+/// release it before writing.
+pub async fn write(path: &Path, json: Vec<u8>) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, json).await?;
+    tokio::fs::rename(&tmp, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("nested").join("store.json");
+        assert_eq!(load::<Vec<u32>>(&path).await, None);
+
+        save(&path, &[1, 2, 3]).await.unwrap();
+        assert_eq!(load::<Vec<u32>>(&path).await, Some(vec![1, 2, 3]));
+        assert!(!path.with_extension("json.tmp").exists());
+
+        std::fs::write(&path, "{ broken").unwrap();
+        assert_eq!(load::<Vec<u32>>(&path).await, None);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::dat::verification;
+use crate::metadata::MetadataProvider;
+use crate::scanner::{Game, enrich};
+use crate::state::AppState;
+use crate::titles::newly_outdated;
+use std::path::Path;
+use tracing::info;
+
+/// Enriches freshly discovered games with the current metadata, checks
+/// them against the imported DATs and appends them to the library.
+pub fn add_games(state: &AppState, mut batch: Vec<Game>) {
+    let dats = state.dats.snapshot();
+    for game in batch.iter_mut() {
+        game.verification = verification(&dats, &state.integrity, game);
+    }
+    let mut games = state.games.lock().unwrap();
+    let provider = state.metadata.snapshot();
+    for game in batch.iter_mut() {
+        enrich(game, &provider);
+    }
+    games.extend(batch);
+}
+
+/// Enriches and checks a discovered game and inserts it, replacing any
+/// entry with the same path. Returns the game as stored.
+pub fn upsert_game(state: &AppState, mut game: Game) -> Game {
+    game.verification = verification(&state.dats.snapshot(), &state.integrity, &game);
+    let mut games = state.games.lock().unwrap();
+    enrich(&mut game, &state.metadata.snapshot());
+    if let Some(existing) = games.iter_mut().find(|g| g.path == game.path) {
+        *existing = game.clone();
+    } else {
+        games.push(game.clone());
+    }
+    drop(games);
+    register_title_ids(state, &game);
+    game
+}
+
+/// Enriches and checks a moved or renamed game and puts it in place of the
+/// entry at `old_path`, under one lock so the file is never missing or
+/// listed twice. Returns the game as stored.
+pub fn replace_game(state: &AppState, old_path: &Path, mut game: Game) -> Game {
+    game.verification = verification(&state.dats.snapshot(), &state.integrity, &game);
+    let mut games = state.games.lock().unwrap();
+    enrich(&mut game, &state.metadata.snapshot());
+    games.retain(|g| g.path != game.path);
+    if let Some(existing) = games.iter_mut().find(|g| g.path == old_path) {
+        *existing = game.clone();
+    } else {
+        games.push(game.clone());
+    }
+    drop(games);
+    register_title_ids(state, &game);
+    game
+}
+
+/// Sets `Game.user_data` on a game that is about to be sent to clients.
+pub async fn with_user_data(state: &AppState, mut game: Game) -> Game {
+    state
+        .user_library
+        .lock()
+        .await
+        .join(std::slice::from_mut(&mut game));
+    game
+}
+
+/// Lets metadata keep the details of a title that is new to the library,
+/// see [`MetadataStore::add_library_ids`](crate::metadata::MetadataStore::add_library_ids).
+fn register_title_ids(state: &AppState, game: &Game) {
+    state.metadata.add_library_ids(
+        [&game.title_id, &game.base_title_id]
+            .into_iter()
+            .flatten()
+            .cloned(),
+    );
+}
+
+/// Re-applies the current metadata snapshot to every game already in
+/// `AppState.games`, without touching the filesystem. Emits a single
+/// `library` event when anything changed and returns the number of games
+/// that did.
+pub fn enrich_library(state: &AppState) -> usize {
+    let mut games = state.games.lock().unwrap();
+    // Read the snapshot while holding the games lock: anything inserted
+    // concurrently is either enriched with this snapshot or a newer one.
+    let provider = state.metadata.snapshot();
+
+    let mut changed = 0;
+    for game in games.iter_mut() {
+        let before = game.clone();
+        enrich(game, &provider);
+        if *game != before {
+            changed += 1;
+        }
+    }
+    let total = games.len();
+    drop(games);
+
+    if changed > 0 {
+        info!(
+            "Metadata changed, re-enriched {} of {} games.",
+            changed, total
+        );
+        let _ = state.tx.send(
+            serde_json::json!({
+                "type": "library",
+                "status": "updated",
+                "count": changed
+            })
+            .to_string(),
+        );
+    }
+    changed
+}
+
+/// Compares the current metadata with the snapshot from before a sync and
+/// emits an `updates_available` event listing owned titles that gained a
+/// newer version.
+pub fn notify_new_versions(state: &AppState, before: &MetadataProvider) {
+    let after = state.metadata.snapshot();
+    let games = state.games.lock().unwrap().clone();
+    let titles = newly_outdated(&games, before, &after);
+    if titles.is_empty() {
+        return;
+    }
+    info!("New updates available for {} owned titles.", titles.len());
+    let _ = state.tx.send(
+        serde_json::json!({
+            "type": "updates_available",
+            "titles": titles
+        })
+        .to_string(),
+    );
+}
@@ -1,16 +1,37 @@
+mod catalog;
+mod cleanup;
 mod config;
+mod container;
+mod dat;
 mod downloads;
+mod export;
+mod fileops;
 mod handlers;
+mod inbox;
+mod integrity;
+mod jsonfile;
+mod library;
+mod matcher;
 mod metadata;
+mod organizer;
+mod overrides;
+mod query;
 mod scanner;
+mod sources;
 mod state;
+mod stats;
 mod tasks;
 mod tinfoil;
+mod titledb;
+mod titles;
+mod uploads;
+mod userdata;
 mod webdav;
 
 use axum::{
     Router,
-    routing::{any, get},
+    extract::DefaultBodyLimit,
+    routing::{any, delete, get, post, put},
 };
 use local_ip_address::local_ip;
 use std::collections::HashMap;
@@ -21,14 +42,35 @@ use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
 use tracing::{Level, info};
 
 use crate::config::Settings;
-use crate::handlers::{api, dbi, files, tinfoil as tinfoil_h, web};
+use crate::handlers::{
+    api, cleanup as cleanup_h, dats, dbi, files, manage, matches, organize,
+    overrides as overrides_h, tinfoil as tinfoil_h, uploads as uploads_h, userdata as userdata_h,
+    web,
+};
 use crate::state::AppState;
 
+/// DAT files of whole platforms run to tens of megabytes.
+const MAX_DAT_SIZE: usize = 64 * 1024 * 1024;
+
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
     let settings = Settings::new().expect("Failed to load configuration");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export") {
+        // stdout may carry the export, so logs go to stderr
+        tracing_subscriber::fmt()
+            .with_env_filter(&settings.log_level)
+            .with_writer(std::io::stderr)
+            .init();
+        if let Err(e) = export::run_cli(settings, &args[1..]).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     tracing_subscriber::fmt()
         .with_env_filter(&settings.log_level)
         .init();
@@ -49,32 +91,63 @@ async fn main() {
     let downloads = Arc::new(Mutex::new(HashMap::new()));
     let (tx, _) = broadcast::channel(100);
 
-    let metadata = Arc::new(tokio::sync::Mutex::new(
-        crate::metadata::MetadataProvider::new(
-            settings.data_dir.clone(),
-            settings.metadata_region.clone(),
-            settings.metadata_language.clone(),
-        )
-        .await,
-    ));
+    let metadata = Arc::new(
+        crate::metadata::MetadataStore::new(settings.data_dir.clone(), settings.locales())
+            .with_library_only_details(settings.metadata_library_only_details)
+            .with_match_threshold(settings.title_match_threshold)
+            .with_sources(settings.merge_rules())
+            .with_remote_url(settings.metadata_remote_url.clone()),
+    );
 
     let dav_handler = webdav::create_dav_handler(&settings);
 
+    let mut quarantine = cleanup::Quarantine::new(&settings.data_dir);
+    quarantine.load().await;
+    let mut trash = cleanup::Quarantine::in_dir(settings.data_dir.join("trash"));
+    trash.load().await;
+    let mut download_history = downloads::DownloadHistory::new(&settings.data_dir);
+    download_history.load().await;
+    let mut stats_history = stats::StatsHistory::new(&settings.data_dir);
+    stats_history.load().await;
+    let mut uploads = uploads::Uploads::new(&settings.data_dir);
+    uploads.load().await;
+    let mut organize_log = organizer::OrganizeLog::new(&settings.data_dir);
+    organize_log.load().await;
+    let mut ingest_log = inbox::IngestLog::new(&settings.data_dir);
+    ingest_log.load().await;
+    let mut integrity = integrity::IntegrityStore::new(&settings.data_dir);
+    integrity.load().await;
+    let mut dats = dat::DatStore::new(&settings.data_dir);
+    dats.load().await;
+    let mut user_library = userdata::UserLibrary::new(&settings.data_dir);
+    user_library.load().await;
+
     let state = AppState {
         games,
         settings: settings.clone(),
         host_url: host_url.clone(),
         downloads,
+        download_history: Arc::new(tokio::sync::Mutex::new(download_history)),
         tx,
         metadata: metadata.clone(),
         dav_handler,
+        quarantine: Arc::new(tokio::sync::Mutex::new(quarantine)),
+        trash: Arc::new(tokio::sync::Mutex::new(trash)),
+        watch_suppression: Arc::default(),
+        file_ops: Arc::default(),
+        uploads: Arc::new(tokio::sync::Mutex::new(uploads)),
+        organize_log: Arc::new(tokio::sync::Mutex::new(organize_log)),
+        ingest_log: Arc::new(tokio::sync::Mutex::new(ingest_log)),
+        integrity: Arc::new(integrity),
+        dats: Arc::new(dats),
+        user_library: Arc::new(tokio::sync::Mutex::new(user_library)),
+        stats_history: Arc::new(tokio::sync::Mutex::new(stats_history)),
     };
 
     // Metadata Init
     let metadata_init = metadata.clone();
     tokio::spawn(async move {
-        let mut meta = metadata_init.lock().await;
-        meta.init().await;
+        metadata_init.init().await;
         info!("Metadata initialized and ready.");
     });
 
@@ -96,8 +169,32 @@ async fn main() {
 pub fn create_app(state: AppState) -> Router {
     let main_routes = Router::new()
         .route("/api/games", get(api::list_games))
+        .route("/api/games/{id}", get(api::game_details))
+        .route("/api/games/{id}/download", get(files::download_game))
         .route("/api/info", get(api::server_info))
+        .route("/api/stats", get(api::library_stats))
+        .route("/api/export", get(api::export_library))
         .route("/api/sync", get(api::sync_metadata))
+        .route("/api/metadata/{title_id}", get(api::title_details))
+        .route("/api/catalog/search", get(api::catalog_search))
+        .route("/api/titles", get(api::list_titles))
+        .route("/api/library/outdated", get(api::list_outdated))
+        .route("/api/library/integrity", get(api::library_integrity))
+        .route("/api/library/verification", get(dats::library_verification))
+        .route("/api/dats", get(dats::list_dats))
+        .route("/api/userdata", get(userdata_h::list_user_data))
+        .route("/api/userdata/{title_id}", get(userdata_h::get_user_data))
+        .route("/api/collections", get(userdata_h::list_collections))
+        .route("/api/cleanup", get(cleanup_h::analyze))
+        .route("/api/quarantine", get(cleanup_h::list_quarantine))
+        .route("/api/organize", get(organize::preview))
+        .route("/api/organize/runs", get(organize::list_runs))
+        .route("/api/inbox", get(api::inbox_log))
+        .route("/api/titles/{base_id}", get(api::title_family_details))
+        .route("/api/overrides", get(overrides_h::list_overrides))
+        .route("/api/overrides/{*key}", get(overrides_h::get_override))
+        .route("/api/matches", get(matches::list_matches))
+        .route("/api/matches/{*path}", get(matches::get_match))
         .route("/tinfoil", get(tinfoil_h::tinfoil_index))
         .route("/tinfoil/", get(tinfoil_h::tinfoil_index))
         .route("/tinwoo", get(tinfoil_h::tinfoil_index))
@@ -118,7 +215,60 @@ pub fn create_app(state: AppState) -> Router {
         )
         .layer(CorsLayer::permissive());
 
-    let mut app = Router::new().merge(main_routes);
+    let manage_routes = Router::new()
+        .route("/api/games/{id}", delete(manage::delete_game))
+        .route("/api/games/{id}/rename", post(manage::rename_game))
+        .route("/api/games/{id}/move", post(manage::move_game))
+        .route("/api/trash", get(manage::list_trash))
+        .route("/api/trash/{id}", delete(manage::purge_trash))
+        .route("/api/trash/{id}/restore", post(manage::restore_trash))
+        .route("/api/organize", post(organize::apply))
+        .route("/api/organize/runs/{id}/undo", post(organize::undo))
+        .route("/api/cleanup/quarantine", post(cleanup_h::quarantine_files))
+        .route("/api/quarantine/purge", post(cleanup_h::purge_expired))
+        .route("/api/quarantine/{id}", delete(cleanup_h::purge))
+        .route("/api/quarantine/{id}/restore", post(cleanup_h::restore))
+        .route(
+            "/api/overrides/{*key}",
+            put(overrides_h::put_override).delete(overrides_h::delete_override),
+        )
+        .route(
+            "/api/icons/{*key}",
+            put(overrides_h::put_icon).delete(overrides_h::delete_icon),
+        )
+        .route(
+            "/api/userdata/{title_id}",
+            put(userdata_h::put_user_data).delete(userdata_h::delete_user_data),
+        )
+        .route(
+            "/api/collections/{name}",
+            delete(userdata_h::delete_collection),
+        )
+        .route(
+            "/api/matches/{*path}",
+            put(matches::put_match).delete(matches::delete_match),
+        )
+        .route(
+            "/api/dats",
+            post(dats::import_dat).layer(DefaultBodyLimit::max(MAX_DAT_SIZE)),
+        )
+        .route("/api/dats/{id}", delete(dats::delete_dat))
+        .route(
+            "/api/uploads",
+            get(uploads_h::list_uploads).post(uploads_h::create_upload),
+        )
+        .route(
+            "/api/uploads/{id}",
+            get(uploads_h::upload_status)
+                .put(uploads_h::upload_chunk)
+                .delete(uploads_h::cancel_upload),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            manage::require_credentials,
+        ));
+
+    let mut app = Router::new().merge(main_routes).merge(manage_routes);
 
     if state.settings.webdav_enabled {
         app = app
@@ -171,11 +321,30 @@ async fn webdav_wrapper(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dat::Verification;
     use crate::scanner::Game;
     use axum_test::TestServer;
     use base64::Engine;
     use tempfile::tempdir;
 
+    async fn list_games(server: &TestServer) -> Vec<Game> {
+        let body: serde_json::Value = server.get("/api/games").await.json();
+        serde_json::from_value(body["games"].clone()).unwrap()
+    }
+
+    /// Serves `state` with WebDAV credentials configured, which the file
+    /// management API requires. Returns the `Authorization` header value.
+    fn with_credentials(state: &mut AppState) -> (TestServer, String) {
+        state.settings.webdav_username = Some("admin".to_string());
+        state.settings.webdav_password = Some("password".to_string());
+        let server = TestServer::new(create_app(state.clone())).unwrap();
+        let auth = format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode("admin:password")
+        );
+        (server, auth)
+    }
+
     async fn setup_test_app() -> (TestServer, AppState, tempfile::TempDir) {
         let tmp_dir = tempdir().unwrap();
         let games_dir = tmp_dir.path().join("games");
@@ -199,27 +368,45 @@ mod tests {
             webdav_enabled: true,
             metadata_region: "US".to_string(),
             metadata_language: "en".to_string(),
+            metadata_locales: None,
+            metadata_library_only_details: false,
+            title_match_threshold: 0.9,
+            metadata_sources: "overrides,catalogue,titledb,remote".to_string(),
+            metadata_field_sources: None,
+            metadata_remote_url: None,
+            metadata_remote_interval_hours: 1,
+            cleanup_preferred_formats: "nsp,nsz,xci,xcz".to_string(),
+            quarantine_retention_days: 30,
+            trash_retention_days: 30,
+            organize_template: "{category}/{name} [{title_id}][v{version}].{ext}".to_string(),
+            inbox_dir: None,
+            inbox_settle_secs: 10,
+            integrity_enabled: true,
+            integrity_verify_days: 30,
+            integrity_repr_digest: false,
+            hide_broken_files: false,
             tinfoil_encrypt: false,
         };
 
         let games = Arc::new(Mutex::new(vec![Game {
+            id: crate::scanner::game_id(&games_dir, "Test Game [0100000000010000][v0].nsp"),
             name: "Test Game".to_string(),
             path: games_dir.join("Test Game [0100000000010000][v0].nsp"),
             relative_path: "Test Game [0100000000010000][v0].nsp".to_string(),
             size: 5,
             format: "nsp".to_string(),
             title_id: Some("0100000000010000".to_string()),
+            base_title_id: Some("0100000000010000".to_string()),
             version: Some("v0".to_string()),
             latest_version: None,
             category: "Base".to_string(),
-            publisher: None,
-            image_url: None,
+            ..Default::default()
         }]));
 
         let (tx, _) = broadcast::channel(10);
-        let metadata = Arc::new(tokio::sync::Mutex::new(
-            crate::metadata::MetadataProvider::new(data_dir, "US".to_string(), "en".to_string())
-                .await,
+        let metadata = Arc::new(crate::metadata::MetadataStore::new(
+            data_dir,
+            settings.locales(),
         ));
         let dav_handler = webdav::create_dav_handler(&settings);
 
@@ -231,6 +418,36 @@ mod tests {
             tx,
             metadata,
             dav_handler,
+            quarantine: Arc::new(tokio::sync::Mutex::new(crate::cleanup::Quarantine::new(
+                &tmp_dir.path().join("data"),
+            ))),
+            trash: Arc::new(tokio::sync::Mutex::new(crate::cleanup::Quarantine::in_dir(
+                tmp_dir.path().join("data").join("trash"),
+            ))),
+            watch_suppression: Arc::default(),
+            file_ops: Arc::default(),
+            uploads: Arc::new(tokio::sync::Mutex::new(crate::uploads::Uploads::new(
+                &tmp_dir.path().join("data"),
+            ))),
+            organize_log: Arc::new(tokio::sync::Mutex::new(crate::organizer::OrganizeLog::new(
+                &tmp_dir.path().join("data"),
+            ))),
+            ingest_log: Arc::new(tokio::sync::Mutex::new(crate::inbox::IngestLog::new(
+                &tmp_dir.path().join("data"),
+            ))),
+            integrity: Arc::new(crate::integrity::IntegrityStore::new(
+                &tmp_dir.path().join("data"),
+            )),
+            dats: Arc::new(crate::dat::DatStore::new(&tmp_dir.path().join("data"))),
+            user_library: Arc::new(tokio::sync::Mutex::new(crate::userdata::UserLibrary::new(
+                &tmp_dir.path().join("data"),
+            ))),
+            download_history: Arc::new(tokio::sync::Mutex::new(
+                crate::downloads::DownloadHistory::new(&tmp_dir.path().join("data")),
+            )),
+            stats_history: Arc::new(tokio::sync::Mutex::new(crate::stats::StatsHistory::new(
+                &tmp_dir.path().join("data"),
+            ))),
         };
 
         let app = create_app(state.clone());
@@ -242,11 +459,144 @@ mod tests {
         let (server, _, _tmp) = setup_test_app().await;
         let response = server.get("/api/games").await;
         response.assert_status_ok();
-        let games: Vec<Game> = response.json();
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["total"], 1);
+        let games: Vec<Game> = serde_json::from_value(body["games"].clone()).unwrap();
         assert_eq!(games.len(), 1);
         assert_eq!(games[0].name, "Test Game");
     }
 
+    #[tokio::test]
+    async fn test_game_details() {
+        let (server, _state, _tmp) = setup_test_app().await;
+        let id = list_games(&server).await[0].id.clone();
+
+        let details: serde_json::Value = server.get(&format!("/api/games/{}", id)).await.json();
+        assert_eq!(details["game"]["name"], "Test Game");
+        assert_eq!(details["file"]["size"], 5);
+        assert!(details["file"]["modified"].is_u64());
+        assert!(details["container"].is_null());
+        assert!(details["container_error"].is_string());
+        assert!(
+            details["downloads"]["history"]
+                .as_array()
+                .unwrap()
+                .is_empty()
+        );
+
+        let response = server.get(&format!("/api/games/{}/download", id)).await;
+        response.assert_status_ok();
+        assert_eq!(response.as_bytes().as_ref(), b"dummy");
+
+        // The finished download is recorded in the background
+        let mut history = serde_json::Value::Null;
+        for _ in 0..50 {
+            let details: serde_json::Value = server.get(&format!("/api/games/{}", id)).await.json();
+            history = details["downloads"]["history"].clone();
+            if !history.as_array().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(history[0]["game_id"], id.as_str());
+        assert_eq!(history[0]["completed"], true);
+
+        server
+            .get("/api/games/0000000000000000")
+            .await
+            .assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn test_library_stats() {
+        let (server, state, _tmp) = setup_test_app().await;
+        crate::stats::record_snapshot(&state).await;
+
+        let stats: serde_json::Value = server.get("/api/stats").await.json();
+        assert_eq!(stats["total"]["files"], 1);
+        assert_eq!(stats["by_category"]["Base"]["size"], 5);
+        assert_eq!(stats["by_format"]["nsp"]["files"], 1);
+        assert_eq!(stats["by_root"].as_object().unwrap().len(), 1);
+        assert_eq!(
+            stats["largest_titles"][0]["base_title_id"],
+            "0100000000010000"
+        );
+        assert!(stats["disks"][0]["total"].as_u64().unwrap() > 0);
+        assert_eq!(stats["history"][0]["total"]["files"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        let (server, _state, _tmp) = setup_test_app().await;
+
+        let response = server
+            .get("/api/export?format=csv&fields=name,title_id,size&file_format=nsp")
+            .await;
+        response.assert_status_ok();
+        assert_eq!(
+            response.header("content-type").to_str().unwrap(),
+            "text/csv; charset=utf-8"
+        );
+        assert!(
+            response
+                .header("content-disposition")
+                .to_str()
+                .unwrap()
+                .contains("switcheroo-library.csv")
+        );
+        assert_eq!(
+            response.text(),
+            "name,title_id,size\r\nTest Game,0100000000010000,5\r\n"
+        );
+
+        let rows: serde_json::Value = server.get("/api/export?file_format=xci").await.json();
+        assert!(rows.as_array().unwrap().is_empty());
+
+        let html = server.get("/api/export?format=html").await.text();
+        assert!(html.contains("<td>Test Game</td>"));
+
+        server
+            .get("/api/export?fields=bogus")
+            .await
+            .assert_status_bad_request();
+    }
+
+    #[tokio::test]
+    async fn test_list_games_query() {
+        let (server, state, tmp) = setup_test_app().await;
+        let games_dir = tmp.path().join("games");
+        for name in [
+            "Alpha [0100000000020000][v0].xci",
+            "Beta [0100000000030000][v0].nsp",
+        ] {
+            std::fs::write(games_dir.join(name), "dummy").unwrap();
+            let game = crate::scanner::discover(&games_dir.join(name), &games_dir).unwrap();
+            crate::library::add_games(&state, vec![game]);
+        }
+
+        let body: serde_json::Value = server
+            .get("/api/games?format=nsp&sort=name&order=desc&page=2&per_page=1")
+            .await
+            .json();
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["page"], 2);
+        assert_eq!(body["games"].as_array().unwrap().len(), 1);
+        assert_eq!(body["games"][0]["name"], "Beta");
+
+        let body: serde_json::Value = server
+            .get("/api/games?q=alpha&title_id_prefix=01000000000200&outdated=false")
+            .await
+            .json();
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["per_page"], 100);
+        assert!(body["games"][0]["added_at"].is_u64());
+
+        server
+            .get("/api/games?sort=bogus")
+            .await
+            .assert_status_bad_request();
+    }
+
     #[tokio::test]
     async fn test_tinfoil_index() {
         let (server, _, _tmp) = setup_test_app().await;
@@ -267,6 +617,307 @@ mod tests {
         assert!(body.contains("Test Game"));
     }
 
+    #[tokio::test]
+    async fn test_dat_verification() {
+        use axum::http::header::AUTHORIZATION;
+
+        let (server, mut state, tmp) = setup_test_app().await;
+        let games_dir = tmp.path().join("games");
+        std::fs::write(games_dir.join("Bad.nsp"), "too long").unwrap();
+        let game = crate::scanner::discover(&games_dir.join("Bad.nsp"), &games_dir).unwrap();
+        crate::library::add_games(&state, vec![game]);
+        crate::integrity::run_pass(&state).await;
+
+        let dat = r#"<?xml version="1.0"?>
+<datafile>
+  <header><name>Switch</name></header>
+  <game name="Test Game">
+    <rom name="Clean Dump.nsp" size="5" sha256="B5A2C96250612366EA272FFAC6D9744AAF4B45AACD96AA7CFCB931EE3B558259"/>
+  </game>
+  <game name="Bad"><rom name="Bad.nsp" size="5"/></game>
+</datafile>"#;
+        server
+            .post("/api/dats")
+            .text(dat)
+            .await
+            .assert_status(axum::http::StatusCode::FORBIDDEN);
+        let (server, auth) = with_credentials(&mut state);
+        server
+            .post("/api/dats")
+            .add_header(AUTHORIZATION, &auth)
+            .text("<datafile>")
+            .await
+            .assert_status(axum::http::StatusCode::BAD_REQUEST);
+        let response = server
+            .post("/api/dats")
+            .add_header(AUTHORIZATION, &auth)
+            .text(dat)
+            .await;
+        response.assert_status(axum::http::StatusCode::CREATED);
+        let summary: serde_json::Value = response.json();
+        assert_eq!(summary["roms"], 2);
+        assert_eq!(summary["roms_with_sha256"], 1);
+        let dats: Vec<serde_json::Value> = server.get("/api/dats").await.json();
+        assert_eq!(dats.len(), 1);
+
+        let report: serde_json::Value = server.get("/api/library/verification").await.json();
+        assert_eq!(report["verified"], 1);
+        assert_eq!(report["bad"], 1);
+        assert_eq!(report["mismatches"][0]["relative_path"], "Bad.nsp");
+        assert_eq!(report["mismatches"][0]["reason"], "size_mismatch");
+        assert_eq!(report["mismatches"][0]["expected"]["size"], 5);
+
+        let body: serde_json::Value = server
+            .get("/api/games")
+            .add_query_param("verification", "verified")
+            .await
+            .json();
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["games"][0]["name"], "Test Game");
+        server
+            .get("/api/games")
+            .add_query_param("verification", "good")
+            .await
+            .assert_status_bad_request();
+
+        // Rediscovered files are checked as they are added
+        let path = games_dir.join("Test Game [0100000000010000][v0].nsp");
+        let game = crate::scanner::discover(&path, &games_dir).unwrap();
+        let game = crate::library::upsert_game(&state, game);
+        assert_eq!(game.verification, Some(Verification::Verified));
+
+        // The CLI export checks files the same way
+        let exported = tmp.path().join("verified.json");
+        let args: Vec<String> = ["--verification", "verified", "--output"]
+            .into_iter()
+            .map(str::to_string)
+            .chain([exported.to_string_lossy().to_string()])
+            .collect();
+        crate::export::run_cli(state.settings.clone(), &args)
+            .await
+            .unwrap();
+        let exported: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&exported).unwrap()).unwrap();
+        assert_eq!(exported.as_array().unwrap().len(), 1);
+        assert_eq!(exported[0]["name"], "Test Game");
+
+        // A replaced file is not judged by the hash of the old one
+        std::fs::write(&path, "dumbo").unwrap();
+        let game = crate::scanner::discover(&path, &games_dir).unwrap();
+        let game = crate::library::upsert_game(&state, game);
+        assert_eq!(game.verification, Some(Verification::Unknown));
+        std::fs::write(&path, "dummy").unwrap();
+        crate::integrity::run_pass(&state).await;
+
+        // The report leaves the library alone
+        for game in state.games.lock().unwrap().iter_mut() {
+            game.verification = None;
+        }
+        let report: serde_json::Value = server.get("/api/library/verification").await.json();
+        assert_eq!(report["verified"], 1);
+        assert!(
+            state
+                .games
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|g| g.verification.is_none())
+        );
+
+        let id = summary["id"].as_str().unwrap();
+        server
+            .delete(&format!("/api/dats/{}", id))
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status(axum::http::StatusCode::NO_CONTENT);
+        server
+            .delete(&format!("/api/dats/{}", id))
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status_not_found();
+        assert!(
+            list_games(&server)
+                .await
+                .iter()
+                .all(|g| g.verification.is_none())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_data() {
+        use axum::http::header::AUTHORIZATION;
+
+        let (server, mut state, tmp) = setup_test_app().await;
+        let games_dir = tmp.path().join("games");
+        let update = "Test Game [0100000000010800][v65536].nsp";
+        std::fs::write(games_dir.join(update), "dummy").unwrap();
+        let game = crate::scanner::discover(&games_dir.join(update), &games_dir).unwrap();
+        crate::library::add_games(&state, vec![game]);
+
+        server
+            .put("/api/userdata/0100000000010000")
+            .json(&serde_json::json!({ "favorite": true }))
+            .await
+            .assert_status(axum::http::StatusCode::FORBIDDEN);
+        let (server, auth) = with_credentials(&mut state);
+        server
+            .put("/api/userdata/nope")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "favorite": true }))
+            .await
+            .assert_status(axum::http::StatusCode::BAD_REQUEST);
+        // Stored under the base game, whichever of its titles is given
+        let data: serde_json::Value = server
+            .put("/api/userdata/0100000000010800")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({
+                "favorite": true,
+                "tags": ["kids", " Kids "],
+                "note": "needs v1.2 for the mod",
+                "collections": ["Family"],
+            }))
+            .await
+            .json();
+        assert_eq!(data["tags"], serde_json::json!(["kids"]));
+        let all: serde_json::Value = server.get("/api/userdata").await.json();
+        assert_eq!(all["0100000000010000"]["favorite"], true);
+
+        let games = list_games(&server).await;
+        assert_eq!(games.len(), 2);
+        assert!(
+            games
+                .iter()
+                .all(|g| g.user_data.as_ref().is_some_and(|u| u.favorite))
+        );
+        for (key, value) in [
+            ("favorite", "true"),
+            ("tag", "KIDS"),
+            ("collection", "family"),
+        ] {
+            let body: serde_json::Value = server
+                .get("/api/games")
+                .add_query_param(key, value)
+                .await
+                .json();
+            assert_eq!(body["total"], 2, "{}={}", key, value);
+        }
+        let body: serde_json::Value = server
+            .get("/api/games")
+            .add_query_param("tag", "co-op")
+            .await
+            .json();
+        assert_eq!(body["total"], 0);
+        let body: serde_json::Value = server
+            .get("/api/games")
+            .add_query_param("per_page", "1")
+            .add_query_param("page", "2")
+            .await
+            .json();
+        assert_eq!(body["games"][0]["user_data"]["favorite"], true);
+        let titles: serde_json::Value = server.get("/api/titles").await.json();
+        assert_eq!(titles[0]["user_data"]["tags"], serde_json::json!(["kids"]));
+
+        // Kept across a rename
+        let (id, old) = {
+            let games = state.games.lock().unwrap();
+            let game = games.iter().find(|g| g.relative_path == update).unwrap();
+            (game.id.clone(), game.path.clone())
+        };
+        let mut events = state.tx.subscribe();
+        crate::fileops::relocate(
+            &state,
+            &old,
+            &games_dir.join("Renamed [0100000000010800][v65536].nsp"),
+        )
+        .await
+        .unwrap();
+        let update = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|e| serde_json::from_str::<serde_json::Value>(&e).unwrap())
+            .find(|e| e["status"] == "update")
+            .unwrap();
+        assert_eq!(update["game"]["user_data"]["favorite"], true);
+        let renamed = list_games(&server)
+            .await
+            .into_iter()
+            .find(|g| g.name == "Renamed")
+            .unwrap();
+        assert_ne!(renamed.id, id);
+        assert_eq!(
+            renamed.user_data.unwrap().note.as_deref(),
+            Some("needs v1.2 for the mod")
+        );
+
+        let collections: serde_json::Value = server.get("/api/collections").await.json();
+        assert_eq!(
+            collections["Family"],
+            serde_json::json!(["0100000000010000"])
+        );
+        server
+            .delete("/api/collections/family")
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status(axum::http::StatusCode::NO_CONTENT);
+        server
+            .delete("/api/collections/family")
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status_not_found();
+        server
+            .delete("/api/userdata/0100000000010000")
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status(axum::http::StatusCode::NO_CONTENT);
+        server
+            .get("/api/userdata/0100000000010000")
+            .await
+            .assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn test_hide_broken_files() {
+        let (server, mut state, tmp) = setup_test_app().await;
+        let games_dir = tmp.path().join("games");
+        let mut content = b"PFS0".to_vec();
+        content.extend([0u8; 12]);
+        for (name, content) in [
+            ("Good [0100000000020000][v0].nsp", content.as_slice()),
+            ("Broken [0100000000030000][v0].nsp", b"PFS0\x01".as_slice()),
+        ] {
+            std::fs::write(games_dir.join(name), content).unwrap();
+            let game = crate::scanner::discover(&games_dir.join(name), &games_dir).unwrap();
+            crate::library::add_games(&state, vec![game]);
+        }
+
+        let body: serde_json::Value = server
+            .get("/api/games")
+            .add_query_param("health", "broken")
+            .await
+            .json();
+        let broken: Vec<Game> = serde_json::from_value(body["games"].clone()).unwrap();
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].name, "Broken");
+        assert_eq!(
+            broken[0].health.as_ref().unwrap().problem.as_deref(),
+            Some("File is truncated")
+        );
+
+        let index: serde_json::Value = server.get("/tinfoil").await.json();
+        assert_eq!(index["files"].as_array().unwrap().len(), 3);
+        state.settings.hide_broken_files = true;
+        let server = TestServer::new(create_app(state)).unwrap();
+        let index: serde_json::Value = server.get("/tinfoil").await.json();
+        let urls: Vec<&str> = index["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["url"].as_str().unwrap())
+            .collect();
+        assert_eq!(urls.len(), 2);
+        assert!(urls.iter().all(|u| !u.contains("Broken")));
+        let html = server.get("/dbi").await.text();
+        assert!(html.contains("Good") && !html.contains("Broken"));
+    }
+
     #[tokio::test]
     async fn test_webdav_options() {
         let (server, _, _tmp) = setup_test_app().await;
@@ -299,14 +950,31 @@ mod tests {
             webdav_enabled: true,
             metadata_region: "US".to_string(),
             metadata_language: "en".to_string(),
+            metadata_locales: None,
+            metadata_library_only_details: false,
+            title_match_threshold: 0.9,
+            metadata_sources: "overrides,catalogue,titledb,remote".to_string(),
+            metadata_field_sources: None,
+            metadata_remote_url: None,
+            metadata_remote_interval_hours: 1,
+            cleanup_preferred_formats: "nsp,nsz,xci,xcz".to_string(),
+            quarantine_retention_days: 30,
+            trash_retention_days: 30,
+            organize_template: "{category}/{name} [{title_id}][v{version}].{ext}".to_string(),
+            inbox_dir: None,
+            inbox_settle_secs: 10,
+            integrity_enabled: true,
+            integrity_verify_days: 30,
+            integrity_repr_digest: false,
+            hide_broken_files: false,
             tinfoil_encrypt: false,
         };
 
         let games = Arc::new(Mutex::new(vec![]));
         let (tx, _) = broadcast::channel(10);
-        let metadata = Arc::new(tokio::sync::Mutex::new(
-            crate::metadata::MetadataProvider::new(data_dir, "US".to_string(), "en".to_string())
-                .await,
+        let metadata = Arc::new(crate::metadata::MetadataStore::new(
+            data_dir,
+            settings.locales(),
         ));
         let dav_handler = webdav::create_dav_handler(&settings);
 
@@ -318,6 +986,36 @@ mod tests {
             tx,
             metadata,
             dav_handler,
+            quarantine: Arc::new(tokio::sync::Mutex::new(crate::cleanup::Quarantine::new(
+                &tmp_dir.path().join("data"),
+            ))),
+            trash: Arc::new(tokio::sync::Mutex::new(crate::cleanup::Quarantine::in_dir(
+                tmp_dir.path().join("data").join("trash"),
+            ))),
+            watch_suppression: Arc::default(),
+            file_ops: Arc::default(),
+            uploads: Arc::new(tokio::sync::Mutex::new(crate::uploads::Uploads::new(
+                &tmp_dir.path().join("data"),
+            ))),
+            organize_log: Arc::new(tokio::sync::Mutex::new(crate::organizer::OrganizeLog::new(
+                &tmp_dir.path().join("data"),
+            ))),
+            ingest_log: Arc::new(tokio::sync::Mutex::new(crate::inbox::IngestLog::new(
+                &tmp_dir.path().join("data"),
+            ))),
+            integrity: Arc::new(crate::integrity::IntegrityStore::new(
+                &tmp_dir.path().join("data"),
+            )),
+            dats: Arc::new(crate::dat::DatStore::new(&tmp_dir.path().join("data"))),
+            user_library: Arc::new(tokio::sync::Mutex::new(crate::userdata::UserLibrary::new(
+                &tmp_dir.path().join("data"),
+            ))),
+            download_history: Arc::new(tokio::sync::Mutex::new(
+                crate::downloads::DownloadHistory::new(&tmp_dir.path().join("data")),
+            )),
+            stats_history: Arc::new(tokio::sync::Mutex::new(crate::stats::StatsHistory::new(
+                &tmp_dir.path().join("data"),
+            ))),
         };
 
         let app = create_app(state);
@@ -349,6 +1047,802 @@ mod tests {
         assert!(body.contains("<div id=\"app\""));
     }
 
+    #[tokio::test]
+    async fn test_metadata_override() {
+        use axum::http::header::AUTHORIZATION;
+
+        let (server, mut state, _tmp) = setup_test_app().await;
+        let request = serde_json::json!({ "name": "Renamed Game", "publisher": "Homebrew" });
+        server
+            .put("/api/overrides/0100000000010000")
+            .json(&request)
+            .await
+            .assert_status(axum::http::StatusCode::FORBIDDEN);
+        server
+            .put("/api/icons/0100000000010000")
+            .bytes(b"\x89PNG\r\n\x1a\n".as_slice().into())
+            .await
+            .assert_status(axum::http::StatusCode::FORBIDDEN);
+        let (server, auth) = with_credentials(&mut state);
+        let response = server
+            .put("/api/overrides/0100000000010000")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&request)
+            .await;
+        response.assert_status_ok();
+        // The enrichment task re-applies metadata once the snapshot changes
+        crate::library::enrich_library(&state);
+
+        let games = list_games(&server).await;
+        assert_eq!(games[0].name, "Renamed Game");
+        assert_eq!(games[0].publisher.as_deref(), Some("Homebrew"));
+
+        let body = server.get("/dbi").await.text();
+        assert!(body.contains("Renamed Game"));
+
+        let body: serde_json::Value = server.get("/tinfoil").await.json();
+        assert_eq!(body["titledb"]["0100000000010000"]["name"], "Renamed Game");
+
+        server
+            .delete("/api/overrides/0100000000010000")
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status(axum::http::StatusCode::NO_CONTENT);
+        crate::library::enrich_library(&state);
+        let games = list_games(&server).await;
+        assert_eq!(games[0].name, "Test Game");
+    }
+
+    #[tokio::test]
+    async fn test_title_details() {
+        let (server, _, _tmp) = setup_test_app().await;
+        let response = server.get("/api/metadata/0100000000010000").await;
+        response.assert_status_ok();
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["kind"], "Application");
+        assert_eq!(body["owned"].as_array().unwrap().len(), 1);
+
+        let response = server.get("/api/metadata/0100000000099000").await;
+        response.assert_status(axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_title_matches() {
+        use axum::http::header::AUTHORIZATION;
+
+        let (server, mut state, tmp) = setup_test_app().await;
+        let titledb = tmp.path().join("data").join("titledb");
+        std::fs::create_dir_all(&titledb).unwrap();
+        std::fs::write(
+            titledb.join("US.en.json"),
+            r#"{
+                "01002B30028F6000": { "name": "Celeste" },
+                "0100000000030000": { "name": "Hollow Knight" }
+            }"#,
+        )
+        .unwrap();
+        let games_dir = tmp.path().join("games");
+        for name in ["Celeste (USA).nsp", "Hollow Night.nsp"] {
+            std::fs::write(games_dir.join(name), "dummy").unwrap();
+            let game = crate::scanner::discover(&games_dir.join(name), &games_dir).unwrap();
+            crate::library::add_games(&state, vec![game]);
+        }
+        state.metadata.init().await;
+        crate::library::enrich_library(&state);
+
+        // An exact name is matched automatically, a close one is only suggested
+        let entries: Vec<serde_json::Value> = server.get("/api/matches").await.json();
+        assert_eq!(entries.len(), 2);
+        let celeste = entries
+            .iter()
+            .find(|e| e["relative_path"] == "Celeste (USA).nsp")
+            .unwrap();
+        assert_eq!(celeste["title_id"], "01002B30028F6000");
+        assert_eq!(celeste["match_confidence"], 1.0);
+        assert_eq!(celeste["confirmed"], false);
+        let hollow = entries
+            .iter()
+            .find(|e| e["name"] == "Hollow Night")
+            .unwrap();
+        assert!(hollow["title_id"].is_null());
+        assert_eq!(hollow["candidates"][0]["title_id"], "0100000000030000");
+        assert_eq!(hollow["candidates"][0]["name"], "Hollow Knight");
+
+        let confirm = serde_json::json!({ "title_id": "0100000000030000" });
+        server
+            .put("/api/matches/Hollow%20Night.nsp")
+            .json(&confirm)
+            .await
+            .assert_status(axum::http::StatusCode::FORBIDDEN);
+        let (server, auth) = with_credentials(&mut state);
+        server
+            .put("/api/matches/Hollow%20Night.nsp")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&confirm)
+            .await
+            .assert_status(axum::http::StatusCode::NO_CONTENT);
+        server
+            .put("/api/matches/Hollow%20Night.nsp")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "title_id": "nope" }))
+            .await
+            .assert_status(axum::http::StatusCode::BAD_REQUEST);
+        // The enrichment task re-applies metadata once the snapshot changes
+        crate::library::enrich_library(&state);
+
+        let games = list_games(&server).await;
+        let hollow = games
+            .iter()
+            .find(|g| g.relative_path == "Hollow Night.nsp")
+            .unwrap();
+        assert_eq!(hollow.title_id.as_deref(), Some("0100000000030000"));
+        assert_eq!(hollow.name, "Hollow Knight");
+        assert_eq!(hollow.match_confidence, Some(1.0));
+        assert!(tmp.path().join("data").join("title_matches.json").exists());
+
+        // Rejecting the automatic match keeps the file unidentified
+        server
+            .put("/api/matches/Celeste%20(USA).nsp")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "title_id": null }))
+            .await
+            .assert_status(axum::http::StatusCode::NO_CONTENT);
+        crate::library::enrich_library(&state);
+        let entry: serde_json::Value = server.get("/api/matches/Celeste%20(USA).nsp").await.json();
+        assert!(entry["title_id"].is_null());
+        assert_eq!(entry["confirmed"], true);
+
+        server
+            .delete("/api/matches/Celeste%20(USA).nsp")
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status(axum::http::StatusCode::NO_CONTENT);
+        crate::library::enrich_library(&state);
+        let entry: serde_json::Value = server.get("/api/matches/Celeste%20(USA).nsp").await.json();
+        assert_eq!(entry["title_id"], "01002B30028F6000");
+
+        // The confirmed match and a path override follow a moved file
+        server
+            .put("/api/overrides/Hollow%20Night.nsp")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "publisher": "Team Cherry" }))
+            .await
+            .assert_status_ok();
+        std::fs::create_dir_all(games_dir.join("Moved")).unwrap();
+        let moved = crate::fileops::relocate(
+            &state,
+            &games_dir.join("Hollow Night.nsp"),
+            &games_dir.join("Moved/Hollow Night.nsp"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(moved.title_id.as_deref(), Some("0100000000030000"));
+        assert_eq!(moved.publisher.as_deref(), Some("Team Cherry"));
+        let entry: serde_json::Value = server
+            .get("/api/matches/Moved/Hollow%20Night.nsp")
+            .await
+            .json();
+        assert_eq!(entry["confirmed"], true);
+        server
+            .get("/api/overrides/Hollow%20Night.nsp")
+            .await
+            .assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn test_catalog_search() {
+        let (server, state, tmp) = setup_test_app().await;
+        let titledb = tmp.path().join("data").join("titledb");
+        std::fs::create_dir_all(&titledb).unwrap();
+        std::fs::write(
+            titledb.join("US.en.json"),
+            r#"{
+                "0100000000010000": { "name": "Test Game", "publisher": "Acme", "category": ["Action"] },
+                "0100000000020000": { "name": "Another Test", "publisher": "Acme", "category": ["Puzzle"] },
+                "0100000000030000": { "name": "Unrelated", "publisher": "Other" }
+            }"#,
+        )
+        .unwrap();
+        state.metadata.init().await;
+
+        let body: serde_json::Value = server.get("/api/catalog/search?q=test").await.json();
+        assert_eq!(body["total"], 2);
+        // Names starting with the query rank first
+        assert_eq!(body["results"][0]["title_id"], "0100000000010000");
+        assert_eq!(body["results"][0]["owned"], true);
+        assert_eq!(body["results"][1]["owned"], false);
+
+        let body: serde_json::Value = server
+            .get("/api/catalog/search?q=test&mode=prefix")
+            .await
+            .json();
+        assert_eq!(body["total"], 1);
+
+        let body: serde_json::Value = server
+            .get("/api/catalog/search?publisher=acme&category=puzzle")
+            .await
+            .json();
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["results"][0]["name"], "Another Test");
+
+        let body: serde_json::Value = server
+            .get("/api/catalog/search?publisher=Acme&per_page=1&page=2")
+            .await
+            .json();
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["results"].as_array().unwrap().len(), 1);
+        assert_eq!(body["results"][0]["title_id"], "0100000000010000");
+
+        let body: serde_json::Value = server
+            .get("/api/catalog/search?q=00000003&mode=substring")
+            .await
+            .json();
+        assert_eq!(body["results"][0]["name"], "Unrelated");
+    }
+
+    #[tokio::test]
+    async fn test_title_families() {
+        let (server, state, tmp) = setup_test_app().await;
+        let titledb = tmp.path().join("data").join("titledb");
+        std::fs::create_dir_all(&titledb).unwrap();
+        std::fs::write(
+            titledb.join("US.en.json"),
+            r#"{ "0100000000010000": { "name": "Test Game" } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            titledb.join("versions.json"),
+            r#"{ "0100000000010000": { "65536": "2020-01-01", "131072": "2020-06-01", "196608": "2021-01-01" } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            titledb.join("cnmts.json"),
+            r#"{
+                "0100000000011001": { "0": { "titleType": 130, "otherApplicationId": "0100000000010000" } },
+                "0100000000011002": { "0": { "titleType": 130, "otherApplicationId": "0100000000010000" } }
+            }"#,
+        )
+        .unwrap();
+        let games_dir = tmp.path().join("games");
+        for name in [
+            "Test Game [0100000000010800][v131072].nsp",
+            "Test Game [0100000000010800][v65536].nsp",
+            "Test Game DLC [0100000000011001][v0].nsp",
+        ] {
+            std::fs::write(games_dir.join(name), "dummy").unwrap();
+            let game = crate::scanner::discover(&games_dir.join(name), &games_dir).unwrap();
+            crate::library::add_games(&state, vec![game]);
+        }
+        state.metadata.init().await;
+        crate::library::enrich_library(&state);
+
+        let families: Vec<serde_json::Value> = server.get("/api/titles").await.json();
+        assert_eq!(families.len(), 1);
+        let family = &families[0];
+        assert_eq!(family["base_title_id"], "0100000000010000");
+        assert_eq!(family["name"], "Test Game");
+        assert_eq!(family["base"].as_array().unwrap().len(), 1);
+        assert_eq!(family["updates"][0]["version"], "v65536");
+        assert_eq!(family["updates"][1]["version"], "v131072");
+        assert_eq!(family["latest_version"], 196608);
+        assert_eq!(family["owned_version"], 131072);
+        assert_eq!(family["missing_updates"], 1);
+        assert_eq!(family["missing_dlc"], 1);
+
+        // Any title ID of the family leads to it
+        let details: serde_json::Value = server.get("/api/titles/0100000000010800").await.json();
+        assert_eq!(details["base_title_id"], "0100000000010000");
+        assert_eq!(details["versions"].as_array().unwrap().len(), 3);
+        assert_eq!(details["versions"][1]["owned"], true);
+        assert_eq!(details["versions"][2]["owned"], false);
+        assert_eq!(
+            details["missing_dlc_titles"][0]["title_id"],
+            "0100000000011002"
+        );
+
+        server
+            .get("/api/titles/0100000000099000")
+            .await
+            .assert_status(axum::http::StatusCode::NOT_FOUND);
+
+        // The base game from the test setup is behind the latest update
+        let outdated: Vec<serde_json::Value> = server.get("/api/library/outdated").await.json();
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0]["owned_version"], 131072);
+        assert_eq!(outdated[0]["latest_version"], 196608);
+        assert_eq!(outdated[0]["latest_release_date"], "2021-01-01");
+    }
+
+    #[tokio::test]
+    async fn test_file_management() {
+        use axum::http::{StatusCode, header::AUTHORIZATION};
+
+        let (server, mut state, tmp) = setup_test_app().await;
+        let games_dir = tmp.path().join("games");
+        let id = list_games(&server).await[0].id.clone();
+
+        // Disabled without credentials
+        server
+            .delete(&format!("/api/games/{}", id))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+
+        let (server, auth) = with_credentials(&mut state);
+        server
+            .post(&format!("/api/games/{}/rename", id))
+            .json(&serde_json::json!({ "name": "Renamed.nsp" }))
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        server
+            .get(&format!("/api/games/{}", id))
+            .await
+            .assert_status_ok();
+
+        let renamed = "Renamed [0100000000010000][v0].nsp";
+        for (name, status) in [
+            ("../Escaped.nsp", StatusCode::BAD_REQUEST),
+            ("Renamed.txt", StatusCode::BAD_REQUEST),
+        ] {
+            server
+                .post(&format!("/api/games/{}/rename", id))
+                .add_header(AUTHORIZATION, &auth)
+                .json(&serde_json::json!({ "name": name }))
+                .await
+                .assert_status(status);
+        }
+        let game: Game = server
+            .post(&format!("/api/games/{}/rename", id))
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "name": renamed }))
+            .await
+            .json();
+        assert_eq!(game.relative_path, renamed);
+        assert!(games_dir.join(renamed).exists());
+        assert!(
+            !games_dir
+                .join("Test Game [0100000000010000][v0].nsp")
+                .exists()
+        );
+        let games = list_games(&server).await;
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].id, game.id);
+
+        for folder in ["../outside", "Sub/../../outside"] {
+            server
+                .post(&format!("/api/games/{}/move", game.id))
+                .add_header(AUTHORIZATION, &auth)
+                .json(&serde_json::json!({ "folder": folder }))
+                .await
+                .assert_status(StatusCode::BAD_REQUEST);
+        }
+        std::fs::create_dir_all(games_dir.join("Taken")).unwrap();
+        std::fs::write(games_dir.join("Taken").join(renamed), "other").unwrap();
+        server
+            .post(&format!("/api/games/{}/move", game.id))
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "folder": "Taken" }))
+            .await
+            .assert_status(StatusCode::CONFLICT);
+
+        let moved: Game = server
+            .post(&format!("/api/games/{}/move", game.id))
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "folder": "Sub/Dir" }))
+            .await
+            .json();
+        assert_eq!(moved.relative_path, format!("Sub/Dir/{}", renamed));
+        assert!(games_dir.join(&moved.relative_path).exists());
+        assert_eq!(list_games(&server).await.len(), 1);
+
+        let entry: serde_json::Value = server
+            .delete(&format!("/api/games/{}", moved.id))
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .json();
+        assert_eq!(entry["reason"], "deleted");
+        assert!(!games_dir.join(&moved.relative_path).exists());
+        assert!(list_games(&server).await.is_empty());
+
+        let trash: serde_json::Value = server
+            .get("/api/trash")
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .json();
+        assert_eq!(trash["entries"].as_array().unwrap().len(), 1);
+        let restored: Game = server
+            .post(&format!(
+                "/api/trash/{}/restore",
+                entry["id"].as_str().unwrap()
+            ))
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .json();
+        assert_eq!(restored.relative_path, moved.relative_path);
+        assert!(games_dir.join(&moved.relative_path).exists());
+        assert_eq!(list_games(&server).await.len(), 1);
+        assert!(state.trash.lock().await.entries().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_upload() {
+        use axum::http::{StatusCode, header::AUTHORIZATION};
+
+        let (_, mut state, tmp) = setup_test_app().await;
+        let (server, auth) = with_credentials(&mut state);
+        let games_dir = tmp.path().join("games");
+        // An empty PFS0 archive
+        let mut content = b"PFS0".to_vec();
+        content.extend([0u8; 12]);
+
+        for (name, folder) in [("Game.txt", ""), ("Game.nsp", "../outside")] {
+            server
+                .post("/api/uploads")
+                .add_header(AUTHORIZATION, &auth)
+                .json(&serde_json::json!({ "name": name, "size": 16, "folder": folder }))
+                .await
+                .assert_status(StatusCode::BAD_REQUEST);
+        }
+        server
+            .post("/api/uploads")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "name": "Game.nsp", "size": u64::MAX / 2 }))
+            .await
+            .assert_status(StatusCode::INSUFFICIENT_STORAGE);
+
+        let name = "Uploaded [0100000000020000][v0].nsp";
+        let response = server
+            .post("/api/uploads")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "name": name, "size": 16, "folder": "New" }))
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let id = response.json::<serde_json::Value>()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let url = format!("/api/uploads/{}", id);
+
+        let mut rx = state.tx.subscribe();
+        let response = server
+            .put(&url)
+            .add_header(AUTHORIZATION, &auth)
+            .add_header("Upload-Offset", "0")
+            .bytes(content[..10].to_vec().into())
+            .await;
+        response.assert_status(StatusCode::NO_CONTENT);
+        response.assert_header("Upload-Offset", "10");
+        let event: serde_json::Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(event["type"], "upload");
+        assert_eq!(event["upload"]["offset"], 10);
+        assert!(!games_dir.join("New").join(name).exists());
+
+        // Resuming needs the offset the server has
+        server
+            .put(&url)
+            .add_header(AUTHORIZATION, &auth)
+            .add_header("Upload-Offset", "0")
+            .bytes(content.clone().into())
+            .await
+            .assert_status(StatusCode::CONFLICT);
+        let status = server.get(&url).add_header(AUTHORIZATION, &auth).await;
+        status.assert_header("Upload-Offset", "10");
+
+        let response = server
+            .put(&url)
+            .add_header(AUTHORIZATION, &auth)
+            .add_header("Upload-Offset", "10")
+            .bytes(content[10..].to_vec().into())
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let game: Game = response.json();
+        assert_eq!(game.relative_path, format!("New/{}", name));
+        assert_eq!(
+            std::fs::read(games_dir.join("New").join(name)).unwrap(),
+            content
+        );
+        assert!(list_games(&server).await.iter().any(|g| g.id == game.id));
+        let uploads: Vec<serde_json::Value> = server
+            .get("/api/uploads")
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .json();
+        assert!(uploads.is_empty());
+
+        // Anything but an NSP or XCI is dropped once its header is in
+        let response = server
+            .post("/api/uploads")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "name": "Fake.nsp", "size": 5 }))
+            .await;
+        let id = response.json::<serde_json::Value>()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        server
+            .put(&format!("/api/uploads/{}", id))
+            .add_header(AUTHORIZATION, &auth)
+            .add_header("Upload-Offset", "0")
+            .bytes(b"dummy".to_vec().into())
+            .await
+            .assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(state.uploads.lock().await.uploads().is_empty());
+        assert!(!games_dir.join("Fake.nsp").exists());
+    }
+
+    #[tokio::test]
+    async fn test_organize() {
+        use axum::http::{StatusCode, header::AUTHORIZATION};
+
+        let (_, mut state, tmp) = setup_test_app().await;
+        let (server, auth) = with_credentials(&mut state);
+        let games_dir = tmp.path().join("games");
+        let original = "Test Game [0100000000010000][v0].nsp";
+        let target = format!("Base/{}", original);
+
+        server
+            .get("/api/organize?template={bogus}.{ext}")
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+        let plan: serde_json::Value = server.get("/api/organize").await.json();
+        assert_eq!(plan["moves"][0]["from"], original);
+        assert_eq!(plan["moves"][0]["to"], target);
+        assert!(games_dir.join(original).exists());
+
+        server
+            .post("/api/organize")
+            .json(&serde_json::json!({}))
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        let result: serde_json::Value = server
+            .post("/api/organize")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({}))
+            .await
+            .json();
+        assert_eq!(result["run"]["moves"][0]["to"], target);
+        assert!(games_dir.join(&target).exists());
+        assert!(!games_dir.join(original).exists());
+        assert_eq!(list_games(&server).await[0].relative_path, target);
+        let plan: serde_json::Value = server.get("/api/organize").await.json();
+        assert_eq!(plan["unchanged"], 1);
+
+        let runs: Vec<serde_json::Value> = server.get("/api/organize/runs").await.json();
+        let run_id = runs[0]["id"].as_str().unwrap();
+        let undone: serde_json::Value = server
+            .post(&format!("/api/organize/runs/{}/undo", run_id))
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .json();
+        assert_eq!(undone["restored"].as_array().unwrap().len(), 1);
+        assert!(games_dir.join(original).exists());
+        assert!(!games_dir.join("Base").exists());
+        assert_eq!(list_games(&server).await[0].relative_path, original);
+        assert!(state.organize_log.lock().await.runs().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_inbox_ingest() {
+        let (server, state, tmp) = setup_test_app().await;
+        let inbox = tmp.path().join("inbox");
+        let games_dir = tmp.path().join("games");
+        std::fs::create_dir_all(inbox.join("Drop")).unwrap();
+        let mut content = b"PFS0".to_vec();
+        content.extend([0u8; 12]);
+        let good = inbox.join("Drop/New Game [0100000000020000][v0].nsp");
+        std::fs::write(&good, &content).unwrap();
+        let bad = inbox.join("Broken [0100000000030000][v0].nsp");
+        std::fs::write(&bad, "dummy").unwrap();
+
+        let mut rx = state.tx.subscribe();
+        let entry = crate::inbox::ingest(&state, &inbox, &good).await;
+        assert_eq!(entry.status, "ingested");
+        let target = "Base/New Game [0100000000020000][v0].nsp";
+        assert_eq!(entry.target.as_deref(), Some(target));
+        assert!(games_dir.join(target).exists());
+        assert!(!inbox.join("Drop").exists());
+        assert!(
+            list_games(&server)
+                .await
+                .iter()
+                .any(|g| g.relative_path == target)
+        );
+        let events: Vec<serde_json::Value> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|e| serde_json::from_str(&e).unwrap())
+            .collect();
+        assert!(events.iter().any(|e| e["type"] == "ingest"));
+
+        let entry = crate::inbox::ingest(&state, &inbox, &bad).await;
+        assert_eq!(entry.status, "rejected");
+        assert_eq!(entry.reason.as_deref(), Some("invalid_container"));
+        let rejected = inbox.join("rejected/Broken [0100000000030000][v0].nsp");
+        assert!(rejected.exists());
+        assert_eq!(
+            std::fs::read_to_string(
+                rejected.with_file_name("Broken [0100000000030000][v0].nsp.reason.txt")
+            )
+            .unwrap(),
+            "invalid_container\n"
+        );
+
+        let log: serde_json::Value = server.get("/api/inbox").await.json();
+        assert_eq!(log["enabled"], false);
+        assert_eq!(log["entries"][0]["status"], "rejected");
+        assert_eq!(log["entries"][1]["status"], "ingested");
+    }
+
+    #[tokio::test]
+    async fn test_library_integrity() {
+        let (server, mut state, tmp) = setup_test_app().await;
+        let name = "Test Game [0100000000010000][v0].nsp";
+        let games_dir = tmp.path().join("games");
+        let path = games_dir.join(name);
+        let url = format!(
+            "/api/games/{}/download",
+            crate::scanner::game_id(&games_dir, name)
+        );
+
+        let report: serde_json::Value = server.get("/api/library/integrity").await.json();
+        assert_eq!(report["files"], 1);
+        assert_eq!(report["pending"], 1);
+
+        let summary = crate::integrity::run_pass(&state).await;
+        assert_eq!(summary.hashed, 1);
+        assert_eq!(crate::integrity::run_pass(&state).await.hashed, 0);
+        let report: serde_json::Value = server
+            .get("/api/library/integrity")
+            .add_query_param("all", true)
+            .await
+            .json();
+        assert_eq!(report["hashed"], 1);
+        assert_eq!(
+            report["records"][name]["sha256"],
+            "b5a2c96250612366ea272ffac6d9744aaf4b45aacd96aa7cfcb931ee3b558259"
+        );
+
+        // Digests are only sent when enabled
+        let response = server.get(&url).await;
+        assert!(response.maybe_header("Repr-Digest").is_none());
+        state.settings.integrity_repr_digest = true;
+        let digest_server = TestServer::new(create_app(state.clone())).unwrap();
+        let response = digest_server.get(&url).await;
+        assert_eq!(
+            response.header("Repr-Digest"),
+            "sha-256=:taLJYlBhI2bqJy/6xtl0Sq9LRarNlqp8/Lkx7jtVglk=:"
+        );
+
+        // Same size and mtime, different content
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::write(&path, "dumpy").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let mut record = state.integrity.get(name).unwrap();
+        record.hashed_at = 0;
+        state.integrity.insert(name.to_string(), record).await;
+        let mut rx = state.tx.subscribe();
+        let summary = crate::integrity::run_pass(&state).await;
+        assert_eq!(summary.corrupt, vec![name.to_string()]);
+        let event: serde_json::Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(event["status"], "corrupt");
+
+        let report: serde_json::Value = server.get("/api/library/integrity").await.json();
+        assert_eq!(report["corrupt"][0]["path"], name);
+        assert!(report["records"].is_null());
+        let response = digest_server.get(&url).await;
+        assert!(response.maybe_header("Repr-Digest").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_quarantine() {
+        use axum::http::{StatusCode, header::AUTHORIZATION};
+
+        let (server, mut state, tmp) = setup_test_app().await;
+        let games_dir = tmp.path().join("games");
+        for name in [
+            "Test Game [0100000000010800][v65536].nsp",
+            "Test Game [0100000000010800][v131072].nsp",
+        ] {
+            std::fs::write(games_dir.join(name), "dummy").unwrap();
+            let game = crate::scanner::discover(&games_dir.join(name), &games_dir).unwrap();
+            crate::library::add_games(&state, vec![game]);
+        }
+        let old = "Test Game [0100000000010800][v65536].nsp";
+
+        let report: serde_json::Value = server.get("/api/cleanup").await.json();
+        assert_eq!(report["candidates"].as_array().unwrap().len(), 1);
+        assert_eq!(report["candidates"][0]["relative_path"], old);
+        assert_eq!(report["candidates"][0]["reason"], "superseded");
+        assert_eq!(report["reclaimable"], 5);
+
+        // Moving files out of the library needs credentials
+        let request = serde_json::json!({ "paths": [old, "Missing.nsp"] });
+        server
+            .post("/api/cleanup/quarantine")
+            .json(&request)
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        let (server, auth) = with_credentials(&mut state);
+        let wrong = format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode("admin:wrong")
+        );
+        server
+            .post("/api/cleanup/quarantine")
+            .add_header(AUTHORIZATION, &wrong)
+            .json(&request)
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        assert!(games_dir.join(old).exists());
+
+        let result: serde_json::Value = server
+            .post("/api/cleanup/quarantine")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&request)
+            .await
+            .json();
+        assert_eq!(result["failed"][0]["path"], "Missing.nsp");
+        let id = result["quarantined"][0]["id"].as_str().unwrap().to_string();
+        assert!(!games_dir.join(old).exists());
+        assert!(
+            !state
+                .games
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|g| g.relative_path == old)
+        );
+
+        let listing: serde_json::Value = server.get("/api/quarantine").await.json();
+        assert_eq!(listing["entries"][0]["reason"], "superseded");
+        assert_eq!(listing["total_size"], 5);
+
+        // Restoring refuses to overwrite a file that took its place
+        std::fs::write(games_dir.join(old), "new").unwrap();
+        server
+            .post(&format!("/api/quarantine/{}/restore", id))
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status(StatusCode::CONFLICT);
+        std::fs::remove_file(games_dir.join(old)).unwrap();
+
+        server
+            .post(&format!("/api/quarantine/{}/restore", id))
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status_ok();
+        assert!(games_dir.join(old).exists());
+        assert!(
+            state
+                .games
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|g| g.relative_path == old)
+        );
+
+        let result: serde_json::Value = server
+            .post("/api/cleanup/quarantine")
+            .add_header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({ "paths": [old] }))
+            .await
+            .json();
+        let id = result["quarantined"][0]["id"].as_str().unwrap().to_string();
+        server
+            .delete(&format!("/api/quarantine/{}", id))
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .delete(&format!("/api/quarantine/{}", id))
+            .add_header(AUTHORIZATION, &auth)
+            .await
+            .assert_status_not_found();
+        assert!(state.quarantine.lock().await.entries().is_empty());
+    }
+
     #[tokio::test]
     async fn test_manual_sync_trigger() {
         let (server, _, _tmp) = setup_test_app().await;
@@ -0,0 +1,312 @@
+use crate::jsonfile;
+use crate::overrides::normalize_key;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Words that say which release of a game a file is rather than which game,
+/// stripped from the end of names before comparing them.
+const EDITION_SUFFIXES: &[&str] = &[
+    "game of the year edition",
+    "digital deluxe edition",
+    "nintendo switch edition",
+    "definitive edition",
+    "complete edition",
+    "deluxe edition",
+    "special edition",
+    "standard edition",
+    "ultimate edition",
+    "collectors edition",
+    "switch edition",
+    "goty edition",
+    "for nintendo switch",
+    "goty",
+];
+
+/// Tokens too common to narrow down the candidates on their own.
+const STOPWORDS: &[&str] = &["the", "of", "a", "an", "and", "in", "on", "to", "for"];
+
+/// A titledb application that might be the game a file contains.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct MatchCandidate {
+    pub title_id: String,
+    pub name: String,
+    /// Between 0 and 1, where 1 means the normalized names are identical.
+    pub score: f64,
+}
+
+/// Reduces a game name to what identifies the game: lowercase ASCII words
+/// without punctuation, trademark signs, parenthesized tags such as regions,
+/// or a trailing edition suffix.
+pub fn normalize_name(name: &str) -> String {
+    let mut stripped = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    for c in name.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '™' | '®' | '©' => {}
+            '&' if depth == 0 => stripped.push_str(" and "),
+            '\'' | '’' => {}
+            _ if depth == 0 => stripped.push(c),
+            _ => {}
+        }
+    }
+
+    let ascii = deunicode::deunicode(&stripped).to_lowercase();
+    let words: Vec<&str> = ascii
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let mut normalized = words.join(" ");
+
+    // Strip edition suffixes repeatedly ("... Deluxe Edition GOTY"), but never
+    // down to nothing
+    while let Some(rest) = EDITION_SUFFIXES.iter().find_map(|suffix| {
+        normalized
+            .strip_suffix(suffix)
+            .filter(|rest| rest.ends_with(' '))
+            .map(|rest| rest.trim_end().to_string())
+    }) {
+        normalized = rest;
+    }
+    normalized
+}
+
+/// Similarity of two normalized names: 1 only when they are equal, otherwise
+/// the mean of the edit distance ratio and the bigram overlap, which is more
+/// forgiving of reordered or extra words.
+pub fn score(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let similarity = (strsim::normalized_levenshtein(a, b) + strsim::sorensen_dice(a, b)) / 2.0;
+    similarity.min(0.99)
+}
+
+fn tokens(normalized: &str) -> impl Iterator<Item = &str> {
+    normalized
+        .split(' ')
+        .filter(|t| !t.is_empty() && !STOPWORDS.contains(t))
+}
+
+/// Normalized names of the titledb applications, with a token index so a
+/// lookup only scores titles sharing at least one word with the query.
+#[derive(Default)]
+pub struct NameIndex {
+    entries: Vec<(String, String)>, // (normalized name, title ID)
+    tokens: HashMap<String, Vec<u32>>,
+}
+
+impl NameIndex {
+    pub fn build(titles: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut index = NameIndex::default();
+        for (title_id, name) in titles {
+            let normalized = normalize_name(&name);
+            if normalized.is_empty() {
+                continue;
+            }
+            let pos = index.entries.len() as u32;
+            for token in tokens(&normalized) {
+                let postings = index.tokens.entry(token.to_string()).or_default();
+                if postings.last() != Some(&pos) {
+                    postings.push(pos);
+                }
+            }
+            index.entries.push((normalized, title_id));
+        }
+        index.entries.shrink_to_fit();
+        index.tokens.shrink_to_fit();
+        index
+    }
+
+    /// The best scoring title IDs for `name`, highest first.
+    pub fn search(&self, name: &str, limit: usize) -> Vec<(String, f64)> {
+        let query = normalize_name(name);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut positions: Vec<u32> = tokens(&query)
+            .filter_map(|t| self.tokens.get(t))
+            .flatten()
+            .copied()
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+        if positions.is_empty() && tokens(&query).next().is_none() {
+            // Nothing but stopwords, compare against everything
+            positions = (0..self.entries.len() as u32).collect();
+        }
+
+        let mut results: Vec<(String, f64)> = positions
+            .into_iter()
+            .map(|pos| {
+                let (normalized, title_id) = &self.entries[pos as usize];
+                (title_id.clone(), score(&query, normalized))
+            })
+            .collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results.truncate(limit);
+        results
+    }
+}
+
+/// Confirmed title IDs for files whose names carry none, kept in
+/// `data_dir/title_matches.json` and keyed by the path relative to
+/// `games_dir`. A `null` entry records that the file has no titledb entry,
+/// which stops it from being matched automatically.
+#[derive(Clone)]
+pub struct TitleMatches {
+    path: PathBuf,
+    entries: HashMap<String, Option<String>>,
+}
+
+impl TitleMatches {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join("title_matches.json"),
+            entries: HashMap::new(),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        if !self.path.exists() {
+            return;
+        }
+        info!("Loading title matches from {:?}", self.path);
+        if let Some(data) = jsonfile::load::<HashMap<String, Option<String>>>(&self.path).await {
+            self.entries = data
+                .into_iter()
+                .map(|(k, v)| (normalize_key(&k), v.map(|id| id.to_uppercase())))
+                .collect();
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        jsonfile::save_pretty(&self.path, &self.entries).await
+    }
+
+    /// `Some(None)` when the user decided the file has no title ID.
+    pub fn get(&self, relative_path: &str) -> Option<Option<&str>> {
+        self.entries
+            .get(&normalize_key(relative_path))
+            .map(|id| id.as_deref())
+    }
+
+    pub fn all(&self) -> &HashMap<String, Option<String>> {
+        &self.entries
+    }
+
+    pub async fn set(
+        &mut self,
+        relative_path: &str,
+        title_id: Option<String>,
+    ) -> std::io::Result<()> {
+        self.entries.insert(
+            normalize_key(relative_path),
+            title_id.map(|id| id.to_uppercase()),
+        );
+        self.save().await
+    }
+
+    pub async fn remove(&mut self, relative_path: &str) -> std::io::Result<bool> {
+        let removed = self.entries.remove(&normalize_key(relative_path)).is_some();
+        if removed {
+            self.save().await?;
+        }
+        Ok(removed)
+    }
+
+    /// Moves the decision about a file to its new path. Returns whether
+    /// there was one.
+    pub async fn rename(&mut self, from: &str, to: &str) -> std::io::Result<bool> {
+        let Some(title_id) = self.entries.remove(&normalize_key(from)) else {
+            return Ok(false);
+        };
+        self.entries.insert(normalize_key(to), title_id);
+        self.save().await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("Celeste"), "celeste");
+        assert_eq!(normalize_name("Pokémon™ Sword"), "pokemon sword");
+        assert_eq!(
+            normalize_name("The Witcher 3: Wild Hunt – Complete Edition"),
+            "the witcher 3 wild hunt"
+        );
+        assert_eq!(normalize_name("Baba Is You (USA) (En,Fr)"), "baba is you");
+        assert_eq!(
+            normalize_name("Ori & the Blind Forest"),
+            "ori and the blind forest"
+        );
+        assert_eq!(normalize_name("Assassin's Creed"), "assassins creed");
+        // A name that is only a suffix is left alone
+        assert_eq!(normalize_name("GOTY"), "goty");
+    }
+
+    #[test]
+    fn test_name_index_search() {
+        let index = NameIndex::build([
+            ("01002B30028F6000".to_string(), "Celeste".to_string()),
+            (
+                "0100000000010000".to_string(),
+                "Celeste Classic".to_string(),
+            ),
+            ("0100000000020000".to_string(), "Hollow Knight".to_string()),
+        ]);
+
+        let results = index.search("celeste", 5);
+        assert_eq!(results[0], ("01002B30028F6000".to_string(), 1.0));
+        assert_eq!(results.len(), 2);
+        assert!(results[1].1 < 0.9);
+
+        let results = index.search("Hollow Night", 5);
+        assert_eq!(results[0].0, "0100000000020000");
+        assert!(results[0].1 > 0.7 && results[0].1 < 1.0);
+
+        assert!(index.search("Unrelated", 5).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_title_matches_persist() {
+        let tmp = tempdir().unwrap();
+        let mut matches = TitleMatches::new(tmp.path());
+        matches
+            .set("Celeste.nsp", Some("01002b30028f6000".to_string()))
+            .await
+            .unwrap();
+        matches.set("/Homebrew\\Tool.nsp", None).await.unwrap();
+
+        let mut reloaded = TitleMatches::new(tmp.path());
+        reloaded.load().await;
+        assert_eq!(reloaded.get("Celeste.nsp"), Some(Some("01002B30028F6000")));
+        assert_eq!(reloaded.get("Homebrew/Tool.nsp"), Some(None));
+        assert_eq!(reloaded.get("Other.nsp"), None);
+
+        assert!(
+            reloaded
+                .rename("Celeste.nsp", "Games/Celeste.nsp")
+                .await
+                .unwrap()
+        );
+        assert!(!reloaded.rename("Celeste.nsp", "Other.nsp").await.unwrap());
+        assert_eq!(reloaded.get("Celeste.nsp"), None);
+        assert_eq!(
+            reloaded.get("Games/Celeste.nsp"),
+            Some(Some("01002B30028F6000"))
+        );
+
+        assert!(reloaded.remove("Games/Celeste.nsp").await.unwrap());
+        assert!(!reloaded.remove("Games/Celeste.nsp").await.unwrap());
+    }
+}
@@ -1,114 +1,433 @@
+use crate::catalog::{CatalogIndex, SearchMode};
+use crate::matcher::{MatchCandidate, NameIndex, TitleMatches};
+use crate::overrides::{Overrides, TitleOverride, is_title_id};
+use crate::sources::{CatalogueSource, MergeRules, MetadataSource, SourceId};
+use crate::titledb::{TitleInfo, read_titles};
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
 use tracing::{info, warn};
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
-pub struct TitleInfo {
-    pub id: String,
-    pub name: Option<String>,
-    pub icon_url: Option<String>,
-    pub banner_url: Option<String>,
-    pub category: Option<Vec<String>>,
-    pub description: Option<String>,
-    pub publisher: Option<String>,
-}
-
-pub struct MetadataProvider {
-    pub data_dir: PathBuf,
+/// A titledb region/language pair such as `US.en`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
     pub region: String,
     pub language: String,
-    titles: HashMap<String, TitleInfo>,
-    versions: HashMap<String, HashMap<String, String>>, // TitleID -> {Version: Date}
 }
 
-impl MetadataProvider {
-    pub async fn new(data_dir: PathBuf, region: String, language: String) -> Self {
-        Self {
-            data_dir,
-            region,
-            language,
-            titles: HashMap::new(),
-            versions: HashMap::new(),
+impl Locale {
+    pub fn filename(&self) -> String {
+        format!("{}.{}.json", self.region, self.language)
+    }
+}
+
+impl std::fmt::Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.region, self.language)
+    }
+}
+
+impl std::str::FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (region, language) = s
+            .trim()
+            .split_once(['.', '_', '-'])
+            .ok_or_else(|| format!("Invalid locale '{}', expected REGION.lang", s))?;
+        if region.is_empty() || language.is_empty() {
+            return Err(format!("Invalid locale '{}', expected REGION.lang", s));
+        }
+        Ok(Self {
+            region: region.to_uppercase(),
+            language: language.to_lowercase(),
+        })
+    }
+}
+
+/// Content meta type as published in titledb's `cnmts.json` (`titleType`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TitleKind {
+    Application,
+    Patch,
+    AddOnContent,
+}
+
+impl TitleKind {
+    fn from_meta_type(value: u8) -> Option<Self> {
+        match value {
+            0x80 => Some(Self::Application),
+            0x81 => Some(Self::Patch),
+            0x82 => Some(Self::AddOnContent),
+            _ => None,
         }
     }
 
-    pub async fn init(&mut self) {
-        self.load_local_data().await;
+    /// Derives the kind from the ID layout: applications end in `000` with
+    /// bit 12 clear, their update is `base | 0x800` and DLC are numbered
+    /// from `(base ^ 0x1000) + 1`.
+    pub fn from_title_id(title_id: &str) -> Option<Self> {
+        let id = u64::from_str_radix(title_id, 16).ok()?;
+        if id & 0x1000 != 0 {
+            Some(Self::AddOnContent)
+        } else if id & 0xFFF == 0x800 {
+            Some(Self::Patch)
+        } else if id & 0xFFF == 0 {
+            Some(Self::Application)
+        } else {
+            None
+        }
     }
+}
+
+#[derive(Debug, Clone)]
+struct ContentMeta {
+    kind: TitleKind,
+    application_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CnmtRecord {
+    #[serde(rename = "titleType")]
+    title_type: Option<u8>,
+    #[serde(rename = "otherApplicationId")]
+    other_application_id: Option<String>,
+}
+
+/// The titledb files parsed into memory. Built in one go by
+/// [`TitleDb::load`] and never modified afterwards.
+#[derive(Default)]
+struct TitleDb {
+    titles: HashMap<String, TitleInfo>,
+    versions: HashMap<String, HashMap<String, String>>, // TitleID -> {Version: Date}
+    cnmts: HashMap<String, ContentMeta>,
+    dlcs: HashMap<String, Vec<String>>, // Base TitleID -> DLC TitleIDs
+}
 
-    async fn load_local_data(&mut self) {
-        let titles_path = self
-            .data_dir
-            .join("titledb")
-            .join(format!("{}.{}.json", self.region, self.language));
-        if titles_path.exists() {
+/// Search indexes over the titles of every source, by their merged names.
+/// Rebuilt by [`MetadataStore::reindex`] whenever a source changes.
+#[derive(Default)]
+struct TitleIndexes {
+    names: NameIndex, // Applications only
+    catalog: CatalogIndex,
+}
+
+/// An immutable snapshot of every metadata source plus the user's
+/// overrides, combined according to the configured [`MergeRules`]. Cheap to
+/// clone; obtain the current one from [`MetadataStore::snapshot`].
+#[derive(Clone)]
+pub struct MetadataProvider {
+    db: Arc<TitleDb>,
+    overrides: Arc<Overrides>,
+    catalogue: Arc<CatalogueSource>,
+    remote: Arc<CatalogueSource>,
+    rules: Arc<MergeRules>,
+    matches: Arc<TitleMatches>,
+    indexes: Arc<TitleIndexes>,
+    /// Minimum score for a name match to be accepted without confirmation.
+    match_threshold: f64,
+}
+
+/// Owns the current [`MetadataProvider`] snapshot. Loads and syncs build a
+/// new database off to the side and swap it in, so readers never wait on
+/// file or network I/O.
+pub struct MetadataStore {
+    pub data_dir: PathBuf,
+    pub locales: Vec<Locale>,
+    /// Self-hosted service publishing a catalogue in the same format as
+    /// `catalogue.json`, fetched on every sync.
+    remote_url: Option<String>,
+    current: watch::Sender<Arc<MetadataProvider>>,
+    /// Keep detail fields only for titles in the library (see `library_ids`).
+    library_only_details: bool,
+    library_ids: RwLock<Arc<HashSet<String>>>,
+    /// Set while a reload for new library IDs waits to run.
+    library_reload_queued: AtomicBool,
+    sync_lock: tokio::sync::Mutex<()>,
+    overrides_lock: tokio::sync::Mutex<()>,
+    matches_lock: tokio::sync::Mutex<()>,
+    index_lock: tokio::sync::Mutex<()>,
+}
+
+impl TitleDb {
+    async fn load(
+        data_dir: &Path,
+        locales: &[Locale],
+        library: Option<Arc<HashSet<String>>>,
+    ) -> Self {
+        let mut db = TitleDb::default();
+
+        // Merge the configured locales, each field from the first one that has it
+        for locale in locales {
+            let titles_path = data_dir.join("titledb").join(locale.filename());
+            if !titles_path.exists() {
+                continue;
+            }
             info!("Loading local titles database from {:?}", titles_path);
-            let content = tokio::fs::read_to_string(&titles_path)
-                .await
-                .unwrap_or_default();
-            if !content.is_empty() {
-                let titles = tokio::task::spawn_blocking(move || {
-                    let mut map = HashMap::new();
-                    if let Ok(data) =
-                        serde_json::from_str::<HashMap<String, serde_json::Value>>(&content)
-                    {
-                        for (id, val) in data {
-                            let info = TitleInfo {
-                                id: id.clone(),
-                                name: val
-                                    .get("name")
-                                    .and_then(|v| v.as_str())
-                                    .map(|s| s.to_string()),
-                                icon_url: val
-                                    .get("iconUrl")
-                                    .and_then(|v| v.as_str())
-                                    .map(|s| s.to_string()),
-                                banner_url: val
-                                    .get("bannerUrl")
-                                    .and_then(|v| v.as_str())
-                                    .map(|s| s.to_string()),
-                                category: val.get("category").and_then(|v| v.as_array()).map(|a| {
-                                    a.iter()
-                                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                                        .collect()
-                                }),
-                                description: val
-                                    .get("description")
-                                    .and_then(|v| v.as_str())
-                                    .map(|s| s.to_string()),
-                                publisher: val
-                                    .get("publisher")
-                                    .and_then(|v| v.as_str())
-                                    .map(|s| s.to_string()),
-                            };
-                            map.insert(id.to_uppercase(), info);
+            let library = library.clone();
+            let path = titles_path.clone();
+            let locale_titles = match tokio::task::spawn_blocking(move || {
+                let file = std::fs::File::open(&path).map_err(serde_json::Error::io)?;
+                read_titles(file, library.as_deref())
+            })
+            .await
+            {
+                Ok(Ok(titles)) => titles,
+                Ok(Err(e)) => {
+                    warn!("Failed to parse {:?}: {}", titles_path, e);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to load {:?}: {}", titles_path, e);
+                    continue;
+                }
+            };
+            let locale_name = locale.to_string();
+            for (id, mut info) in locale_titles {
+                match db.titles.get_mut(&id) {
+                    Some(existing) => {
+                        if existing.fill_from(info) {
+                            existing.fallback_locales.push(locale_name.clone());
                         }
                     }
-                    map
-                })
-                .await
-                .unwrap_or_default();
-                self.titles = titles;
+                    None => {
+                        info.locale = Some(locale_name.clone());
+                        db.titles.insert(id, info);
+                    }
+                }
             }
         }
 
-        let versions_path = self.data_dir.join("titledb").join("versions.json");
+        let versions_path = data_dir.join("titledb").join("versions.json");
         if versions_path.exists() {
             info!("Loading local versions database from {:?}", versions_path);
-            if let Ok(content) = tokio::fs::read_to_string(&versions_path).await
-                && let Ok(data) =
-                    serde_json::from_str::<HashMap<String, HashMap<String, String>>>(&content)
-            {
-                self.versions = data;
+            let versions = tokio::task::spawn_blocking(move || {
+                std::fs::File::open(&versions_path).ok().and_then(|file| {
+                    serde_json::from_reader::<_, HashMap<String, HashMap<String, String>>>(
+                        std::io::BufReader::new(file),
+                    )
+                    .ok()
+                })
+            })
+            .await;
+            if let Ok(Some(data)) = versions {
+                db.versions = data;
+            }
+        }
+
+        let cnmts_path = data_dir.join("titledb").join("cnmts.json");
+        if cnmts_path.exists() {
+            info!("Loading local content meta database from {:?}", cnmts_path);
+            let cnmts = tokio::task::spawn_blocking(move || {
+                std::fs::File::open(&cnmts_path).ok().map(read_cnmts)
+            })
+            .await;
+            if let Ok(Some((cnmts, dlcs))) = cnmts {
+                db.cnmts = cnmts;
+                db.dlcs = dlcs;
+            }
+        }
+
+        db
+    }
+}
+
+impl MetadataStore {
+    pub fn new(data_dir: PathBuf, locales: Vec<Locale>) -> Self {
+        let empty = MetadataProvider {
+            db: Arc::new(TitleDb::default()),
+            overrides: Arc::new(Overrides::new(&data_dir)),
+            catalogue: Arc::new(CatalogueSource::default()),
+            remote: Arc::new(CatalogueSource::default()),
+            rules: Arc::new(MergeRules::default()),
+            matches: Arc::new(TitleMatches::new(&data_dir)),
+            indexes: Arc::default(),
+            match_threshold: 0.9,
+        };
+        Self {
+            data_dir,
+            locales,
+            remote_url: None,
+            current: watch::Sender::new(Arc::new(empty)),
+            library_only_details: false,
+            library_ids: RwLock::new(Arc::new(HashSet::new())),
+            library_reload_queued: AtomicBool::new(false),
+            sync_lock: tokio::sync::Mutex::new(()),
+            overrides_lock: tokio::sync::Mutex::new(()),
+            matches_lock: tokio::sync::Mutex::new(()),
+            index_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn with_sources(self, rules: MergeRules) -> Self {
+        self.swap(|p| p.rules = Arc::new(rules));
+        self
+    }
+
+    pub fn with_remote_url(mut self, url: Option<String>) -> Self {
+        self.remote_url = url;
+        self
+    }
+
+    /// Name matches scoring at least `threshold` (0 to 1) assign a title ID
+    /// to files without one; weaker ones wait for the user to confirm.
+    pub fn with_match_threshold(self, threshold: f64) -> Self {
+        self.swap(|p| p.match_threshold = threshold);
+        self
+    }
+
+    /// Only keep descriptions, screenshots and other detail fields for the
+    /// titles registered through [`set_library_ids`](Self::set_library_ids),
+    /// trading completeness of the catalogue for a much smaller footprint.
+    pub fn with_library_only_details(mut self, enabled: bool) -> Self {
+        self.library_only_details = enabled;
+        self
+    }
+
+    pub fn library_only_details(&self) -> bool {
+        self.library_only_details
+    }
+
+    /// Records the title IDs present in the library. Returns whether the set
+    /// changed, in which case a [`reload`](Self::reload) picks it up.
+    pub fn set_library_ids(&self, ids: HashSet<String>) -> bool {
+        let mut current = self.library_ids.write().unwrap();
+        if **current == ids {
+            return false;
+        }
+        *current = Arc::new(ids);
+        true
+    }
+
+    /// Registers title IDs that joined the library after the initial scan.
+    /// When any are new, a reload runs in the background to load their
+    /// details; IDs added while one is queued are picked up by it.
+    pub fn add_library_ids(self: &Arc<Self>, ids: impl IntoIterator<Item = String>) {
+        if !self.library_only_details {
+            return;
+        }
+        {
+            let mut current = self.library_ids.write().unwrap();
+            let new: Vec<String> = ids.into_iter().filter(|id| !current.contains(id)).collect();
+            if new.is_empty() {
+                return;
+            }
+            Arc::make_mut(&mut current).extend(new);
+        }
+        if self.library_reload_queued.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            self.library_reload_queued.store(false, Ordering::SeqCst);
+            return;
+        };
+        let store = self.clone();
+        handle.spawn(async move {
+            // Serialized with syncs, so an older reload can't publish last
+            let _guard = store.sync_lock.lock().await;
+            store.library_reload_queued.store(false, Ordering::SeqCst);
+            store.reload().await;
+        });
+    }
+
+    pub fn snapshot(&self) -> Arc<MetadataProvider> {
+        self.current.borrow().clone()
+    }
+
+    /// Notifies the receiver every time a new snapshot is published.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<MetadataProvider>> {
+        self.current.subscribe()
+    }
+
+    fn swap(&self, update: impl FnOnce(&mut MetadataProvider)) {
+        self.current.send_modify(|current| {
+            let mut next = MetadataProvider::clone(current);
+            update(&mut next);
+            *current = Arc::new(next);
+        });
+    }
+
+    pub async fn init(&self) {
+        {
+            let _guard = self.overrides_lock.lock().await;
+            let mut overrides = Overrides::new(&self.data_dir);
+            overrides.load().await;
+            self.swap(|p| p.overrides = Arc::new(overrides));
+        }
+        {
+            let _guard = self.matches_lock.lock().await;
+            let mut matches = TitleMatches::new(&self.data_dir);
+            matches.load().await;
+            self.swap(|p| p.matches = Arc::new(matches));
+        }
+        self.reload().await;
+    }
+
+    /// Re-reads titledb and the catalogue files from disk and publishes them
+    /// as a new snapshot.
+    pub async fn reload(&self) {
+        let library = self
+            .library_only_details
+            .then(|| self.library_ids.read().unwrap().clone());
+        let db = TitleDb::load(&self.data_dir, &self.locales, library).await;
+        let catalogue = CatalogueSource::load(&[
+            &self.data_dir.join("catalogue.json"),
+            &self.data_dir.join("catalogue.toml"),
+        ])
+        .await;
+        let remote = CatalogueSource::load(&[&self.data_dir.join("remote.json")]).await;
+        self.swap(|p| {
+            p.db = Arc::new(db);
+            p.catalogue = Arc::new(catalogue);
+            p.remote = Arc::new(remote);
+        });
+        self.reindex().await;
+    }
+
+    /// Rebuilds the search indexes from the current snapshot, so titles
+    /// that only the catalogue, the remote service or an override knows
+    /// can be searched and matched too.
+    async fn reindex(&self) {
+        let _guard = self.index_lock.lock().await;
+        let meta = self.snapshot();
+        let indexes = tokio::task::spawn_blocking(move || meta.build_indexes())
+            .await
+            .unwrap_or_default();
+        self.swap(|p| p.indexes = Arc::new(indexes));
+    }
+
+    /// Fetches the remote catalogue, if one is configured, and reloads.
+    pub async fn sync_remote(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let _guard = self.sync_lock.lock().await;
+        self.download_remote().await?;
+        self.reload().await;
+        Ok(())
+    }
+
+    async fn download_remote(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(url) = self.remote_url.as_deref() else {
+            return Ok(());
+        };
+        info!("Syncing remote metadata from {}...", url);
+        match reqwest::get(url).await {
+            Ok(resp) if resp.status().is_success() => {
+                tokio::fs::create_dir_all(&self.data_dir).await?;
+                download_to(resp, &self.data_dir.join("remote.json")).await?;
             }
+            Ok(resp) => warn!("Failed to sync {}: status {}", url, resp.status()),
+            Err(e) => warn!("Failed to sync {}: {}", url, e),
         }
+        Ok(())
     }
 
-    pub async fn sync(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn sync(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let _guard = self.sync_lock.lock().await;
         let titledb_dir = self.data_dir.join("titledb");
         if !titledb_dir.exists() {
             info!("Creating titledb directory: {:?}", titledb_dir);
@@ -117,66 +436,803 @@ impl MetadataProvider {
 
         let client = reqwest::Client::new();
 
-        // Sync versions.json
-        info!("Syncing versions.json...");
-        match client
-            .get("https://raw.githubusercontent.com/blawar/titledb/master/versions.json")
-            .send()
-            .await
-        {
-            Ok(resp) if resp.status().is_success() => {
-                let mut file = File::create(titledb_dir.join("versions.json")).await?;
-                let mut stream = resp.bytes_stream();
-                while let Some(item) = stream.next().await {
-                    file.write_all(&item?).await?;
+        // Sync versions.json and cnmts.json
+        for name in ["versions.json", "cnmts.json"] {
+            info!("Syncing {}...", name);
+            match client
+                .get(format!(
+                    "https://raw.githubusercontent.com/blawar/titledb/master/{}",
+                    name
+                ))
+                .send()
+                .await
+            {
+                Ok(resp) if resp.status().is_success() => {
+                    download_to(resp, &titledb_dir.join(name)).await?;
                 }
+                Ok(resp) => warn!("Failed to sync {}: status {}", name, resp.status()),
+                Err(e) => warn!("Failed to sync {}: {}", name, e),
             }
-            Ok(resp) => warn!("Failed to sync versions.json: status {}", resp.status()),
-            Err(e) => warn!("Failed to sync versions.json: {}", e),
-        }
-
-        // Try region-specific first, then titles.json
-        let filename = format!("{}.{}.json", self.region, self.language);
-        let urls = vec![
-            format!(
-                "https://raw.githubusercontent.com/blawar/titledb/master/{}",
-                filename
-            ),
-            "https://raw.githubusercontent.com/blawar/titledb/master/titles.json".to_string(),
-        ];
-
-        for url in urls {
-            info!("Syncing titles from {}...", url);
-            match client.get(&url).send().await {
-                Ok(resp) if resp.status().is_success() => {
-                    let dest = titledb_dir.join(&filename);
-                    let mut file = File::create(dest).await?;
-                    let mut stream = resp.bytes_stream();
-                    while let Some(item) = stream.next().await {
-                        file.write_all(&item?).await?;
+        }
+
+        // Try each locale's file first, then titles.json
+        for locale in &self.locales {
+            let filename = locale.filename();
+            let urls = vec![
+                format!(
+                    "https://raw.githubusercontent.com/blawar/titledb/master/{}",
+                    filename
+                ),
+                "https://raw.githubusercontent.com/blawar/titledb/master/titles.json".to_string(),
+            ];
+
+            for url in urls {
+                info!("Syncing titles from {}...", url);
+                match client.get(&url).send().await {
+                    Ok(resp) if resp.status().is_success() => {
+                        download_to(resp, &titledb_dir.join(&filename)).await?;
+                        info!("Successfully synced titles from {}", url);
+                        break;
                     }
-                    info!("Successfully synced titles from {}", url);
-                    break;
+                    Ok(resp) => warn!("Failed to sync from {}: status {}", url, resp.status()),
+                    Err(e) => warn!("Failed to sync from {}: {}", url, e),
                 }
-                Ok(resp) => warn!("Failed to sync from {}: status {}", url, resp.status()),
-                Err(e) => warn!("Failed to sync from {}: {}", url, e),
             }
         }
 
-        self.load_local_data().await;
+        self.download_remote().await?;
+        self.reload().await;
         Ok(())
     }
 
-    pub fn get_title_info(&self, title_id: &str) -> Option<&TitleInfo> {
-        self.titles.get(&title_id.to_uppercase())
+    /// Applies `edit` to the override stored under `key` (starting from an
+    /// empty one), persists it and publishes a new snapshot.
+    pub async fn update_override(
+        &self,
+        key: &str,
+        edit: impl FnOnce(&mut TitleOverride),
+    ) -> std::io::Result<TitleOverride> {
+        let _guard = self.overrides_lock.lock().await;
+        let mut overrides = Overrides::clone(&self.snapshot().overrides);
+        let mut value = overrides.get(key).cloned().unwrap_or_default();
+        edit(&mut value);
+        overrides.set(key, value.clone()).await?;
+        self.swap(|p| p.overrides = Arc::new(overrides));
+        if is_title_id(key.trim()) {
+            self.reindex().await;
+        }
+        Ok(value)
+    }
+
+    pub async fn remove_override(&self, key: &str) -> std::io::Result<Option<TitleOverride>> {
+        let _guard = self.overrides_lock.lock().await;
+        let mut overrides = Overrides::clone(&self.snapshot().overrides);
+        let removed = overrides.remove(key).await?;
+        if removed.is_some() {
+            self.swap(|p| p.overrides = Arc::new(overrides));
+            if is_title_id(key.trim()) {
+                self.reindex().await;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Records the user's decision about which title a file is (`None` for
+    /// none at all), persists it and publishes a new snapshot.
+    pub async fn set_title_match(
+        &self,
+        relative_path: &str,
+        title_id: Option<String>,
+    ) -> std::io::Result<()> {
+        let _guard = self.matches_lock.lock().await;
+        let mut matches = TitleMatches::clone(&self.snapshot().matches);
+        matches.set(relative_path, title_id).await?;
+        self.swap(|p| p.matches = Arc::new(matches));
+        Ok(())
     }
 
-    pub fn get_latest_version(&self, title_id: &str) -> Option<String> {
-        let versions = self.versions.get(&title_id.to_lowercase())?;
+    pub async fn remove_title_match(&self, relative_path: &str) -> std::io::Result<bool> {
+        let _guard = self.matches_lock.lock().await;
+        let mut matches = TitleMatches::clone(&self.snapshot().matches);
+        let removed = matches.remove(relative_path).await?;
+        if removed {
+            self.swap(|p| p.matches = Arc::new(matches));
+        }
+        Ok(removed)
+    }
+
+    /// Moves the override and title match of a file along with it, so they
+    /// still apply once the library picks it up at `to`.
+    pub async fn rename_path(&self, from: &str, to: &str) -> std::io::Result<()> {
+        {
+            let _guard = self.overrides_lock.lock().await;
+            let mut overrides = Overrides::clone(&self.snapshot().overrides);
+            if overrides.rename(from, to).await? {
+                self.swap(|p| p.overrides = Arc::new(overrides));
+            }
+        }
+        let _guard = self.matches_lock.lock().await;
+        let mut matches = TitleMatches::clone(&self.snapshot().matches);
+        if matches.rename(from, to).await? {
+            self.swap(|p| p.matches = Arc::new(matches));
+        }
+        Ok(())
+    }
+}
+
+/// Streams a response into `dest` through a temporary file, so a concurrent
+/// load never sees a partially written database.
+async fn download_to(
+    resp: reqwest::Response,
+    dest: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let part = dest.with_extension("json.part");
+    let mut file = File::create(&part).await?;
+    let mut stream = resp.bytes_stream();
+    while let Some(item) = stream.next().await {
+        file.write_all(&item?).await?;
+    }
+    file.flush().await?;
+    drop(file);
+    tokio::fs::rename(&part, dest).await?;
+    Ok(())
+}
+
+impl MetadataSource for TitleDb {
+    fn title_info(&self, title_id: &str) -> Option<TitleInfo> {
+        self.titles.get(&title_id.to_uppercase()).cloned()
+    }
+
+    fn title_ids(&self) -> Vec<&str> {
+        self.titles.keys().map(String::as_str).collect()
+    }
+
+    fn title_name(&self, title_id: &str) -> Option<String> {
+        self.titles.get(&title_id.to_uppercase())?.name.clone()
+    }
+
+    fn versions(&self, title_id: &str) -> Vec<(u64, String)> {
+        self.versions
+            .get(&title_id.to_lowercase())
+            .map(|v| {
+                v.iter()
+                    .filter_map(|(ver, date)| Some((ver.parse().ok()?, date.clone())))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl MetadataProvider {
+    fn source(&self, id: SourceId) -> &dyn MetadataSource {
+        match id {
+            SourceId::Overrides => self.overrides.as_ref(),
+            SourceId::Catalogue => self.catalogue.as_ref(),
+            SourceId::TitleDb => self.db.as_ref(),
+            SourceId::Remote => self.remote.as_ref(),
+        }
+    }
+
+    /// What the sources know about the title itself, merged field by field.
+    fn merged_title_info(&self, id: &str) -> Option<TitleInfo> {
+        let found: Vec<(SourceId, TitleInfo)> = self
+            .rules
+            .sources()
+            .into_iter()
+            .filter_map(|source| Some((source, self.source(source).title_info(id)?)))
+            .collect();
+        self.rules.merge(id, &found)
+    }
+
+    /// Indexes every title the configured sources know under the name they
+    /// merge to. Parent names are not inherited, as in titledb itself.
+    fn build_indexes(&self) -> TitleIndexes {
+        let mut ids: Vec<&str> = self
+            .rules
+            .sources()
+            .into_iter()
+            .flat_map(|source| self.source(source).title_ids())
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let titles: Vec<(String, Option<String>)> = ids
+            .into_iter()
+            .map(|id| {
+                let name = self
+                    .rules
+                    .order_for("name")
+                    .iter()
+                    .find_map(|source| self.source(*source).title_name(id));
+                (id.to_string(), name)
+            })
+            .collect();
+        let names = NameIndex::build(titles.iter().filter_map(|(id, name)| {
+            let name = name.clone()?;
+            (self.get_title_kind(id) == Some(TitleKind::Application)).then(|| (id.clone(), name))
+        }));
+        TitleIndexes {
+            names,
+            catalog: CatalogIndex::build(titles),
+        }
+    }
+
+    pub fn get_title_info(&self, title_id: &str) -> Option<TitleInfo> {
+        let id = title_id.to_uppercase();
+        let own = self.merged_title_info(&id);
+
+        // Updates and DLC inherit what they lack from their parent game.
+        // Base games have no parent, so one hop is enough; going further
+        // would loop on a malformed parent chain.
+        if let Some(parent_id) = self.get_base_title_id(&id)
+            && parent_id != id
+            && let Some(parent) = self.merged_title_info(&parent_id)
+        {
+            // A name the user set is used as is, not prefixed like DLC names
+            let pinned_name = self
+                .overrides
+                .get(&id)
+                .and_then(|ov| ov.name.clone())
+                .filter(|name| own.as_ref().and_then(|i| i.name.as_ref()) == Some(name));
+            let mut info = inherit_from_parent(&id, own, parent, self.get_title_kind(&id));
+            if pinned_name.is_some() {
+                info.name = pinned_name;
+            }
+            return Some(info);
+        }
+        own
+    }
+
+    /// Whether any source besides titledb knows something about the title.
+    pub fn has_custom_info(&self, title_id: &str) -> bool {
+        self.rules
+            .sources()
+            .into_iter()
+            .filter(|id| *id != SourceId::TitleDb)
+            .any(|id| self.source(id).title_info(title_id).is_some())
+    }
+
+    /// Like [`get_title_info`](Self::get_title_info), but also applies an
+    /// override registered for the file itself. Path overrides are more
+    /// specific than title ID ones and therefore win.
+    pub fn resolve_title_info(
+        &self,
+        title_id: Option<&str>,
+        relative_path: &str,
+    ) -> Option<TitleInfo> {
+        let info = title_id.and_then(|tid| self.get_title_info(tid));
+        match self.overrides.get(relative_path) {
+            Some(ov) => {
+                let mut info = info.unwrap_or_else(|| TitleInfo {
+                    id: title_id.map(|t| t.to_uppercase()).unwrap_or_default(),
+                    ..Default::default()
+                });
+                ov.apply(&mut info);
+                Some(info)
+            }
+            None => info,
+        }
+    }
+
+    pub fn get_title_kind(&self, title_id: &str) -> Option<TitleKind> {
+        match self.db.cnmts.get(&title_id.to_uppercase()) {
+            Some(meta) => Some(meta.kind),
+            None => TitleKind::from_title_id(title_id),
+        }
+    }
+
+    /// Returns the application an update or DLC belongs to, preferring the
+    /// relationship recorded in `cnmts.json` over the ID arithmetic.
+    pub fn get_base_title_id(&self, title_id: &str) -> Option<String> {
+        let id = title_id.to_uppercase();
+        if let Some(meta) = self.db.cnmts.get(&id) {
+            return match meta.kind {
+                TitleKind::Application => Some(id),
+                _ => meta.application_id.clone(),
+            };
+        }
+        let value = u64::from_str_radix(&id, 16).ok()?;
+        let base = match TitleKind::from_title_id(&id)? {
+            TitleKind::Application => value,
+            TitleKind::Patch => value & !0xFFF,
+            TitleKind::AddOnContent => (value & !0xFFF) ^ 0x1000,
+        };
+        Some(format!("{:016X}", base))
+    }
+
+    /// All DLC known to belong to `base_id`, sorted by title ID.
+    pub fn get_dlc_ids(&self, base_id: &str) -> Vec<String> {
+        self.db
+            .dlcs
+            .get(&base_id.to_uppercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn overrides(&self) -> &Overrides {
+        &self.overrides
+    }
+
+    pub fn title_matches(&self) -> &TitleMatches {
+        &self.matches
+    }
+
+    /// Applications whose names resemble `name`, best first.
+    pub fn match_candidates(&self, name: &str, limit: usize) -> Vec<MatchCandidate> {
+        self.indexes
+            .names
+            .search(name, limit)
+            .into_iter()
+            .map(|(title_id, score)| MatchCandidate {
+                name: self
+                    .get_title_info(&title_id)
+                    .and_then(|info| info.name)
+                    .unwrap_or_default(),
+                title_id,
+                score,
+            })
+            .collect()
+    }
+
+    /// Title IDs from the whole catalogue matching `query`, best first, or
+    /// every title by name when the query is empty. `category` and
+    /// `publisher` are compared case-insensitively, after overrides.
+    pub fn search_catalog(
+        &self,
+        query: &str,
+        mode: SearchMode,
+        category: Option<&str>,
+        publisher: Option<&str>,
+    ) -> Vec<String> {
+        let found = if query.trim().is_empty() {
+            self.indexes.catalog.all()
+        } else {
+            self.indexes.catalog.search(query, mode)
+        };
+        found
+            .into_iter()
+            .map(|pos| self.indexes.catalog.title_id(pos))
+            .filter(|id| {
+                if category.is_none() && publisher.is_none() {
+                    return true;
+                }
+                let info = self.get_title_info(id).unwrap_or_default();
+                category.is_none_or(|wanted| {
+                    info.category
+                        .is_some_and(|c| c.iter().any(|c| c.eq_ignore_ascii_case(wanted)))
+                }) && publisher.is_none_or(|wanted| {
+                    info.publisher
+                        .is_some_and(|p| p.eq_ignore_ascii_case(wanted))
+                })
+            })
+            .collect()
+    }
+
+    /// Works out the title ID of a file whose name carries none: the user's
+    /// recorded decision if there is one, otherwise the best name match if
+    /// it clears the threshold and is not tied with another title. Returns
+    /// the ID with its confidence, 1 for confirmed matches.
+    pub fn infer_title_id(&self, relative_path: &str, name: &str) -> Option<(String, f64)> {
+        if let Some(decision) = self.matches.get(relative_path) {
+            return decision.map(|id| (id.to_string(), 1.0));
+        }
+        let results = self.indexes.names.search(name, 2);
+        let (best_id, best) = results.first()?;
+        let tied = results.get(1).is_some_and(|(_, second)| second == best);
+        (*best >= self.match_threshold && !tied).then(|| (best_id.clone(), *best))
+    }
+
+    /// Known versions of a title with their release dates, oldest first,
+    /// from the first source in the `versions` order that has any.
+    pub fn get_versions(&self, title_id: &str) -> Vec<(u64, String)> {
+        let mut versions = self
+            .rules
+            .order_for("versions")
+            .iter()
+            .map(|id| self.source(*id).versions(title_id))
+            .find(|v| !v.is_empty())
+            .unwrap_or_default();
+        versions.sort();
         versions
-            .keys()
-            .filter_map(|v| v.parse::<u64>().ok())
-            .max()
-            .map(|v| v.to_string())
+    }
+
+    pub fn get_latest_version(&self, title_id: &str) -> Option<String> {
+        self.get_versions(title_id)
+            .last()
+            .map(|(version, _)| version.to_string())
+    }
+}
+
+fn read_cnmts<R: std::io::Read>(
+    reader: R,
+) -> (HashMap<String, ContentMeta>, HashMap<String, Vec<String>>) {
+    let mut cnmts = HashMap::new();
+    let mut dlcs: HashMap<String, Vec<String>> = HashMap::new();
+
+    let Ok(data) = serde_json::from_reader::<_, HashMap<String, HashMap<String, CnmtRecord>>>(
+        std::io::BufReader::new(reader),
+    ) else {
+        warn!("Failed to parse cnmts.json");
+        return (cnmts, dlcs);
+    };
+
+    for (id, records) in data {
+        let id = id.to_uppercase();
+        // Every version of a title shares its type and parent, any record will do
+        let Some(meta) = records.into_values().find_map(|r| {
+            Some(ContentMeta {
+                kind: TitleKind::from_meta_type(r.title_type?)?,
+                application_id: r.other_application_id.map(|a| a.to_uppercase()),
+            })
+        }) else {
+            continue;
+        };
+        if meta.kind == TitleKind::AddOnContent
+            && let Some(ref app) = meta.application_id
+        {
+            dlcs.entry(app.clone()).or_default().push(id.clone());
+        }
+        cnmts.insert(id, meta);
+    }
+
+    for ids in dlcs.values_mut() {
+        ids.sort();
+    }
+    (cnmts, dlcs)
+}
+
+/// Fills an update's or DLC's title info from its parent game. DLC names are
+/// prefixed with the game's name ("Game – Expansion Pass") unless titledb
+/// already includes it.
+fn inherit_from_parent(
+    id: &str,
+    own: Option<TitleInfo>,
+    parent: TitleInfo,
+    kind: Option<TitleKind>,
+) -> TitleInfo {
+    let mut info = own.unwrap_or_else(|| TitleInfo {
+        id: id.to_string(),
+        ..Default::default()
+    });
+
+    if kind == Some(TitleKind::AddOnContent) {
+        if let Some(ref parent_name) = parent.name {
+            info.name = Some(match info.name.take() {
+                Some(n) if n.starts_with(parent_name.as_str()) => n,
+                Some(n) => format!("{} – {}", parent_name, n),
+                None => {
+                    let index = u64::from_str_radix(id, 16).map(|v| v & 0xFFF).unwrap_or(0);
+                    format!("{} – DLC {}", parent_name, index)
+                }
+            });
+        }
+    } else if info.name.is_none() {
+        info.name = parent.name;
+    }
+
+    if info.publisher.is_none() {
+        info.publisher = parent.publisher;
+    }
+    if info.icon_url.is_none() {
+        info.icon_url = parent.icon_url;
+    }
+    if info.banner_url.is_none() {
+        info.banner_url = parent.banner_url;
+    }
+    if info.category.is_none() {
+        info.category = parent.category;
+    }
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_parent_chain_loop() {
+        let tmp = tempdir().unwrap();
+        let titledb = tmp.path().join("titledb");
+        std::fs::create_dir_all(&titledb).unwrap();
+        std::fs::write(
+            titledb.join("US.en.json"),
+            r#"{ "0100000000010800": { "name": "Looping Update" } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            titledb.join("cnmts.json"),
+            r#"{
+                "0100000000010800": { "65536": { "titleType": 129, "otherApplicationId": "0100000000020800" } },
+                "0100000000020800": { "65536": { "titleType": 129, "otherApplicationId": "0100000000010800" } }
+            }"#,
+        )
+        .unwrap();
+
+        let store = MetadataStore::new(tmp.path().to_path_buf(), vec!["US.en".parse().unwrap()]);
+        store.init().await;
+        let meta = store.snapshot();
+        assert!(meta.get_title_info("0100000000010800").is_some());
+        assert!(meta.get_title_info("0100000000020800").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_library_ids_added_later() {
+        let tmp = tempdir().unwrap();
+        let titledb = tmp.path().join("titledb");
+        std::fs::create_dir_all(&titledb).unwrap();
+        std::fs::write(
+            titledb.join("US.en.json"),
+            r#"{ "0100000000010000": { "name": "Game", "description": "Jump." } }"#,
+        )
+        .unwrap();
+
+        let store = Arc::new(
+            MetadataStore::new(tmp.path().to_path_buf(), vec!["US.en".parse().unwrap()])
+                .with_library_only_details(true),
+        );
+        store.init().await;
+        let info = store.snapshot().get_title_info("0100000000010000").unwrap();
+        assert!(info.description.is_none());
+
+        let mut rx = store.subscribe();
+        rx.borrow_and_update();
+        store.add_library_ids(["0100000000010000".to_string()]);
+        tokio::time::timeout(std::time::Duration::from_secs(5), rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        let info = store.snapshot().get_title_info("0100000000010000").unwrap();
+        assert_eq!(info.description.as_deref(), Some("Jump."));
+
+        // Known IDs don't reload again
+        store.add_library_ids(["0100000000010000".to_string()]);
+        assert!(!store.library_reload_queued.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_dlc_linking() {
+        let tmp = tempdir().unwrap();
+        let titledb = tmp.path().join("titledb");
+        std::fs::create_dir_all(&titledb).unwrap();
+        std::fs::write(
+            titledb.join("US.en.json"),
+            r#"{
+                "0100000000010000": { "name": "Game Name", "publisher": "Nintendo" },
+                "0100000000011001": { "name": "Expansion Pass" }
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            titledb.join("cnmts.json"),
+            r#"{
+                "0100000000010000": { "0": { "titleType": 128, "otherApplicationId": "0100000000010800" } },
+                "0100000000010800": { "65536": { "titleType": 129, "otherApplicationId": "0100000000010000" } },
+                "0100000000011001": { "0": { "titleType": 130, "otherApplicationId": "0100000000010000" } },
+                "0100000000011002": { "0": { "titleType": 130, "otherApplicationId": "0100000000010000" } }
+            }"#,
+        )
+        .unwrap();
+
+        let store = MetadataStore::new(tmp.path().to_path_buf(), vec!["US.en".parse().unwrap()]);
+        store.init().await;
+        let meta = store.snapshot();
+
+        assert_eq!(
+            meta.get_dlc_ids("0100000000010000"),
+            vec!["0100000000011001", "0100000000011002"]
+        );
+        assert_eq!(
+            meta.get_base_title_id("0100000000010800").as_deref(),
+            Some("0100000000010000")
+        );
+
+        let dlc = meta.get_title_info("0100000000011001").unwrap();
+        assert_eq!(dlc.name.as_deref(), Some("Game Name – Expansion Pass"));
+        assert_eq!(dlc.publisher.as_deref(), Some("Nintendo"));
+        let unnamed = meta.get_title_info("0100000000011002").unwrap();
+        assert_eq!(unnamed.name.as_deref(), Some("Game Name – DLC 2"));
+        let update = meta.get_title_info("0100000000010800").unwrap();
+        assert_eq!(update.name.as_deref(), Some("Game Name"));
+    }
+
+    #[tokio::test]
+    async fn test_source_chain() {
+        let tmp = tempdir().unwrap();
+        let titledb = tmp.path().join("titledb");
+        std::fs::create_dir_all(&titledb).unwrap();
+        std::fs::write(
+            titledb.join("US.en.json"),
+            r#"{ "0100000000010000": { "name": "Game", "publisher": "Publisher", "iconUrl": "titledb.png" } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            titledb.join("versions.json"),
+            r#"{ "0100000000010000": { "65536": "2020-01-01" } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            tmp.path().join("catalogue.toml"),
+            r#"
+            [05000000000A0000]
+            name = "Homebrew Tool"
+            versions = { "131072" = "2024-01-01" }
+            "#,
+        )
+        .unwrap();
+
+        // A stand-in for a self-hosted metadata service
+        let remote = axum::Router::new().route(
+            "/catalogue.json",
+            axum::routing::get(|| async {
+                axum::Json(serde_json::json!({
+                    "0100000000010000": { "name": "Remote Name", "icon_url": "remote.png", "description": "Remote" }
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, remote).await });
+
+        let store = MetadataStore::new(tmp.path().to_path_buf(), vec!["US.en".parse().unwrap()])
+            .with_sources(MergeRules::parse(
+                "overrides, catalogue, titledb, remote",
+                Some("icon_url = remote, titledb"),
+            ))
+            .with_remote_url(Some(format!("http://{}/catalogue.json", addr)));
+        store.init().await;
+        store.sync_remote().await.unwrap();
+        let meta = store.snapshot();
+
+        // titledb outranks the remote service, except for the icon rule, and
+        // the remote fills what titledb lacks
+        let game = meta.get_title_info("0100000000010000").unwrap();
+        assert_eq!(game.name.as_deref(), Some("Game"));
+        assert_eq!(game.icon_url.as_deref(), Some("remote.png"));
+        assert_eq!(game.description.as_deref(), Some("Remote"));
+        assert_eq!(game.publisher.as_deref(), Some("Publisher"));
+        assert_eq!(
+            meta.get_latest_version("0100000000010000").as_deref(),
+            Some("65536")
+        );
+        assert!(meta.has_custom_info("0100000000010000"));
+
+        let tool = meta.get_title_info("05000000000A0000").unwrap();
+        assert_eq!(tool.name.as_deref(), Some("Homebrew Tool"));
+        assert_eq!(
+            meta.get_latest_version("05000000000A0000").as_deref(),
+            Some("131072")
+        );
+        // Titles only the catalogue knows can be searched and matched
+        assert_eq!(
+            meta.search_catalog("homebrew", SearchMode::Token, None, None),
+            vec!["05000000000A0000"]
+        );
+        assert_eq!(
+            meta.match_candidates("Homebrew Tool", 1)[0].title_id,
+            "05000000000A0000"
+        );
+
+        // Overrides come first in the chain
+        store
+            .update_override("0100000000010000", |ov| ov.name = Some("Mine".to_string()))
+            .await
+            .unwrap();
+        let game = store.snapshot().get_title_info("0100000000010000").unwrap();
+        assert_eq!(game.name.as_deref(), Some("Mine"));
+        assert_eq!(game.publisher.as_deref(), Some("Publisher"));
+
+        // ...and their names are what the indexes find
+        store
+            .update_override("0100000000020000", |ov| {
+                ov.name = Some("Brand New".to_string())
+            })
+            .await
+            .unwrap();
+        let meta = store.snapshot();
+        assert!(
+            meta.search_catalog("game", SearchMode::Token, None, None)
+                .is_empty()
+        );
+        assert_eq!(
+            meta.search_catalog("mine", SearchMode::Token, None, None),
+            vec!["0100000000010000"]
+        );
+        assert_eq!(
+            meta.infer_title_id("Brand New.nsp", "Brand New"),
+            Some(("0100000000020000".to_string(), 1.0))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_locale_fallback_chain() {
+        let tmp = tempdir().unwrap();
+        let titledb = tmp.path().join("titledb");
+        std::fs::create_dir_all(&titledb).unwrap();
+        std::fs::write(
+            titledb.join("US.en.json"),
+            r#"{ "0100000000010000": { "name": "Game", "description": null } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            titledb.join("GB.en.json"),
+            r#"{ "0100000000010000": { "name": "Game (GB)", "description": "From GB" } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            titledb.join("JP.ja.json"),
+            r#"{ "0100000000020000": { "name": "Japan Only" } }"#,
+        )
+        .unwrap();
+
+        let locales = "US.en, GB.en, JP.ja"
+            .split(',')
+            .map(|l| l.parse().unwrap())
+            .collect();
+        let store = MetadataStore::new(tmp.path().to_path_buf(), locales);
+        store.init().await;
+        let meta = store.snapshot();
+
+        let game = meta.get_title_info("0100000000010000").unwrap();
+        assert_eq!(game.name.as_deref(), Some("Game"));
+        assert_eq!(game.description.as_deref(), Some("From GB"));
+        assert_eq!(game.locale.as_deref(), Some("US.en"));
+        assert_eq!(game.fallback_locales, vec!["GB.en"]);
+
+        let jp = meta.get_title_info("0100000000020000").unwrap();
+        assert_eq!(jp.name.as_deref(), Some("Japan Only"));
+        assert_eq!(jp.locale.as_deref(), Some("JP.ja"));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_swap() {
+        let tmp = tempdir().unwrap();
+        let titledb = tmp.path().join("titledb");
+        std::fs::create_dir_all(&titledb).unwrap();
+        let store = MetadataStore::new(tmp.path().to_path_buf(), vec!["US.en".parse().unwrap()]);
+        store.init().await;
+        let before = store.snapshot();
+
+        std::fs::write(
+            titledb.join("US.en.json"),
+            r#"{ "0100000000010000": { "name": "Game" } }"#,
+        )
+        .unwrap();
+        store.reload().await;
+        store
+            .update_override("0100000000020000", |ov| {
+                ov.name = Some("Homebrew".to_string())
+            })
+            .await
+            .unwrap();
+
+        // Snapshots taken earlier are unaffected by later loads and edits
+        assert!(before.get_title_info("0100000000010000").is_none());
+        let after = store.snapshot();
+        assert!(after.get_title_info("0100000000010000").is_some());
+        assert_eq!(
+            after
+                .get_title_info("0100000000020000")
+                .unwrap()
+                .name
+                .as_deref(),
+            Some("Homebrew")
+        );
+    }
+
+    #[test]
+    fn test_title_kind_from_id() {
+        assert_eq!(
+            TitleKind::from_title_id("0100000000010000"),
+            Some(TitleKind::Application)
+        );
+        assert_eq!(
+            TitleKind::from_title_id("0100000000010800"),
+            Some(TitleKind::Patch)
+        );
+        assert_eq!(
+            TitleKind::from_title_id("0100000000011001"),
+            Some(TitleKind::AddOnContent)
+        );
     }
 }
@@ -0,0 +1,485 @@
+use crate::jsonfile;
+use crate::metadata::MetadataProvider;
+use crate::scanner::Game;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// How many applied runs can be undone.
+const LOG_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Name,
+    BaseName,
+    TitleId,
+    BaseTitleId,
+    Version,
+    Category,
+    Publisher,
+    Year,
+    Ext,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("name", Field::Name),
+    ("base_name", Field::BaseName),
+    ("title_id", Field::TitleId),
+    ("base_title_id", Field::BaseTitleId),
+    ("version", Field::Version),
+    ("category", Field::Category),
+    ("publisher", Field::Publisher),
+    ("year", Field::Year),
+    ("ext", Field::Ext),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// A target path pattern relative to `games_dir`, such as
+/// `{category}/{name} [{title_id}][v{version}].{ext}`. `/` separates
+/// folders.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template(Vec<Part>);
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| "Unclosed '{' in template".to_string())?;
+            let name = &rest[start + 1..start + end];
+            let field = FIELDS
+                .iter()
+                .find(|(known, _)| *known == name)
+                .map(|(_, field)| *field)
+                .ok_or_else(|| format!("Unknown template field '{}'", name))?;
+            parts.push(Part::Field(field));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        if !parts.contains(&Part::Field(Field::Ext)) {
+            return Err("Template must contain {ext}".to_string());
+        }
+        Ok(Self(parts))
+    }
+
+    fn uses(&self, fields: &[Field]) -> bool {
+        self.0
+            .iter()
+            .any(|p| matches!(p, Part::Field(f) if fields.contains(f)))
+    }
+
+    /// The path for `game`, or `None` when it lacks a title ID the template
+    /// needs.
+    fn render(&self, game: &Game, meta: &MetadataProvider) -> Option<String> {
+        if game.title_id.is_none()
+            && self.uses(&[Field::TitleId, Field::BaseTitleId, Field::BaseName])
+        {
+            return None;
+        }
+        let base_title_id = game.base_title_id.clone().or(game.title_id.clone());
+        let mut path = String::new();
+        for part in &self.0 {
+            let value = match part {
+                Part::Text(text) => {
+                    path.push_str(text);
+                    continue;
+                }
+                Part::Field(Field::Name) => game.name.clone(),
+                Part::Field(Field::BaseName) => base_title_id
+                    .as_deref()
+                    .and_then(|id| meta.get_title_info(id))
+                    .and_then(|info| info.name)
+                    .unwrap_or_else(|| game.name.clone()),
+                Part::Field(Field::TitleId) => game.title_id.clone().unwrap_or_default(),
+                Part::Field(Field::BaseTitleId) => base_title_id.clone().unwrap_or_default(),
+                Part::Field(Field::Version) => game
+                    .version
+                    .as_deref()
+                    .map(|v| v.trim_start_matches('v').to_string())
+                    .unwrap_or_else(|| "0".to_string()),
+                Part::Field(Field::Category) => game.category.clone(),
+                Part::Field(Field::Publisher) => game
+                    .publisher
+                    .clone()
+                    .unwrap_or_else(|| "Unknown".to_string()),
+                Part::Field(Field::Year) => game
+                    .release_date
+                    .as_deref()
+                    .and_then(|d| d.get(..4))
+                    .unwrap_or("Unknown")
+                    .to_string(),
+                Part::Field(Field::Ext) => game.format.to_lowercase(),
+            };
+            path.push_str(&sanitize(&value));
+        }
+        Some(
+            path.split('/')
+                .map(|c| c.split_whitespace().collect::<Vec<_>>().join(" "))
+                .map(|c| c.trim_end_matches('.').to_string())
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
+
+    /// The path for `game` relative to `games_dir`, or why it has none:
+    /// `no_title_id` or `invalid_path`.
+    pub fn target(
+        &self,
+        game: &Game,
+        meta: &MetadataProvider,
+        games_dir: &Path,
+    ) -> Result<String, &'static str> {
+        let to = self.render(game, meta).ok_or("no_title_id")?;
+        let valid = crate::fileops::library_dir(games_dir, &to).is_some()
+            && Path::new(&to)
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(crate::fileops::valid_file_name);
+        if valid { Ok(to) } else { Err("invalid_path") }
+    }
+}
+
+/// Makes a value safe as part of a file name on any common filesystem.
+fn sanitize(value: &str) -> String {
+    value
+        .replace(':', " -")
+        .chars()
+        .filter(|c| !matches!(c, '/' | '\\' | '*' | '?' | '"' | '<' | '>' | '|') && !c.is_control())
+        .collect()
+}
+
+/// What to do when two files would get the same path, or a file is
+/// already there.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Leave the file where it is.
+    #[default]
+    Skip,
+    /// Add ` (2)`, ` (3)`... before the extension.
+    Suffix,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct PlannedMove {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Conflict {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+    /// `duplicate` when another file gets the same path, `exists` when a
+    /// file is already there.
+    pub reason: &'static str,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Skipped {
+    pub id: String,
+    pub from: String,
+    /// `no_title_id` or `invalid_path`.
+    pub reason: &'static str,
+}
+
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct Plan {
+    pub moves: Vec<PlannedMove>,
+    pub conflicts: Vec<Conflict>,
+    pub skipped: Vec<Skipped>,
+    /// Files already where the template puts them.
+    pub unchanged: usize,
+}
+
+/// `path` with ` (n)` before its extension.
+pub fn with_suffix(path: &str, n: usize) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, ext)) => format!("{} ({}).{}", stem, n, ext),
+        None => format!("{} ({})", path, n),
+    }
+}
+
+/// Computes where each game goes. Nothing is moved; `games_dir` is only
+/// read to find files in the way.
+pub fn plan(
+    games: &[Game],
+    meta: &MetadataProvider,
+    template: &Template,
+    games_dir: &Path,
+    on_conflict: OnConflict,
+) -> Plan {
+    let mut plan = Plan::default();
+    // Paths already given out, compared case-insensitively so a plan holds
+    // on case-insensitive filesystems too
+    let key = |path: &str| path.to_lowercase();
+    let mut taken: HashSet<String> = HashSet::new();
+    let mut targets = Vec::new();
+
+    for game in games {
+        let to = match template.target(game, meta, games_dir) {
+            Ok(to) => to,
+            Err(reason) => {
+                plan.skipped.push(Skipped {
+                    id: game.id.clone(),
+                    from: game.relative_path.clone(),
+                    reason,
+                });
+                continue;
+            }
+        };
+        if to == game.relative_path {
+            plan.unchanged += 1;
+            taken.insert(key(&to));
+        } else {
+            targets.push((game, to));
+        }
+    }
+
+    for (game, to) in targets {
+        let mut target = to.clone();
+        let mut n = 1;
+        loop {
+            // Anything on disk is in the way, even a file that is planned
+            // to move, so the result never depends on the order of moves.
+            // The file itself only is on case-insensitive filesystems.
+            let duplicate = taken.contains(&key(&target));
+            let exists =
+                games_dir.join(&target).exists() && key(&target) != key(&game.relative_path);
+            if !duplicate && !exists {
+                taken.insert(key(&target));
+                plan.moves.push(PlannedMove {
+                    id: game.id.clone(),
+                    from: game.relative_path.clone(),
+                    to: target,
+                });
+                break;
+            }
+            if on_conflict == OnConflict::Skip {
+                plan.conflicts.push(Conflict {
+                    id: game.id.clone(),
+                    from: game.relative_path.clone(),
+                    to: target,
+                    reason: if duplicate { "duplicate" } else { "exists" },
+                });
+                break;
+            }
+            n += 1;
+            target = with_suffix(&to, n);
+        }
+    }
+
+    plan
+}
+
+/// A file moved by the organizer, relative to `games_dir`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Move {
+    pub from: String,
+    pub to: String,
+}
+
+/// One applied plan.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OrganizeRun {
+    pub id: String,
+    pub applied_at: u64,
+    pub template: String,
+    pub moves: Vec<Move>,
+}
+
+/// The most recent runs, persisted in `data_dir/organize_log.json`, so they
+/// can be undone.
+pub struct OrganizeLog {
+    path: PathBuf,
+    runs: Vec<OrganizeRun>,
+}
+
+impl OrganizeLog {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join("organize_log.json"),
+            runs: Vec::new(),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        if let Some(runs) = jsonfile::load(&self.path).await {
+            self.runs = runs;
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        jsonfile::save_pretty(&self.path, &self.runs).await
+    }
+
+    pub fn runs(&self) -> &[OrganizeRun] {
+        &self.runs
+    }
+
+    pub fn get(&self, id: &str) -> Option<&OrganizeRun> {
+        self.runs.iter().find(|r| r.id == id)
+    }
+
+    pub async fn record(&mut self, run: OrganizeRun) {
+        self.runs.push(run);
+        if self.runs.len() > LOG_LIMIT {
+            let excess = self.runs.len() - LOG_LIMIT;
+            self.runs.drain(..excess);
+        }
+        if let Err(e) = self.save().await {
+            warn!("Failed to save organize log: {}", e);
+        }
+    }
+
+    /// Keeps only the `remaining` moves of a run, dropping it once none
+    /// are left.
+    pub async fn set_remaining(&mut self, id: &str, remaining: Vec<Move>) {
+        if remaining.is_empty() {
+            self.runs.retain(|r| r.id != id);
+        } else if let Some(run) = self.runs.iter_mut().find(|r| r.id == id) {
+            run.moves = remaining;
+        }
+        if let Err(e) = self.save().await {
+            warn!("Failed to save organize log: {}", e);
+        }
+    }
+}
+
+/// Removes the folders above `path` that became empty, up to `games_dir`.
+pub async fn remove_empty_parents(path: &Path, games_dir: &Path) {
+    for dir in path.ancestors().skip(1) {
+        if dir == games_dir || !dir.starts_with(games_dir) {
+            break;
+        }
+        // Fails, and stops, at the first folder that isn't empty
+        if tokio::fs::remove_dir(dir).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::MetadataStore;
+    use tempfile::tempdir;
+
+    fn game(relative_path: &str, name: &str, title_id: Option<&str>, category: &str) -> Game {
+        Game {
+            id: relative_path.to_string(),
+            name: name.to_string(),
+            relative_path: relative_path.to_string(),
+            format: relative_path.rsplit('.').next().unwrap().to_string(),
+            title_id: title_id.map(str::to_string),
+            version: Some("v65536".to_string()),
+            category: category.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_template_parse() {
+        assert!(Template::parse("{category}/{name} [{title_id}][v{version}].{ext}").is_ok());
+        assert_eq!(
+            Template::parse("{name}.{bogus}").unwrap_err(),
+            "Unknown template field 'bogus'"
+        );
+        assert!(Template::parse("{name").is_err());
+        assert!(Template::parse("{name}.nsp").is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let tmp = tempdir().unwrap();
+        let games_dir = tmp.path().join("games");
+        std::fs::create_dir_all(games_dir.join("Update")).unwrap();
+        let meta = MetadataStore::new(tmp.path().to_path_buf(), vec![]).snapshot();
+        let template = Template::parse("{category}/{name} [{title_id}][v{version}].{ext}").unwrap();
+
+        let games = vec![
+            game("a.nsp", "Zelda: BotW", Some("0100000000010800"), "Update"),
+            game("b.nsp", "Zelda: BotW", Some("0100000000010800"), "Update"),
+            game(
+                "Update/Done [0100000000020800][v65536].nsz",
+                "Done",
+                Some("0100000000020800"),
+                "Update",
+            ),
+            game("c.xci", "No ID", None, "Base"),
+        ];
+        // A file outside the library is in the way of the third game's twin
+        std::fs::write(
+            games_dir.join("Update/Taken [0100000000030800][v65536].nsp"),
+            "",
+        )
+        .unwrap();
+
+        let result = plan(&games, &meta, &template, &games_dir, OnConflict::Skip);
+        assert_eq!(result.unchanged, 1);
+        assert_eq!(result.moves.len(), 1);
+        assert_eq!(
+            result.moves[0].to,
+            "Update/Zelda - BotW [0100000000010800][v65536].nsp"
+        );
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.conflicts[0].reason, "duplicate");
+        assert_eq!(result.skipped[0].reason, "no_title_id");
+
+        let result = plan(&games, &meta, &template, &games_dir, OnConflict::Suffix);
+        assert_eq!(result.moves.len(), 2);
+        assert_eq!(
+            result.moves[1].to,
+            "Update/Zelda - BotW [0100000000010800][v65536] (2).nsp"
+        );
+
+        let taken = vec![game("d.nsp", "Taken", Some("0100000000030800"), "Update")];
+        let result = plan(&taken, &meta, &template, &games_dir, OnConflict::Skip);
+        assert_eq!(result.conflicts[0].reason, "exists");
+    }
+
+    #[tokio::test]
+    async fn test_organize_log() {
+        let tmp = tempdir().unwrap();
+        let mut log = OrganizeLog::new(tmp.path());
+        let moves = vec![
+            Move {
+                from: "a.nsp".to_string(),
+                to: "Base/a.nsp".to_string(),
+            },
+            Move {
+                from: "b.nsp".to_string(),
+                to: "Base/b.nsp".to_string(),
+            },
+        ];
+        log.record(OrganizeRun {
+            id: "r1".to_string(),
+            applied_at: 0,
+            template: "{category}/{name}.{ext}".to_string(),
+            moves: moves.clone(),
+        })
+        .await;
+        log.set_remaining("r1", moves[1..].to_vec()).await;
+
+        let mut reloaded = OrganizeLog::new(tmp.path());
+        reloaded.load().await;
+        assert_eq!(reloaded.get("r1").unwrap().moves, moves[1..]);
+        reloaded.set_remaining("r1", vec![]).await;
+        assert!(reloaded.runs().is_empty());
+    }
+}
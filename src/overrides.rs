@@ -0,0 +1,231 @@
+use crate::jsonfile;
+use crate::titledb::TitleInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// User supplied corrections for a title, keyed by title ID or by the
+/// file's path relative to `games_dir`. Every field is optional; only the
+/// ones that are set replace (or fill in) the titledb values.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct TitleOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating_content: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number_of_players: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub languages: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nsu_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screenshots: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intro: Option<String>,
+}
+
+impl TitleOverride {
+    pub fn apply(&self, info: &mut TitleInfo) {
+        if let Some(ref v) = self.name {
+            info.name = Some(v.clone());
+        }
+        if let Some(ref v) = self.publisher {
+            info.publisher = Some(v.clone());
+        }
+        if let Some(ref v) = self.icon_url {
+            info.icon_url = Some(v.clone());
+        }
+        if let Some(ref v) = self.banner_url {
+            info.banner_url = Some(v.clone());
+        }
+        if let Some(ref v) = self.category {
+            info.category = Some(v.clone());
+        }
+        if let Some(ref v) = self.description {
+            info.description = Some(v.clone());
+        }
+        if let Some(ref v) = self.release_date {
+            info.release_date = Some(v.clone());
+        }
+        if let Some(v) = self.size {
+            info.size = Some(v);
+        }
+        if let Some(v) = self.rating {
+            info.rating = Some(v);
+        }
+        if let Some(ref v) = self.rating_content {
+            info.rating_content = Some(v.clone());
+        }
+        if let Some(v) = self.number_of_players {
+            info.number_of_players = Some(v);
+        }
+        if let Some(ref v) = self.languages {
+            info.languages = Some(v.clone());
+        }
+        if let Some(v) = self.nsu_id {
+            info.nsu_id = Some(v);
+        }
+        if let Some(ref v) = self.screenshots {
+            info.screenshots = Some(v.clone());
+        }
+        if let Some(ref v) = self.intro {
+            info.intro = Some(v.clone());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Persistent store for [`TitleOverride`]s, kept in `data_dir/overrides.json`
+/// so it is independent from the titledb files replaced on every sync.
+#[derive(Clone)]
+pub struct Overrides {
+    path: PathBuf,
+    entries: HashMap<String, TitleOverride>,
+}
+
+impl Overrides {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join("overrides.json"),
+            entries: HashMap::new(),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        if !self.path.exists() {
+            return;
+        }
+        info!("Loading metadata overrides from {:?}", self.path);
+        if let Some(data) = jsonfile::load::<HashMap<String, TitleOverride>>(&self.path).await {
+            self.entries = data
+                .into_iter()
+                .map(|(k, v)| (normalize_key(&k), v))
+                .collect();
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        jsonfile::save_pretty(&self.path, &self.entries).await
+    }
+
+    pub fn get(&self, key: &str) -> Option<&TitleOverride> {
+        self.entries.get(&normalize_key(key))
+    }
+
+    pub fn all(&self) -> &HashMap<String, TitleOverride> {
+        &self.entries
+    }
+
+    pub async fn set(&mut self, key: &str, value: TitleOverride) -> std::io::Result<()> {
+        let key = normalize_key(key);
+        if value.is_empty() {
+            self.entries.remove(&key);
+        } else {
+            self.entries.insert(key, value);
+        }
+        self.save().await
+    }
+
+    pub async fn remove(&mut self, key: &str) -> std::io::Result<Option<TitleOverride>> {
+        let removed = self.entries.remove(&normalize_key(key));
+        if removed.is_some() {
+            self.save().await?;
+        }
+        Ok(removed)
+    }
+
+    /// Moves the override of a file to its new path. Returns whether it
+    /// had one.
+    pub async fn rename(&mut self, from: &str, to: &str) -> std::io::Result<bool> {
+        let Some(value) = self.entries.remove(&normalize_key(from)) else {
+            return Ok(false);
+        };
+        self.entries.insert(normalize_key(to), value);
+        self.save().await?;
+        Ok(true)
+    }
+}
+
+pub fn is_title_id(key: &str) -> bool {
+    key.len() == 16 && key.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Title IDs are matched case-insensitively, paths are compared with forward
+/// slashes and without a leading separator, like `Game::relative_path`.
+pub fn normalize_key(key: &str) -> String {
+    let key = key.trim();
+    if is_title_id(key) {
+        key.to_uppercase()
+    } else {
+        key.replace('\\', "/").trim_start_matches('/').to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(normalize_key("0100abcdef000000"), "0100ABCDEF000000");
+        assert_eq!(
+            normalize_key("/Homebrew\\Forwarder.nsp"),
+            "Homebrew/Forwarder.nsp"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_overrides_persist() {
+        let tmp = tempdir().unwrap();
+        let mut overrides = Overrides::new(tmp.path());
+        overrides
+            .set(
+                "0100abcdef000000",
+                TitleOverride {
+                    name: Some("Custom".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut reloaded = Overrides::new(tmp.path());
+        reloaded.load().await;
+        let ov = reloaded.get("0100ABCDEF000000").unwrap();
+        assert_eq!(ov.name.as_deref(), Some("Custom"));
+
+        let mut info = TitleInfo {
+            name: Some("Original".to_string()),
+            publisher: Some("Publisher".to_string()),
+            ..Default::default()
+        };
+        ov.apply(&mut info);
+        assert_eq!(info.name.as_deref(), Some("Custom"));
+        assert_eq!(info.publisher.as_deref(), Some("Publisher"));
+
+        assert!(reloaded.remove("0100abcdef000000").await.unwrap().is_some());
+        assert!(reloaded.get("0100ABCDEF000000").is_none());
+    }
+}
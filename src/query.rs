@@ -0,0 +1,292 @@
+use crate::catalog::fold;
+use crate::dat::Verification;
+use crate::metadata::MetadataProvider;
+use crate::scanner::Game;
+use crate::titles::outdated_titles;
+use crate::userdata::UserLibrary;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// What `/api/games` sorts by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameSort {
+    #[default]
+    Name,
+    Size,
+    /// When the file appeared in the games directory.
+    Added,
+    ReleaseDate,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Filters and sort order for the library listing. Every filter is
+/// optional; text comparisons ignore case and accents.
+#[derive(Debug, Default, Deserialize)]
+pub struct GameQuery {
+    /// Words that must all appear in the name, path or title ID.
+    pub q: Option<String>,
+    pub category: Option<String>,
+    pub format: Option<String>,
+    pub publisher: Option<String>,
+    /// Only files of games with a newer update available.
+    #[serde(default)]
+    pub outdated: bool,
+    pub title_id_prefix: Option<String>,
+    /// `ok` or `broken`, see [`crate::container::Health`].
+    pub health: Option<String>,
+    pub verification: Option<Verification>,
+    /// Only titles marked as favorite.
+    #[serde(default)]
+    pub favorite: bool,
+    pub tag: Option<String>,
+    pub collection: Option<String>,
+    #[serde(default)]
+    pub sort: GameSort,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+impl GameQuery {
+    /// The games matching the filters, sorted. The user data filters look
+    /// titles up in `user_library`; `Game.user_data` is left as it is.
+    pub fn apply<'a>(
+        &self,
+        games: &'a [Game],
+        meta: &MetadataProvider,
+        user_library: &UserLibrary,
+    ) -> Vec<&'a Game> {
+        let words: Vec<String> = self
+            .q
+            .as_deref()
+            .map(fold)
+            .unwrap_or_default()
+            .split(' ')
+            .filter(|w| !w.is_empty())
+            .map(str::to_string)
+            .collect();
+        let publisher = self.publisher.as_deref().map(fold);
+        let prefix = self.title_id_prefix.as_deref().map(str::to_uppercase);
+        let outdated: Option<HashSet<String>> = self.outdated.then(|| {
+            outdated_titles(games, meta)
+                .into_iter()
+                .map(|t| t.base_title_id)
+                .collect()
+        });
+
+        let mut found: Vec<&Game> = games
+            .iter()
+            .filter(|g| {
+                self.category
+                    .as_deref()
+                    .is_none_or(|c| g.category.eq_ignore_ascii_case(c))
+            })
+            .filter(|g| {
+                self.format
+                    .as_deref()
+                    .is_none_or(|f| g.format.eq_ignore_ascii_case(f))
+            })
+            .filter(|g| {
+                publisher.as_deref().is_none_or(|p| {
+                    g.publisher
+                        .as_deref()
+                        .is_some_and(|own| fold(own).contains(p))
+                })
+            })
+            .filter(|g| {
+                prefix.as_deref().is_none_or(|p| {
+                    g.title_id
+                        .as_deref()
+                        .is_some_and(|tid| tid.to_uppercase().starts_with(p))
+                })
+            })
+            .filter(|g| {
+                self.health.as_deref().is_none_or(|h| {
+                    g.health
+                        .as_ref()
+                        .is_some_and(|own| own.status.eq_ignore_ascii_case(h))
+                })
+            })
+            .filter(|g| self.verification.is_none_or(|v| g.verification == Some(v)))
+            .filter(|g| {
+                let user = user_library.for_game(g);
+                (!self.favorite || user.is_some_and(|u| u.favorite))
+                    && self
+                        .tag
+                        .as_deref()
+                        .is_none_or(|t| user.is_some_and(|u| u.has_tag(t)))
+                    && self
+                        .collection
+                        .as_deref()
+                        .is_none_or(|c| user.is_some_and(|u| u.in_collection(c)))
+            })
+            .filter(|g| {
+                outdated.as_ref().is_none_or(|ids| {
+                    g.base_title_id
+                        .as_ref()
+                        .or(g.title_id.as_ref())
+                        .is_some_and(|id| ids.contains(id))
+                })
+            })
+            .filter(|g| {
+                if words.is_empty() {
+                    return true;
+                }
+                let text = fold(&format!(
+                    "{} {} {}",
+                    g.name,
+                    g.relative_path,
+                    g.title_id.as_deref().unwrap_or_default()
+                ));
+                words.iter().all(|w| text.contains(w.as_str()))
+            })
+            .collect();
+
+        // Sorted by name first, so the stable sorts below keep ties in name
+        // order
+        found.sort_by_cached_key(|&g| (g.name.to_lowercase(), g.relative_path.as_str()));
+        let directed = |ordering: Ordering| match self.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        };
+        match self.sort {
+            GameSort::Name => {
+                if self.order == SortOrder::Desc {
+                    found.reverse();
+                }
+            }
+            GameSort::Size => found.sort_by(|a, b| directed(a.size.cmp(&b.size))),
+            GameSort::Added => found.sort_by(|a, b| missing_last(a.added_at, b.added_at, directed)),
+            GameSort::ReleaseDate => found.sort_by(|a, b| {
+                missing_last(a.release_date.as_ref(), b.release_date.as_ref(), directed)
+            }),
+        }
+        found
+    }
+}
+
+/// Compares optional sort keys, putting files without one last in either
+/// direction.
+fn missing_last<T: Ord>(
+    a: Option<T>,
+    b: Option<T>,
+    directed: impl Fn(Ordering) -> Ordering,
+) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => directed(a.cmp(&b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::MetadataStore;
+    use tempfile::tempdir;
+
+    fn game(name: &str, title_id: &str, category: &str, format: &str, size: u64) -> Game {
+        Game {
+            name: name.to_string(),
+            relative_path: format!("{}.{}", name, format),
+            title_id: Some(title_id.to_string()),
+            category: category.to_string(),
+            format: format.to_string(),
+            size,
+            ..Default::default()
+        }
+    }
+
+    fn names(games: Vec<&Game>) -> Vec<String> {
+        games.into_iter().map(|g| g.relative_path.clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_game_query() {
+        let tmp = tempdir().unwrap();
+        let store = MetadataStore::new(tmp.path().to_path_buf(), vec![]);
+        let meta = store.snapshot();
+
+        let mut games = vec![
+            game("Pokémon Sword", "0100ABF008968000", "Base", "nsp", 300),
+            game("Celeste", "01002B30028F6000", "Base", "xci", 100),
+            game("Celeste Update", "01002B30028F6800", "Update", "nsp", 10),
+        ];
+        games[0].publisher = Some("Nintendo".to_string());
+        games[0].release_date = Some("2019-11-15".to_string());
+        games[1].release_date = Some("2018-01-25".to_string());
+        games[1].added_at = Some(20);
+        games[2].added_at = Some(10);
+
+        let user_library = UserLibrary::new(tmp.path());
+        let query = |q: GameQuery| names(q.apply(&games, &meta, &user_library));
+
+        assert_eq!(
+            query(GameQuery::default()),
+            vec!["Celeste.xci", "Celeste Update.nsp", "Pokémon Sword.nsp"]
+        );
+        assert_eq!(
+            query(GameQuery {
+                q: Some("pokemon SWO".to_string()),
+                ..Default::default()
+            }),
+            vec!["Pokémon Sword.nsp"]
+        );
+        assert_eq!(
+            query(GameQuery {
+                category: Some("base".to_string()),
+                format: Some("NSP".to_string()),
+                ..Default::default()
+            }),
+            vec!["Pokémon Sword.nsp"]
+        );
+        assert_eq!(
+            query(GameQuery {
+                publisher: Some("nintendo".to_string()),
+                ..Default::default()
+            }),
+            vec!["Pokémon Sword.nsp"]
+        );
+        assert_eq!(
+            query(GameQuery {
+                title_id_prefix: Some("01002b".to_string()),
+                ..Default::default()
+            }),
+            vec!["Celeste.xci", "Celeste Update.nsp"]
+        );
+        assert_eq!(
+            query(GameQuery {
+                sort: GameSort::Size,
+                order: SortOrder::Desc,
+                ..Default::default()
+            }),
+            vec!["Pokémon Sword.nsp", "Celeste.xci", "Celeste Update.nsp"]
+        );
+        // Files without the key stay last whatever the direction
+        for order in [SortOrder::Asc, SortOrder::Desc] {
+            let sorted = query(GameQuery {
+                sort: GameSort::ReleaseDate,
+                order,
+                ..Default::default()
+            });
+            assert_eq!(sorted[2], "Celeste Update.nsp");
+        }
+        assert_eq!(
+            query(GameQuery {
+                sort: GameSort::Added,
+                order: SortOrder::Desc,
+                ..Default::default()
+            }),
+            vec!["Celeste.xci", "Celeste Update.nsp", "Pokémon Sword.nsp"]
+        );
+    }
+}
@@ -1,19 +1,64 @@
+use crate::container::Health;
+use crate::metadata::TitleKind;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
-#[derive(Clone, Serialize, Debug, Deserialize)]
+#[derive(Clone, Serialize, Debug, Deserialize, Default, PartialEq)]
 pub struct Game {
+    /// Stable identifier, see [`game_id`].
+    #[serde(default)]
+    pub id: String,
     pub name: String,
     pub path: PathBuf,
     pub relative_path: String,
     pub size: u64,
     pub format: String,
     pub title_id: Option<String>,
+    #[serde(default)]
+    pub base_title_id: Option<String>,
     pub version: Option<String>,
     pub latest_version: Option<String>,
     pub category: String, // "Base", "Update", "DLC"
     pub publisher: Option<String>,
     pub image_url: Option<String>,
+    #[serde(default)]
+    pub release_date: Option<String>,
+    /// Install size from titledb, as opposed to `size` which is the file's.
+    #[serde(default)]
+    pub install_size: Option<u64>,
+    #[serde(default)]
+    pub rating: Option<u32>,
+    #[serde(default)]
+    pub number_of_players: Option<u32>,
+    #[serde(default)]
+    pub languages: Option<Vec<String>>,
+    #[serde(default)]
+    pub nsu_id: Option<u64>,
+    /// Set when `title_id` was inferred from the name instead of read from
+    /// the filename: the match score, or 1 for a match the user confirmed.
+    #[serde(default)]
+    pub match_confidence: Option<f64>,
+    /// Unix timestamp of when the file was created, or last modified where
+    /// the filesystem does not record creation.
+    #[serde(default)]
+    pub added_at: Option<u64>,
+    /// Whether the container headers are sound, checked at scan time.
+    /// `None` when the file couldn't be read.
+    #[serde(default)]
+    pub health: Option<Health>,
+    /// How the file compares to the imported DATs; `None` without any.
+    #[serde(default)]
+    pub verification: Option<crate::dat::Verification>,
+    /// Favorites, tags, notes and collections of the title, see
+    /// [`crate::userdata`].
+    #[serde(default)]
+    pub user_data: Option<crate::userdata::UserData>,
+}
+
+impl Game {
+    pub fn is_broken(&self) -> bool {
+        self.health.as_ref().is_some_and(Health::is_broken)
+    }
 }
 
 fn parse_filename(filename: &str) -> (String, Option<String>, Option<String>, String) {
@@ -87,20 +132,41 @@ fn parse_filename(filename: &str) -> (String, Option<String>, Option<String>, St
     (final_name, title_id, version, category)
 }
 
-pub fn process_entry(
-    path: &Path,
-    root_dir: &Path,
-    _data_dir: &Path,
-    metadata: Option<&crate::metadata::MetadataProvider>,
-) -> Option<Game> {
-    let valid_extensions = ["nsp", "nsz", "xci", "xcz"];
+/// The game name as written in a file's name, without tags or extension.
+pub fn name_from_path(path: &str) -> String {
+    let filename = Path::new(path)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or(path);
+    parse_filename(filename).0
+}
+
+/// Extensions of the files that make up the library.
+pub const GAME_EXTENSIONS: &[&str] = &["nsp", "nsz", "xci", "xcz"];
+
+/// Identifies a file by its games directory and path within it, so the ID
+/// survives restarts and rescans but changes when the file is moved.
+pub fn game_id(root_dir: &Path, relative_path: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(root_dir.to_string_lossy().as_bytes());
+    hasher.update([0]);
+    hasher.update(relative_path.as_bytes());
+    hasher.finalize()[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
 
+/// Builds a [`Game`] from the file alone: its name, size and whatever the
+/// filename tells us. No metadata is applied.
+pub fn discover(path: &Path, root_dir: &Path) -> Option<Game> {
     if !path.is_file() {
         return None;
     }
 
     let ext = path.extension()?.to_str()?;
-    if !valid_extensions.contains(&ext.to_lowercase().as_str()) {
+    if !GAME_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
         return None;
     }
 
@@ -109,29 +175,22 @@ pub fn process_entry(
         .and_then(|s| s.to_str())
         .unwrap_or("Unknown")
         .to_string();
-    let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let metadata = std::fs::metadata(path).ok();
+    let size = metadata.as_ref().map_or(0, |m| m.len());
+    let added_at = metadata
+        .and_then(|m| m.created().or_else(|_| m.modified()).ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
     let relative_path = path
         .strip_prefix(root_dir)
         .unwrap_or(path)
         .to_string_lossy()
         .to_string();
 
-    let (mut name, title_id, version, category) = parse_filename(&filename);
-    let mut publisher = None;
-    let mut latest_version = None;
-
-    // Enhance info from metadata provider if available
-    if let (Some(provider), Some(tid)) = (metadata, title_id.as_ref()) {
-        if let Some(info) = provider.get_title_info(tid) {
-            if let Some(ref n) = info.name {
-                name = n.clone();
-            }
-            publisher = info.publisher.clone();
-        }
-        latest_version = provider.get_latest_version(tid);
-    }
+    let (name, title_id, version, category) = parse_filename(&filename);
 
     Some(Game {
+        id: game_id(root_dir, &relative_path),
         name,
         path: path.to_path_buf(),
         relative_path,
@@ -139,13 +198,72 @@ pub fn process_entry(
         format: ext.to_lowercase(),
         title_id,
         version,
-        latest_version,
         category,
-        publisher,
-        image_url: None,
+        added_at,
+        health: Health::check(path),
+        ..Game::default()
     })
 }
 
+/// (Re-)applies metadata from `provider` (titledb plus user overrides) to a
+/// discovered game. Fields derived from metadata are reset first, so this
+/// can be called again whenever the metadata changes.
+pub fn enrich(game: &mut Game, provider: &crate::metadata::MetadataProvider) {
+    let filename = game
+        .path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Unknown");
+    let (name, title_id, _, category) = parse_filename(filename);
+
+    game.match_confidence = None;
+    game.title_id = title_id;
+    if game.title_id.is_none()
+        && let Some((tid, confidence)) = provider.infer_title_id(&game.relative_path, &name)
+    {
+        game.title_id = Some(tid);
+        game.match_confidence = Some(confidence);
+    }
+
+    game.name = name;
+    game.category = category;
+    game.base_title_id = None;
+    game.latest_version = None;
+    game.publisher = None;
+    game.image_url = None;
+    game.release_date = None;
+    game.install_size = None;
+    game.rating = None;
+    game.number_of_players = None;
+    game.languages = None;
+    game.nsu_id = None;
+
+    if let Some(tid) = game.title_id.as_ref() {
+        game.latest_version = provider.get_latest_version(tid);
+        game.base_title_id = provider.get_base_title_id(tid);
+        // The content meta type is more reliable than filename tags
+        match provider.get_title_kind(tid) {
+            Some(TitleKind::Patch) => game.category = "Update".to_string(),
+            Some(TitleKind::AddOnContent) => game.category = "DLC".to_string(),
+            _ => {}
+        }
+    }
+
+    if let Some(info) = provider.resolve_title_info(game.title_id.as_deref(), &game.relative_path) {
+        if let Some(n) = info.name {
+            game.name = n;
+        }
+        game.publisher = info.publisher;
+        game.image_url = info.icon_url;
+        game.release_date = info.release_date;
+        game.install_size = info.size;
+        game.rating = info.rating;
+        game.number_of_players = info.number_of_players;
+        game.languages = info.languages;
+        game.nsu_id = info.nsu_id;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -187,14 +305,43 @@ mod tests {
     }
 
     #[test]
-    fn test_process_entry() {
+    fn test_discover() {
         let tmp = tempdir().unwrap();
         let game_path = tmp.path().join("Test [0100000000010000][v0].nsp");
         std::fs::write(&game_path, "dummy").unwrap();
 
-        let game = process_entry(&game_path, tmp.path(), tmp.path(), None).unwrap();
+        let game = discover(&game_path, tmp.path()).unwrap();
         assert_eq!(game.name, "Test");
         assert_eq!(game.title_id, Some("0100000000010000".to_string()));
         assert_eq!(game.format, "nsp");
+        assert_eq!(game.id.len(), 16);
+        assert_eq!(game.id, discover(&game_path, tmp.path()).unwrap().id);
+        assert_ne!(game.id, game_id(tmp.path(), "Other.nsp"));
+        assert!(game.is_broken());
+    }
+
+    #[tokio::test]
+    async fn test_enrich_is_repeatable() {
+        let tmp = tempdir().unwrap();
+        let game_path = tmp.path().join("Test [0100000000010000][v0].nsp");
+        std::fs::write(&game_path, "dummy").unwrap();
+        let mut game = discover(&game_path, tmp.path()).unwrap();
+
+        let store = crate::metadata::MetadataStore::new(tmp.path().to_path_buf(), vec![]);
+        store
+            .update_override("0100000000010000", |ov| {
+                ov.name = Some("Renamed".to_string());
+                ov.publisher = Some("Someone".to_string());
+            })
+            .await
+            .unwrap();
+        enrich(&mut game, &store.snapshot());
+        assert_eq!(game.name, "Renamed");
+        assert_eq!(game.publisher.as_deref(), Some("Someone"));
+
+        store.remove_override("0100000000010000").await.unwrap();
+        enrich(&mut game, &store.snapshot());
+        assert_eq!(game.name, "Test");
+        assert_eq!(game.publisher, None);
     }
 }
@@ -0,0 +1,371 @@
+use crate::overrides::{Overrides, TitleOverride, is_title_id};
+use crate::titledb::TitleInfo;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Somewhere title metadata comes from. Sources are combined by
+/// [`MergeRules`]: for every field, the first source in priority order that
+/// has a value supplies it.
+pub trait MetadataSource: Send + Sync {
+    /// What the source knows about a title on its own, without inheriting
+    /// from a parent title.
+    fn title_info(&self, title_id: &str) -> Option<TitleInfo>;
+
+    /// Every title ID the source has a record for, uppercase.
+    fn title_ids(&self) -> Vec<&str>;
+
+    /// Just the name from [`title_info`](Self::title_info), for sources
+    /// that can look it up without building the whole record.
+    fn title_name(&self, title_id: &str) -> Option<String> {
+        self.title_info(title_id)?.name
+    }
+
+    /// Known versions of a title with their release dates.
+    fn versions(&self, _title_id: &str) -> Vec<(u64, String)> {
+        Vec::new()
+    }
+}
+
+/// The sources that can appear in `metadata_sources`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SourceId {
+    /// Title ID overrides edited through the API.
+    Overrides,
+    /// `data_dir/catalogue.json` or `catalogue.toml`, e.g. for homebrew.
+    Catalogue,
+    /// The titledb files.
+    TitleDb,
+    /// A self-hosted metadata service, see `metadata_remote_url`.
+    Remote,
+}
+
+impl std::str::FromStr for SourceId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "overrides" => Ok(SourceId::Overrides),
+            "catalogue" | "catalog" => Ok(SourceId::Catalogue),
+            "titledb" => Ok(SourceId::TitleDb),
+            "remote" => Ok(SourceId::Remote),
+            other => Err(format!("unknown metadata source '{}'", other)),
+        }
+    }
+}
+
+/// Fields that can be given their own source order, plus `versions`.
+const FIELDS: &[&str] = &[
+    "name",
+    "icon_url",
+    "banner_url",
+    "category",
+    "description",
+    "publisher",
+    "release_date",
+    "size",
+    "rating",
+    "rating_content",
+    "number_of_players",
+    "languages",
+    "nsu_id",
+    "screenshots",
+    "intro",
+    "versions",
+];
+
+/// Priority order of the metadata sources, optionally overridden per field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeRules {
+    pub order: Vec<SourceId>,
+    pub fields: HashMap<String, Vec<SourceId>>,
+}
+
+impl Default for MergeRules {
+    fn default() -> Self {
+        Self {
+            order: vec![
+                SourceId::Overrides,
+                SourceId::Catalogue,
+                SourceId::TitleDb,
+                SourceId::Remote,
+            ],
+            fields: HashMap::new(),
+        }
+    }
+}
+
+fn parse_sources(list: &str) -> Vec<SourceId> {
+    let mut sources = Vec::new();
+    for name in list.split(',').filter(|s| !s.trim().is_empty()) {
+        match name.parse() {
+            Ok(id) if !sources.contains(&id) => sources.push(id),
+            Ok(_) => {}
+            Err(e) => warn!("Ignoring {}", e),
+        }
+    }
+    sources
+}
+
+impl MergeRules {
+    /// Parses `overrides, catalogue, titledb` style lists. `fields` holds
+    /// per-field orders as `field=source,source; field=source`.
+    pub fn parse(order: &str, fields: Option<&str>) -> Self {
+        let mut rules = Self {
+            order: parse_sources(order),
+            fields: HashMap::new(),
+        };
+        if rules.order.is_empty() {
+            warn!("No valid metadata sources configured, using the defaults");
+            rules.order = Self::default().order;
+        }
+        for rule in fields.unwrap_or_default().split(';') {
+            let Some((field, sources)) = rule.split_once('=') else {
+                if !rule.trim().is_empty() {
+                    warn!("Ignoring metadata field rule '{}'", rule.trim());
+                }
+                continue;
+            };
+            let field = field.trim().to_lowercase();
+            if !FIELDS.contains(&field.as_str()) {
+                warn!("Ignoring rule for unknown metadata field '{}'", field);
+                continue;
+            }
+            rules.fields.insert(field, parse_sources(sources));
+        }
+        rules
+    }
+
+    /// The sources to consult for `field`, most important first.
+    pub fn order_for(&self, field: &str) -> &[SourceId] {
+        self.fields.get(field).unwrap_or(&self.order)
+    }
+
+    /// Every source referenced by the chain or a field rule.
+    pub fn sources(&self) -> Vec<SourceId> {
+        let mut all = self.order.clone();
+        for id in self.fields.values().flatten() {
+            if !all.contains(id) {
+                all.push(*id);
+            }
+        }
+        all
+    }
+
+    /// Combines what each source returned into one record, field by field.
+    /// Returns `None` when no source knows the title.
+    pub fn merge(&self, title_id: &str, found: &[(SourceId, TitleInfo)]) -> Option<TitleInfo> {
+        if found.is_empty() {
+            return None;
+        }
+        let get = |id: SourceId| found.iter().find(|(s, _)| *s == id).map(|(_, i)| i);
+
+        let mut merged = TitleInfo {
+            id: title_id.to_string(),
+            ..Default::default()
+        };
+        macro_rules! merge_fields {
+            ($($field:ident),*) => {$(
+                merged.$field = self
+                    .order_for(stringify!($field))
+                    .iter()
+                    .find_map(|id| get(*id).and_then(|i| i.$field.clone()));
+            )*};
+        }
+        merge_fields!(
+            name,
+            icon_url,
+            banner_url,
+            category,
+            description,
+            publisher,
+            release_date,
+            size,
+            rating,
+            rating_content,
+            number_of_players,
+            languages,
+            nsu_id,
+            screenshots,
+            intro
+        );
+        if let Some(titledb) = get(SourceId::TitleDb) {
+            merged.locale = titledb.locale.clone();
+            merged.fallback_locales = titledb.fallback_locales.clone();
+        }
+        Some(merged)
+    }
+}
+
+impl MetadataSource for Overrides {
+    fn title_info(&self, title_id: &str) -> Option<TitleInfo> {
+        let ov = self.get(title_id)?;
+        let mut info = TitleInfo {
+            id: title_id.to_uppercase(),
+            ..Default::default()
+        };
+        ov.apply(&mut info);
+        Some(info)
+    }
+
+    fn title_ids(&self) -> Vec<&str> {
+        self.all()
+            .keys()
+            .map(String::as_str)
+            .filter(|key| is_title_id(key))
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct CatalogueEntry {
+    #[serde(flatten)]
+    info: TitleOverride,
+    /// Version number to release date, like titledb's `versions.json`.
+    #[serde(default)]
+    versions: HashMap<String, String>,
+}
+
+/// Titles from a hand-written catalogue file keyed by title ID, in JSON or
+/// TOML. Every field is optional and named like in the overrides file.
+#[derive(Default)]
+pub struct CatalogueSource {
+    entries: HashMap<String, CatalogueEntry>,
+}
+
+impl CatalogueSource {
+    /// Loads the first of `paths` that exists, picking the format from its
+    /// extension. Missing or broken files give an empty catalogue.
+    pub async fn load(paths: &[&Path]) -> Self {
+        let Some(path) = paths.iter().find(|p| p.exists()) else {
+            return Self::default();
+        };
+        info!("Loading metadata catalogue from {:?}", path);
+        let content = match tokio::fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Failed to read {:?}: {}", path, e);
+                return Self::default();
+            }
+        };
+        let parsed: Result<HashMap<String, CatalogueEntry>, String> =
+            if path.extension().is_some_and(|e| e == "toml") {
+                toml::from_str(&content).map_err(|e| e.to_string())
+            } else {
+                serde_json::from_str(&content).map_err(|e| e.to_string())
+            };
+        match parsed {
+            Ok(entries) => Self {
+                entries: entries
+                    .into_iter()
+                    .map(|(id, entry)| (id.to_uppercase(), entry))
+                    .collect(),
+            },
+            Err(e) => {
+                warn!("Failed to parse {:?}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+}
+
+impl MetadataSource for CatalogueSource {
+    fn title_info(&self, title_id: &str) -> Option<TitleInfo> {
+        let entry = self.entries.get(&title_id.to_uppercase())?;
+        let mut info = TitleInfo {
+            id: title_id.to_uppercase(),
+            ..Default::default()
+        };
+        entry.info.apply(&mut info);
+        Some(info)
+    }
+
+    fn title_ids(&self) -> Vec<&str> {
+        self.entries.keys().map(String::as_str).collect()
+    }
+
+    fn versions(&self, title_id: &str) -> Vec<(u64, String)> {
+        self.entries
+            .get(&title_id.to_uppercase())
+            .map(|entry| {
+                entry
+                    .versions
+                    .iter()
+                    .filter_map(|(ver, date)| Some((ver.parse().ok()?, date.clone())))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_merge_rules() {
+        let rules = MergeRules::parse(
+            "titledb, catalogue, bogus",
+            Some("icon_url = catalogue, titledb; nonsense = titledb"),
+        );
+        assert_eq!(rules.order, vec![SourceId::TitleDb, SourceId::Catalogue]);
+        assert_eq!(rules.fields.len(), 1);
+
+        let found = vec![
+            (
+                SourceId::TitleDb,
+                TitleInfo {
+                    name: Some("From titledb".to_string()),
+                    icon_url: Some("titledb.png".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                SourceId::Catalogue,
+                TitleInfo {
+                    name: Some("From catalogue".to_string()),
+                    icon_url: Some("catalogue.png".to_string()),
+                    publisher: Some("Homebrew".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let merged = rules.merge("0100000000010000", &found).unwrap();
+        assert_eq!(merged.name.as_deref(), Some("From titledb"));
+        assert_eq!(merged.icon_url.as_deref(), Some("catalogue.png"));
+        assert_eq!(merged.publisher.as_deref(), Some("Homebrew"));
+        assert!(rules.merge("0100000000010000", &[]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_catalogue_formats() {
+        let tmp = tempdir().unwrap();
+        let toml_path = tmp.path().join("catalogue.toml");
+        std::fs::write(
+            &toml_path,
+            r#"
+            [05000000000A0000]
+            name = "Homebrew Tool"
+            publisher = "Someone"
+            versions = { "65536" = "2024-01-01" }
+            "#,
+        )
+        .unwrap();
+        let json_path = tmp.path().join("catalogue.json");
+
+        let catalogue = CatalogueSource::load(&[&json_path, &toml_path]).await;
+        let info = catalogue.title_info("05000000000a0000").unwrap();
+        assert_eq!(info.name.as_deref(), Some("Homebrew Tool"));
+        assert_eq!(
+            catalogue.versions("05000000000A0000"),
+            vec![(65536, "2024-01-01".to_string())]
+        );
+
+        std::fs::write(&json_path, r#"{ "05000000000B0000": { "name": "Json" } }"#).unwrap();
+        let catalogue = CatalogueSource::load(&[&json_path, &toml_path]).await;
+        assert!(catalogue.title_info("05000000000B0000").is_some());
+        assert!(catalogue.title_info("05000000000A0000").is_none());
+    }
+}
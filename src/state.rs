@@ -1,7 +1,16 @@
+use crate::cleanup::Quarantine;
 use crate::config::Settings;
-use crate::downloads::Downloads;
-use crate::metadata::MetadataProvider;
+use crate::dat::DatStore;
+use crate::downloads::{DownloadHistory, Downloads};
+use crate::fileops::WatchSuppression;
+use crate::inbox::IngestLog;
+use crate::integrity::IntegrityStore;
+use crate::metadata::MetadataStore;
+use crate::organizer::OrganizeLog;
 use crate::scanner::Game;
+use crate::stats::StatsHistory;
+use crate::uploads::Uploads;
+use crate::userdata::UserLibrary;
 use dav_server::DavHandler;
 use std::sync::{Arc, Mutex};
 use tokio::sync::broadcast;
@@ -12,7 +21,22 @@ pub struct AppState {
     pub settings: Settings,
     pub host_url: String,
     pub downloads: Downloads,
+    pub download_history: Arc<tokio::sync::Mutex<DownloadHistory>>,
     pub tx: broadcast::Sender<String>,
-    pub metadata: Arc<tokio::sync::Mutex<MetadataProvider>>,
+    pub metadata: Arc<MetadataStore>,
     pub dav_handler: DavHandler,
+    pub quarantine: Arc<tokio::sync::Mutex<Quarantine>>,
+    /// Files deleted through the API, in `data_dir/trash`.
+    pub trash: Arc<tokio::sync::Mutex<Quarantine>>,
+    pub watch_suppression: Arc<WatchSuppression>,
+    /// Held by everything that moves files into or out of `games_dir`, so
+    /// checking that a target is free and moving there can't race.
+    pub file_ops: Arc<tokio::sync::Mutex<()>>,
+    pub uploads: Arc<tokio::sync::Mutex<Uploads>>,
+    pub organize_log: Arc<tokio::sync::Mutex<OrganizeLog>>,
+    pub ingest_log: Arc<tokio::sync::Mutex<IngestLog>>,
+    pub integrity: Arc<IntegrityStore>,
+    pub dats: Arc<DatStore>,
+    pub user_library: Arc<tokio::sync::Mutex<UserLibrary>>,
+    pub stats_history: Arc<tokio::sync::Mutex<StatsHistory>>,
 }
@@ -0,0 +1,340 @@
+use crate::jsonfile;
+use crate::metadata::MetadataProvider;
+use crate::scanner::Game;
+use crate::state::AppState;
+use crate::titles::group_titles;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// How many daily snapshots are kept.
+const HISTORY_LIMIT: usize = 730;
+const LARGEST_TITLES: usize = 10;
+const GIB: u64 = 1 << 30;
+const MIB: u64 = 1 << 20;
+
+/// Upper bounds and labels of the size histogram.
+const SIZE_BUCKETS: &[(u64, &str)] = &[
+    (100 * MIB, "< 100 MiB"),
+    (GIB, "100 MiB - 1 GiB"),
+    (4 * GIB, "1 - 4 GiB"),
+    (16 * GIB, "4 - 16 GiB"),
+    (u64::MAX, "16 GiB +"),
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Bucket {
+    pub files: usize,
+    pub size: u64,
+}
+
+impl Bucket {
+    fn add(&mut self, size: u64) {
+        self.files += 1;
+        self.size += size;
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct NamedBucket {
+    pub name: String,
+    #[serde(flatten)]
+    pub bucket: Bucket,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct LargestTitle {
+    pub base_title_id: String,
+    pub name: String,
+    /// Base game, updates and DLC together.
+    #[serde(flatten)]
+    pub bucket: Bucket,
+}
+
+/// Space saved by NSZ/XCZ compression, estimated from the install size
+/// titledb reports for the compressed files.
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct Compression {
+    pub compressed: Bucket,
+    /// Compressed files whose install size is known.
+    pub estimated_files: usize,
+    pub estimated_savings: u64,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DiskUsage {
+    pub root: PathBuf,
+    pub total: u64,
+    pub available: u64,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct LibraryStats {
+    pub total: Bucket,
+    pub by_category: BTreeMap<String, Bucket>,
+    pub by_format: BTreeMap<String, Bucket>,
+    /// Largest first.
+    pub by_publisher: Vec<NamedBucket>,
+    pub by_root: BTreeMap<String, Bucket>,
+    /// Smallest first.
+    pub by_size: Vec<NamedBucket>,
+    pub compression: Compression,
+    pub largest_titles: Vec<LargestTitle>,
+}
+
+/// Aggregates the library. Files are attributed to the first of `roots`
+/// containing them.
+pub fn library_stats(games: &[Game], meta: &MetadataProvider, roots: &[PathBuf]) -> LibraryStats {
+    let mut total = Bucket::default();
+    let mut by_category: BTreeMap<String, Bucket> = BTreeMap::new();
+    let mut by_format: BTreeMap<String, Bucket> = BTreeMap::new();
+    let mut by_publisher: BTreeMap<String, Bucket> = BTreeMap::new();
+    let mut by_root: BTreeMap<String, Bucket> = BTreeMap::new();
+    let mut by_size: Vec<NamedBucket> = SIZE_BUCKETS
+        .iter()
+        .map(|(_, label)| NamedBucket {
+            name: label.to_string(),
+            bucket: Bucket::default(),
+        })
+        .collect();
+    let mut compression = Compression::default();
+
+    for game in games {
+        total.add(game.size);
+        by_category
+            .entry(game.category.clone())
+            .or_default()
+            .add(game.size);
+        by_format
+            .entry(game.format.clone())
+            .or_default()
+            .add(game.size);
+        by_publisher
+            .entry(
+                game.publisher
+                    .clone()
+                    .unwrap_or_else(|| "Unknown".to_string()),
+            )
+            .or_default()
+            .add(game.size);
+        if let Some(root) = roots.iter().find(|r| game.path.starts_with(r)) {
+            by_root
+                .entry(root.to_string_lossy().to_string())
+                .or_default()
+                .add(game.size);
+        }
+        let bucket = SIZE_BUCKETS
+            .iter()
+            .position(|(max, _)| game.size < *max)
+            .unwrap_or(SIZE_BUCKETS.len() - 1);
+        by_size[bucket].bucket.add(game.size);
+
+        if matches!(game.format.as_str(), "nsz" | "xcz") {
+            compression.compressed.add(game.size);
+            if let Some(install_size) = game.install_size {
+                compression.estimated_files += 1;
+                compression.estimated_savings += install_size.saturating_sub(game.size);
+            }
+        }
+    }
+
+    let mut by_publisher: Vec<NamedBucket> = by_publisher
+        .into_iter()
+        .map(|(name, bucket)| NamedBucket { name, bucket })
+        .collect();
+    by_publisher.sort_by_key(|b| std::cmp::Reverse(b.bucket.size));
+
+    let mut largest_titles: Vec<LargestTitle> = group_titles(games, meta)
+        .into_iter()
+        .map(|family| {
+            let mut bucket = Bucket::default();
+            for game in family.base.iter().chain(&family.updates).chain(&family.dlc) {
+                bucket.add(game.size);
+            }
+            LargestTitle {
+                base_title_id: family.base_title_id,
+                name: family.name,
+                bucket,
+            }
+        })
+        .collect();
+    largest_titles.sort_by_key(|t| std::cmp::Reverse(t.bucket.size));
+    largest_titles.truncate(LARGEST_TITLES);
+
+    LibraryStats {
+        total,
+        by_category,
+        by_format,
+        by_publisher,
+        by_root,
+        by_size,
+        compression,
+        largest_titles,
+    }
+}
+
+/// Size and free space of the filesystem holding each root.
+pub fn disk_usage(roots: &[PathBuf]) -> Vec<DiskUsage> {
+    roots
+        .iter()
+        .filter_map(|root| match fs4::statvfs(root) {
+            Ok(stats) => Some(DiskUsage {
+                root: root.clone(),
+                total: stats.total_space(),
+                available: stats.available_space(),
+            }),
+            Err(e) => {
+                warn!("Failed to read disk usage of {:?}: {}", root, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The library size on one day.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StatsSnapshot {
+    /// `YYYY-MM-DD`, UTC.
+    pub date: String,
+    pub total: Bucket,
+    pub by_category: BTreeMap<String, Bucket>,
+}
+
+/// One snapshot per day, persisted in `data_dir/stats_history.json`, to
+/// show how the library grows.
+pub struct StatsHistory {
+    path: PathBuf,
+    snapshots: Vec<StatsSnapshot>,
+}
+
+impl StatsHistory {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join("stats_history.json"),
+            snapshots: Vec::new(),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        if let Some(snapshots) = jsonfile::load(&self.path).await {
+            self.snapshots = snapshots;
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        jsonfile::save(&self.path, &self.snapshots).await
+    }
+
+    pub fn snapshots(&self) -> &[StatsSnapshot] {
+        &self.snapshots
+    }
+
+    /// Adds a snapshot, replacing an earlier one from the same day.
+    pub async fn record(&mut self, snapshot: StatsSnapshot) {
+        if self
+            .snapshots
+            .last()
+            .is_some_and(|last| last.date == snapshot.date)
+        {
+            self.snapshots.pop();
+        }
+        self.snapshots.push(snapshot);
+        if self.snapshots.len() > HISTORY_LIMIT {
+            let excess = self.snapshots.len() - HISTORY_LIMIT;
+            self.snapshots.drain(..excess);
+        }
+        if let Err(e) = self.save().await {
+            warn!("Failed to save stats history: {}", e);
+        }
+    }
+}
+
+/// Records today's snapshot of the library.
+pub async fn record_snapshot(state: &AppState) {
+    let snapshot = {
+        let games = state.games.lock().unwrap();
+        let mut total = Bucket::default();
+        let mut by_category: BTreeMap<String, Bucket> = BTreeMap::new();
+        for game in games.iter() {
+            total.add(game.size);
+            by_category
+                .entry(game.category.clone())
+                .or_default()
+                .add(game.size);
+        }
+        StatsSnapshot {
+            date: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+            total,
+            by_category,
+        }
+    };
+    state.stats_history.lock().await.record(snapshot).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::MetadataStore;
+    use tempfile::tempdir;
+
+    fn game(path: &str, title_id: &str, category: &str, size: u64) -> Game {
+        Game {
+            name: "Game".to_string(),
+            path: PathBuf::from(path),
+            relative_path: path.trim_start_matches("/games/").to_string(),
+            format: path.rsplit('.').next().unwrap().to_string(),
+            title_id: Some(title_id.to_string()),
+            base_title_id: Some("0100000000010000".to_string()),
+            category: category.to_string(),
+            size,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_library_stats() {
+        let tmp = tempdir().unwrap();
+        let meta = MetadataStore::new(tmp.path().to_path_buf(), vec![]).snapshot();
+        let mut games = vec![
+            game("/games/Base.nsz", "0100000000010000", "Base", 3 * GIB),
+            game("/games/Update.nsp", "0100000000010800", "Update", 50 * MIB),
+            game("/games/Dlc.nsp", "0100000000011001", "DLC", 200 * MIB),
+        ];
+        games[0].install_size = Some(5 * GIB);
+        games[0].publisher = Some("Nintendo".to_string());
+
+        let stats = library_stats(&games, &meta, &[PathBuf::from("/games")]);
+        assert_eq!(stats.total.files, 3);
+        assert_eq!(stats.by_category["DLC"].size, 200 * MIB);
+        assert_eq!(stats.by_format["nsp"].files, 2);
+        assert_eq!(stats.by_publisher[0].name, "Nintendo");
+        assert_eq!(stats.by_root["/games"].files, 3);
+        let sizes: Vec<usize> = stats.by_size.iter().map(|b| b.bucket.files).collect();
+        assert_eq!(sizes, vec![1, 1, 1, 0, 0]);
+        assert_eq!(stats.compression.compressed.files, 1);
+        assert_eq!(stats.compression.estimated_savings, 2 * GIB);
+        assert_eq!(stats.largest_titles.len(), 1);
+        assert_eq!(stats.largest_titles[0].bucket.files, 3);
+    }
+
+    #[tokio::test]
+    async fn test_stats_history_keeps_one_snapshot_per_day() {
+        let tmp = tempdir().unwrap();
+        let snapshot = |date: &str, files: usize| StatsSnapshot {
+            date: date.to_string(),
+            total: Bucket { files, size: 0 },
+            by_category: BTreeMap::new(),
+        };
+
+        let mut history = StatsHistory::new(tmp.path());
+        history.record(snapshot("2026-01-01", 1)).await;
+        history.record(snapshot("2026-01-02", 2)).await;
+        history.record(snapshot("2026-01-02", 3)).await;
+
+        let mut reloaded = StatsHistory::new(tmp.path());
+        reloaded.load().await;
+        assert_eq!(reloaded.snapshots().len(), 2);
+        assert_eq!(reloaded.snapshots()[1].total.files, 3);
+    }
+}
@@ -1,5 +1,7 @@
-use crate::scanner::process_entry;
+use crate::library::{add_games, enrich_library, notify_new_versions, upsert_game};
+use crate::scanner::discover;
 use crate::state::AppState;
+use crate::stats::record_snapshot;
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
 use std::collections::HashMap;
 use std::sync::mpsc::channel;
@@ -15,8 +17,8 @@ pub fn start_background_tasks(state: AppState) {
         loop {
             interval.tick().await;
             info!("Starting periodic metadata sync...");
-            let mut meta = state_sync.metadata.lock().await;
-            if let Err(e) = meta.sync().await {
+            let before = state_sync.metadata.snapshot();
+            if let Err(e) = state_sync.metadata.sync().await {
                 error!("Failed to sync metadata: {}", e);
             } else {
                 info!("Metadata sync complete.");
@@ -27,16 +29,77 @@ pub fn start_background_tasks(state: AppState) {
                     })
                     .to_string(),
                 );
+                notify_new_versions(&state_sync, &before);
             }
-            drop(meta);
         }
     });
 
-    // 2. Download Speed Calculator Task
+    // 1b. Remote Metadata Sync Task: the remote service is refreshed more
+    // often than titledb
+    if state.settings.metadata_remote_url.is_some()
+        && state.settings.metadata_remote_interval_hours > 0
+    {
+        let state_remote = state.clone();
+        tokio::spawn(async move {
+            let period =
+                Duration::from_secs(state_remote.settings.metadata_remote_interval_hours * 3600);
+            // The first tick would repeat the download of the full sync
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = state_remote.metadata.sync_remote().await {
+                    error!("Failed to sync remote metadata: {}", e);
+                }
+            }
+        });
+    }
+
+    // 2. Library Enrichment Task: re-apply metadata whenever a new snapshot is published
+    let state_enrich = state.clone();
+    tokio::spawn(async move {
+        let mut rx = state_enrich.metadata.subscribe();
+        while rx.changed().await.is_ok() {
+            let state = state_enrich.clone();
+            let _ = tokio::task::spawn_blocking(move || enrich_library(&state)).await;
+        }
+    });
+
+    // 3. Quarantine and Trash Retention Task
+    let state_purge = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(24 * 3600));
+        loop {
+            interval.tick().await;
+            let retention = state_purge.settings.quarantine_retention_days;
+            let purged = state_purge
+                .quarantine
+                .lock()
+                .await
+                .purge_expired(retention)
+                .await;
+            if !purged.is_empty() {
+                info!("Purged {} expired files from quarantine", purged.len());
+            }
+            let retention = state_purge.settings.trash_retention_days;
+            let purged = state_purge
+                .trash
+                .lock()
+                .await
+                .purge_expired(retention)
+                .await;
+            if !purged.is_empty() {
+                info!("Purged {} expired files from the trash", purged.len());
+            }
+        }
+    });
+
+    // 4. Download Speed Calculator Task
     let state_speed = state.clone();
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(1));
         let mut last_bytes_map: HashMap<String, u64> = HashMap::new();
+        let mut was_active = false;
 
         loop {
             interval.tick().await;
@@ -58,7 +121,10 @@ pub fn start_background_tasks(state: AppState) {
             // Clean up finished downloads from local map
             last_bytes_map.retain(|k, _| current_ids.contains(k));
 
-            if !downloads.is_empty()
+            // Finished downloads leave the map; one last update clears them
+            // from the UI
+            let active = !downloads.is_empty();
+            if (active || was_active)
                 && let Ok(data_json) = serde_json::to_value(&*downloads)
             {
                 let msg = serde_json::json!({
@@ -68,10 +134,11 @@ pub fn start_background_tasks(state: AppState) {
                 .to_string();
                 let _ = state_speed.tx.send(msg);
             }
+            was_active = active;
         }
     });
 
-    // 3. Initial Game Scanning Task
+    // 5. Initial Game Scanning Task
     let state_scan = state.clone();
     tokio::task::spawn_blocking(move || {
         info!(
@@ -92,26 +159,16 @@ pub fn start_background_tasks(state: AppState) {
         let mut batch = Vec::new();
         let mut total_count = 0;
 
-        let handle = tokio::runtime::Handle::current();
-        let meta_provider_guard = handle.block_on(state_scan.metadata.lock());
-
         for entry in WalkDir::new(&state_scan.settings.games_dir)
             .into_iter()
             .filter_map(|e| e.ok())
         {
-            if let Some(game) = process_entry(
-                entry.path(),
-                &state_scan.settings.games_dir,
-                &state_scan.settings.data_dir,
-                Some(&meta_provider_guard),
-            ) {
+            if let Some(game) = discover(entry.path(), &state_scan.settings.games_dir) {
                 batch.push(game);
                 total_count += 1;
 
                 if batch.len() >= 50 {
-                    let mut g_lock = state_scan.games.lock().unwrap();
-                    g_lock.extend(batch.drain(..));
-                    drop(g_lock);
+                    add_games(&state_scan, std::mem::take(&mut batch));
 
                     let _ = state_scan.tx.send(
                         serde_json::json!({
@@ -126,8 +183,7 @@ pub fn start_background_tasks(state: AppState) {
         }
 
         if !batch.is_empty() {
-            let mut g_lock = state_scan.games.lock().unwrap();
-            g_lock.extend(batch);
+            add_games(&state_scan, batch);
         }
 
         info!(
@@ -136,6 +192,21 @@ pub fn start_background_tasks(state: AppState) {
             start_time.elapsed()
         );
 
+        if state_scan.metadata.library_only_details() {
+            let ids = state_scan
+                .games
+                .lock()
+                .unwrap()
+                .iter()
+                .flat_map(|g| [g.title_id.clone(), g.base_title_id.clone()])
+                .flatten()
+                .collect();
+            if state_scan.metadata.set_library_ids(ids) {
+                let metadata = state_scan.metadata.clone();
+                tokio::runtime::Handle::current().spawn(async move { metadata.reload().await });
+            }
+        }
+
         let _ = state_scan.tx.send(
             serde_json::json!({
                 "type": "scan",
@@ -144,9 +215,24 @@ pub fn start_background_tasks(state: AppState) {
             })
             .to_string(),
         );
+
+        // Daily size snapshots start once the library is fully indexed
+        let state_stats = state_scan.clone();
+        tokio::runtime::Handle::current().spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(24 * 3600));
+            loop {
+                interval.tick().await;
+                record_snapshot(&state_stats).await;
+            }
+        });
+
+        // Hashing starts once the library is fully indexed
+        if state_scan.settings.integrity_enabled {
+            crate::integrity::start(state_scan.clone());
+        }
     });
 
-    // 4. File Watcher Task
+    // 6. File Watcher Task
     let state_watch = state.clone();
     tokio::task::spawn_blocking(move || {
         let (std_tx, std_rx) = channel();
@@ -165,6 +251,15 @@ pub fn start_background_tasks(state: AppState) {
             use notify::EventKind;
             use notify::event::{ModifyKind, RenameMode};
 
+            // The API already applied its own moves and deletes
+            if event
+                .paths
+                .iter()
+                .any(|p| state_watch.watch_suppression.is_held(p))
+            {
+                continue;
+            }
+
             match event.kind {
                 EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
                     if event.paths.len() == 2 {
@@ -173,24 +268,20 @@ pub fn start_background_tasks(state: AppState) {
 
                         let mut games = state_watch.games.lock().unwrap();
                         if let Some(idx) = games.iter().position(|g| g.path == *from) {
-                            games.remove(idx);
+                            let removed = games.remove(idx);
                             let _ = state_watch.tx.send(
-                                serde_json::json!({ "type": "scan", "status": "remove", "path": from })
+                                serde_json::json!({ "type": "scan", "status": "remove", "id": removed.id, "path": from })
                                     .to_string(),
                             );
                         }
                         drop(games);
 
-                        let handle = tokio::runtime::Handle::current();
-                        let meta_provider = handle.block_on(state_watch.metadata.lock());
-                        if let Some(game) = process_entry(
-                            to,
-                            &state_watch.settings.games_dir,
-                            &state_watch.settings.data_dir,
-                            Some(&meta_provider),
-                        ) {
-                            let mut games = state_watch.games.lock().unwrap();
-                            games.push(game.clone());
+                        if let Some(game) = discover(to, &state_watch.settings.games_dir) {
+                            let mut game = upsert_game(&state_watch, game);
+                            state_watch
+                                .user_library
+                                .blocking_lock()
+                                .join(std::slice::from_mut(&mut game));
                             let _ = state_watch.tx.send(
                                 serde_json::json!({ "type": "scan", "status": "update", "game": game })
                                     .to_string(),
@@ -200,26 +291,16 @@ pub fn start_background_tasks(state: AppState) {
                 }
                 EventKind::Create(_) | EventKind::Modify(_) => {
                     for path in event.paths {
-                        if path.is_file() {
-                            let handle = tokio::runtime::Handle::current();
-                            let meta_provider = handle.block_on(state_watch.metadata.lock());
-                            if let Some(game) = process_entry(
-                                &path,
-                                &state_watch.settings.games_dir,
-                                &state_watch.settings.data_dir,
-                                Some(&meta_provider),
-                            ) {
-                                let mut games = state_watch.games.lock().unwrap();
-                                if let Some(idx) = games.iter().position(|g| g.path == game.path) {
-                                    games[idx] = game.clone();
-                                } else {
-                                    games.push(game.clone());
-                                }
-                                let _ = state_watch.tx.send(
-                                    serde_json::json!({ "type": "scan", "status": "update", "game": game })
-                                        .to_string(),
-                                );
-                            }
+                        if let Some(game) = discover(&path, &state_watch.settings.games_dir) {
+                            let mut game = upsert_game(&state_watch, game);
+                            state_watch
+                                .user_library
+                                .blocking_lock()
+                                .join(std::slice::from_mut(&mut game));
+                            let _ = state_watch.tx.send(
+                                serde_json::json!({ "type": "scan", "status": "update", "game": game })
+                                    .to_string(),
+                            );
                         }
                     }
                 }
@@ -227,9 +308,9 @@ pub fn start_background_tasks(state: AppState) {
                     for path in event.paths {
                         let mut games = state_watch.games.lock().unwrap();
                         if let Some(idx) = games.iter().position(|g| g.path == path) {
-                            games.remove(idx);
+                            let removed = games.remove(idx);
                             let _ = state_watch.tx.send(
-                                serde_json::json!({ "type": "scan", "status": "remove", "path": path })
+                                serde_json::json!({ "type": "scan", "status": "remove", "id": removed.id, "path": path })
                                     .to_string(),
                             );
                         }
@@ -239,4 +320,9 @@ pub fn start_background_tasks(state: AppState) {
             }
         }
     });
+
+    // 7. Inbox Ingestion Task
+    if let Some(inbox) = state.settings.inbox_dir.clone() {
+        crate::inbox::start(state, inbox);
+    }
 }
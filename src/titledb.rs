@@ -0,0 +1,467 @@
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct TitleInfo {
+    pub id: String,
+    pub name: Option<String>,
+    pub icon_url: Option<String>,
+    pub banner_url: Option<String>,
+    pub category: Option<Vec<String>>,
+    pub description: Option<String>,
+    pub publisher: Option<String>,
+    /// Release date as `YYYY-MM-DD`.
+    #[serde(default)]
+    pub release_date: Option<String>,
+    /// Install size reported by the eShop, in bytes.
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Age rating (e.g. 12 for PEGI 12 / ESRB equivalent).
+    #[serde(default)]
+    pub rating: Option<u32>,
+    #[serde(default)]
+    pub rating_content: Option<Vec<String>>,
+    #[serde(default)]
+    pub number_of_players: Option<u32>,
+    #[serde(default)]
+    pub languages: Option<Vec<String>>,
+    #[serde(default)]
+    pub nsu_id: Option<u64>,
+    #[serde(default)]
+    pub screenshots: Option<Vec<String>>,
+    #[serde(default)]
+    pub intro: Option<String>,
+    /// Locale whose titledb record supplied this entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Further locales that filled fields missing from the primary record.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_locales: Vec<String>,
+}
+
+impl TitleInfo {
+    /// Fills fields that are still missing from `other`. Returns whether
+    /// anything was taken from it.
+    pub fn fill_from(&mut self, other: TitleInfo) -> bool {
+        fn fill<T>(field: &mut Option<T>, value: Option<T>) -> bool {
+            if field.is_none() && value.is_some() {
+                *field = value;
+                true
+            } else {
+                false
+            }
+        }
+
+        let mut used = fill(&mut self.name, other.name);
+        used |= fill(&mut self.icon_url, other.icon_url);
+        used |= fill(&mut self.banner_url, other.banner_url);
+        used |= fill(&mut self.category, other.category);
+        used |= fill(&mut self.description, other.description);
+        used |= fill(&mut self.publisher, other.publisher);
+        used |= fill(&mut self.release_date, other.release_date);
+        used |= fill(&mut self.size, other.size);
+        used |= fill(&mut self.rating, other.rating);
+        used |= fill(&mut self.rating_content, other.rating_content);
+        used |= fill(&mut self.number_of_players, other.number_of_players);
+        used |= fill(&mut self.languages, other.languages);
+        used |= fill(&mut self.nsu_id, other.nsu_id);
+        used |= fill(&mut self.screenshots, other.screenshots);
+        used |= fill(&mut self.intro, other.intro);
+        used
+    }
+}
+
+/// The subset of a titledb record we keep. Unknown fields are skipped by the
+/// deserializer without being materialized, and a field of an unexpected
+/// type is dropped rather than failing the whole file.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawTitle {
+    #[serde(default, deserialize_with = "lenient_string")]
+    name: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    icon_url: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    banner_url: Option<String>,
+    #[serde(default, deserialize_with = "lenient_strings")]
+    category: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    description: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    publisher: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    release_date: Option<String>,
+    #[serde(default, deserialize_with = "lenient_u64")]
+    size: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_u64")]
+    rating: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_strings")]
+    rating_content: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "lenient_u64")]
+    number_of_players: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_strings")]
+    languages: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "lenient_u64")]
+    nsu_id: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_strings")]
+    screenshots: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    intro: Option<String>,
+}
+
+impl RawTitle {
+    /// Without `full`, only the fields needed for listing and matching are
+    /// kept; descriptions, screenshots and the like are dropped.
+    fn into_info(self, id: String, full: bool) -> TitleInfo {
+        let mut info = TitleInfo {
+            id,
+            name: self.name,
+            icon_url: self.icon_url,
+            category: self.category,
+            publisher: self.publisher,
+            release_date: self.release_date.as_deref().and_then(format_release_date),
+            size: self.size,
+            rating: self.rating.and_then(|v| u32::try_from(v).ok()),
+            number_of_players: self.number_of_players.and_then(|v| u32::try_from(v).ok()),
+            nsu_id: self.nsu_id,
+            ..Default::default()
+        };
+        if full {
+            info.banner_url = self.banner_url;
+            info.description = self.description;
+            info.rating_content = self.rating_content;
+            info.languages = self.languages;
+            info.screenshots = self.screenshots;
+            info.intro = self.intro;
+        }
+        info
+    }
+}
+
+/// Accepts numbers, numeric strings and null; anything else becomes `None`
+/// instead of failing the whole file. Fractions are rounded.
+fn lenient_u64<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(lenient_string(deserializer)?.and_then(|s| {
+        s.parse().ok().or_else(|| {
+            s.parse::<f64>()
+                .ok()
+                .map(f64::round)
+                .filter(|v| (0.0..u64::MAX as f64).contains(v))
+                .map(|v| v as u64)
+        })
+    }))
+}
+
+/// Accepts strings, numbers and null; arrays, objects and booleans become
+/// `None`.
+fn lenient_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserializer.deserialize_any(Lenient)
+}
+
+/// Accepts an array, keeping its string and number items; anything else
+/// becomes `None`.
+fn lenient_strings<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct Items;
+
+    impl<'de> serde::de::Visitor<'de> for Items {
+        type Value = Option<Vec<String>>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("an array of strings")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::SeqAccess<'de>,
+        {
+            let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(Text(item)) = seq.next_element()? {
+                items.extend(item);
+            }
+            Ok(Some(items))
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::MapAccess<'de>,
+        {
+            while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+            Ok(None)
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, _: u64) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, _: i64) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_f64<E: serde::de::Error>(self, _: f64) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_str<E: serde::de::Error>(self, _: &str) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_bool<E: serde::de::Error>(self, _: bool) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+    }
+
+    deserializer.deserialize_any(Items)
+}
+
+/// An array item read with [`lenient_string`].
+struct Text(Option<String>);
+
+impl<'de> Deserialize<'de> for Text {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        lenient_string(deserializer).map(Text)
+    }
+}
+
+struct Lenient;
+
+impl<'de> serde::de::Visitor<'de> for Lenient {
+    type Value = Option<String>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a string, number or null")
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn visit_bool<E: serde::de::Error>(self, _: bool) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(None)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+        Ok(None)
+    }
+}
+
+/// A titledb record, or `None` when it is not an object.
+struct Record(Option<RawTitle>);
+
+impl<'de> Deserialize<'de> for Record {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RecordVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for RecordVisitor {
+            type Value = Record;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a titledb record")
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                RawTitle::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+                    .map(|raw| Record(Some(raw)))
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(Record(None))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, _: u64) -> Result<Self::Value, E> {
+                Ok(Record(None))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, _: i64) -> Result<Self::Value, E> {
+                Ok(Record(None))
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, _: f64) -> Result<Self::Value, E> {
+                Ok(Record(None))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, _: &str) -> Result<Self::Value, E> {
+                Ok(Record(None))
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, _: bool) -> Result<Self::Value, E> {
+                Ok(Record(None))
+            }
+
+            fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+                Ok(Record(None))
+            }
+        }
+
+        deserializer.deserialize_any(RecordVisitor)
+    }
+}
+
+/// Streams a titledb titles file (`{ "<id>": { ... }, ... }`) into
+/// [`TitleInfo`]s without holding the file or an intermediate JSON tree in
+/// memory. When `library` is given, full records are only kept for those IDs.
+pub fn read_titles<R: std::io::Read>(
+    reader: R,
+    library: Option<&HashSet<String>>,
+) -> serde_json::Result<HashMap<String, TitleInfo>> {
+    struct TitlesVisitor<'a> {
+        library: Option<&'a HashSet<String>>,
+    }
+
+    impl<'de> serde::de::Visitor<'de> for TitlesVisitor<'_> {
+        type Value = HashMap<String, TitleInfo>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a map of title IDs to titledb records")
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::MapAccess<'de>,
+        {
+            let mut titles = HashMap::with_capacity(map.size_hint().unwrap_or(0));
+            while let Some(id) = map.next_key::<String>()? {
+                let Record(Some(raw)) = map.next_value()? else {
+                    warn!("Skipping titledb record {}: not an object", id);
+                    continue;
+                };
+                let id = id.to_uppercase();
+                let full = self.library.is_none_or(|ids| ids.contains(&id));
+                let info = raw.into_info(id.clone(), full);
+                titles.insert(id, info);
+            }
+            titles.shrink_to_fit();
+            Ok(titles)
+        }
+    }
+
+    let mut de = serde_json::Deserializer::from_reader(std::io::BufReader::new(reader));
+    let titles = serde::Deserializer::deserialize_map(&mut de, TitlesVisitor { library })?;
+    de.end()?;
+    Ok(titles)
+}
+
+/// titledb stores release dates as `YYYYMMDD` integers.
+fn format_release_date(raw: &str) -> Option<String> {
+    if raw.len() == 8 && raw.chars().all(|c| c.is_ascii_digit()) {
+        Some(format!("{}-{}-{}", &raw[..4], &raw[4..6], &raw[6..]))
+    } else if raw.is_empty() {
+        None
+    } else {
+        Some(raw.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_titles_details() {
+        let titles = read_titles(
+            r#"{ "0100000000010000": {
+                "name": "Game", "releaseDate": 20170303, "size": 1048576,
+                "rating": 12, "numberOfPlayers": 4, "nsuId": 70010000000025,
+                "languages": ["en", "ja"], "screenshots": ["https://example/1.jpg"],
+                "intro": "Jump!", "unused": { "nested": [1, 2, 3] }
+            } }"#
+                .as_bytes(),
+            None,
+        )
+        .unwrap();
+        let game = &titles["0100000000010000"];
+        assert_eq!(game.release_date.as_deref(), Some("2017-03-03"));
+        assert_eq!(game.size, Some(1048576));
+        assert_eq!(game.rating, Some(12));
+        assert_eq!(game.number_of_players, Some(4));
+        assert_eq!(game.nsu_id, Some(70010000000025));
+        assert_eq!(game.languages.as_ref().unwrap().len(), 2);
+        assert_eq!(game.intro.as_deref(), Some("Jump!"));
+    }
+
+    #[test]
+    fn test_parse_titles_unexpected_types() {
+        let titles = read_titles(
+            r#"{
+                "0100000000010000": {
+                    "name": "Game", "category": "Action", "publisher": ["Acme"],
+                    "languages": ["en", null, {}], "rating": 12.6,
+                    "numberOfPlayers": 5000000000, "screenshots": { "a": 1 }
+                },
+                "0100000000020000": null,
+                "0100000000030000": ["not", "a", "record"],
+                "0100000000040000": { "name": "Other" }
+            }"#
+            .as_bytes(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(titles.len(), 2);
+        let game = &titles["0100000000010000"];
+        assert_eq!(game.name.as_deref(), Some("Game"));
+        assert_eq!(game.category, None);
+        assert_eq!(game.publisher, None);
+        assert_eq!(game.languages.as_deref(), Some(&["en".to_string()][..]));
+        assert_eq!(game.rating, Some(13));
+        assert_eq!(game.number_of_players, None);
+        assert_eq!(game.screenshots, None);
+        assert_eq!(titles["0100000000040000"].name.as_deref(), Some("Other"));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::metadata::MetadataProvider;
+use crate::scanner::Game;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// Everything owned for one base title: the game itself, its updates and
+/// its DLC, with what is still missing according to the metadata.
+#[derive(Debug, Serialize, Clone)]
+pub struct TitleFamily {
+    pub base_title_id: String,
+    pub name: String,
+    pub icon_url: Option<String>,
+    pub base: Vec<Game>,
+    /// Oldest first.
+    pub updates: Vec<Game>,
+    pub dlc: Vec<Game>,
+    /// Newest version known to the metadata.
+    pub latest_version: Option<u64>,
+    /// Newest version among the owned base files and updates.
+    pub owned_version: Option<u64>,
+    /// Known versions newer than `owned_version`.
+    pub missing_updates: usize,
+    pub missing_dlc: usize,
+    /// What the user recorded about the title, filled in by the handlers.
+    pub user_data: Option<crate::userdata::UserData>,
+}
+
+/// The numeric version of a file, from `v65536` or `65536`.
+pub fn version_number(game: &Game) -> Option<u64> {
+    game.version
+        .as_deref()
+        .map(|v| v.trim_start_matches(['v', 'V']))
+        .and_then(|v| v.parse().ok())
+}
+
+/// The base title a file belongs to.
+fn family_key(game: &Game, meta: &MetadataProvider) -> Option<String> {
+    let tid = game.title_id.as_deref()?;
+    game.base_title_id
+        .clone()
+        .or_else(|| meta.get_base_title_id(tid))
+        .or_else(|| Some(tid.to_string()))
+}
+
+/// Groups the library by base title. Files without a title ID cannot be
+/// placed and are left out.
+pub fn group_titles(games: &[Game], meta: &MetadataProvider) -> Vec<TitleFamily> {
+    let mut groups: BTreeMap<String, Vec<&Game>> = BTreeMap::new();
+    for game in games {
+        if let Some(base) = family_key(game, meta) {
+            groups.entry(base).or_default().push(game);
+        }
+    }
+
+    let mut families: Vec<TitleFamily> = groups
+        .into_iter()
+        .map(|(base_id, files)| build_family(base_id, files, meta))
+        .collect();
+    families.sort_by_cached_key(|f| (f.name.to_lowercase(), f.base_title_id.clone()));
+    families
+}
+
+/// The family of `base_id` alone, or `None` if nothing of it is owned.
+pub fn title_family(games: &[Game], meta: &MetadataProvider, base_id: &str) -> Option<TitleFamily> {
+    let base_id = base_id.to_uppercase();
+    let files: Vec<&Game> = games
+        .iter()
+        .filter(|g| family_key(g, meta).as_deref() == Some(base_id.as_str()))
+        .collect();
+    (!files.is_empty()).then(|| build_family(base_id, files, meta))
+}
+
+fn build_family(base_id: String, files: Vec<&Game>, meta: &MetadataProvider) -> TitleFamily {
+    let mut base = Vec::new();
+    let mut updates = Vec::new();
+    let mut dlc = Vec::new();
+    for game in files {
+        match game.category.as_str() {
+            "Update" => updates.push(game.clone()),
+            "DLC" => dlc.push(game.clone()),
+            _ => base.push(game.clone()),
+        }
+    }
+    updates.sort_by_key(|g| (version_number(g), g.relative_path.clone()));
+    dlc.sort_by(|a, b| {
+        a.title_id
+            .cmp(&b.title_id)
+            .then(a.relative_path.cmp(&b.relative_path))
+    });
+
+    let info = meta.get_title_info(&base_id);
+    let name = info
+        .as_ref()
+        .and_then(|i| i.name.clone())
+        .or_else(|| base.first().map(|g| g.name.clone()))
+        .or_else(|| updates.first().map(|g| g.name.clone()))
+        .unwrap_or_else(|| base_id.clone());
+
+    let owned_version = base.iter().chain(&updates).filter_map(version_number).max();
+    let known = meta.get_versions(&base_id);
+    let missing_updates = known
+        .iter()
+        .filter(|(v, _)| owned_version.is_none_or(|owned| *v > owned))
+        .count();
+
+    let owned_dlc: HashSet<&str> = dlc.iter().filter_map(|g| g.title_id.as_deref()).collect();
+    let missing_dlc = meta
+        .get_dlc_ids(&base_id)
+        .iter()
+        .filter(|id| !owned_dlc.contains(id.as_str()))
+        .count();
+
+    TitleFamily {
+        name,
+        icon_url: info.and_then(|i| i.icon_url),
+        latest_version: known.last().map(|(v, _)| *v),
+        owned_version,
+        missing_updates,
+        missing_dlc,
+        base,
+        updates,
+        dlc,
+        base_title_id: base_id,
+        user_data: None,
+    }
+}
+
+/// An owned game with newer versions available than the newest owned one.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct OutdatedTitle {
+    pub base_title_id: String,
+    pub name: String,
+    pub owned_version: u64,
+    pub owned_release_date: Option<String>,
+    pub latest_version: u64,
+    pub latest_release_date: Option<String>,
+    /// How many known versions lie between the owned and the latest one,
+    /// the latest included.
+    pub versions_behind: usize,
+}
+
+/// Owned base games whose newest owned update is older than the latest
+/// version known to the metadata.
+pub fn outdated_titles(games: &[Game], meta: &MetadataProvider) -> Vec<OutdatedTitle> {
+    group_titles(games, meta)
+        .into_iter()
+        .filter(|f| !f.base.is_empty())
+        .filter_map(|f| {
+            let owned = f.owned_version.unwrap_or(0);
+            let latest = f.latest_version.filter(|latest| *latest > owned)?;
+            let versions = meta.get_versions(&f.base_title_id);
+            let date = |version: u64| {
+                versions
+                    .iter()
+                    .find(|(v, _)| *v == version)
+                    .map(|(_, d)| d.clone())
+            };
+            Some(OutdatedTitle {
+                owned_release_date: date(owned),
+                latest_release_date: date(latest),
+                versions_behind: versions.iter().filter(|(v, _)| *v > owned).count(),
+                owned_version: owned,
+                latest_version: latest,
+                name: f.name,
+                base_title_id: f.base_title_id,
+            })
+        })
+        .collect()
+}
+
+/// Outdated titles whose latest version is newer in `after` than it was in
+/// `before`, i.e. the updates a metadata sync just revealed.
+pub fn newly_outdated(
+    games: &[Game],
+    before: &MetadataProvider,
+    after: &MetadataProvider,
+) -> Vec<OutdatedTitle> {
+    outdated_titles(games, after)
+        .into_iter()
+        .filter(|t| {
+            before
+                .get_versions(&t.base_title_id)
+                .last()
+                .is_none_or(|(previous, _)| *previous < t.latest_version)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::MetadataStore;
+    use tempfile::tempdir;
+
+    async fn provider(versions: &str) -> (std::sync::Arc<MetadataProvider>, tempfile::TempDir) {
+        let tmp = tempdir().unwrap();
+        let titledb = tmp.path().join("titledb");
+        std::fs::create_dir_all(&titledb).unwrap();
+        std::fs::write(titledb.join("versions.json"), versions).unwrap();
+        let store = MetadataStore::new(tmp.path().to_path_buf(), vec![]);
+        store.init().await;
+        (store.snapshot(), tmp)
+    }
+
+    fn game(title_id: &str, version: &str, category: &str) -> Game {
+        Game {
+            name: "Game".to_string(),
+            relative_path: format!("Game [{}][{}].nsp", title_id, version),
+            title_id: Some(title_id.to_string()),
+            base_title_id: Some("0100000000010000".to_string()),
+            version: Some(version.to_string()),
+            category: category.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outdated_titles() {
+        let games = vec![
+            game("0100000000010000", "v0", "Base"),
+            game("0100000000010800", "v65536", "Update"),
+        ];
+        let (before, _tmp1) =
+            provider(r#"{ "0100000000010000": { "65536": "2020-01-01" } }"#).await;
+        let (after, _tmp2) = provider(
+            r#"{ "0100000000010000": { "65536": "2020-01-01", "131072": "2020-06-01" } }"#,
+        )
+        .await;
+
+        assert!(outdated_titles(&games, &before).is_empty());
+        let outdated = outdated_titles(&games, &after);
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0].owned_version, 65536);
+        assert_eq!(
+            outdated[0].owned_release_date.as_deref(),
+            Some("2020-01-01")
+        );
+        assert_eq!(outdated[0].latest_version, 131072);
+        assert_eq!(outdated[0].versions_behind, 1);
+
+        assert_eq!(newly_outdated(&games, &before, &after), outdated);
+        assert!(newly_outdated(&games, &after, &after).is_empty());
+
+        // Updates without the base game are not reported
+        assert!(outdated_titles(&games[1..], &after).is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::jsonfile;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// An upload in progress. Its data is appended to `<id>.part` until
+/// `offset` reaches `size`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Upload {
+    pub id: String,
+    /// Where the file goes, relative to `games_dir`.
+    pub relative_path: String,
+    pub size: u64,
+    /// Bytes received so far; taken from the partial file on load.
+    pub offset: u64,
+    pub created_at: u64,
+}
+
+impl Upload {
+    pub fn remaining(&self) -> u64 {
+        self.size.saturating_sub(self.offset)
+    }
+}
+
+/// Partial uploads, kept in `data_dir/uploads` so they survive a restart
+/// and can be resumed. The index is persisted in `index.json`.
+pub struct Uploads {
+    dir: PathBuf,
+    uploads: Vec<Upload>,
+    /// Held while a chunk is written, so one upload isn't appended to twice.
+    locks: HashMap<String, Arc<tokio::sync::Mutex<()>>>,
+}
+
+impl Uploads {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            dir: data_dir.join("uploads"),
+            uploads: Vec::new(),
+            locks: HashMap::new(),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        let Some(uploads) = jsonfile::load::<Vec<Upload>>(&self.dir.join("index.json")).await
+        else {
+            return;
+        };
+        // The partial files are the truth: a write may have been cut short
+        // after the index was saved
+        for mut upload in uploads {
+            match tokio::fs::metadata(self.part_path(&upload.id)).await {
+                Ok(meta) => {
+                    upload.offset = meta.len().min(upload.size);
+                    self.uploads.push(upload);
+                }
+                Err(_) => warn!("Partial upload {} is missing, dropping it", upload.id),
+            }
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        jsonfile::save_pretty(&self.dir.join("index.json"), &self.uploads).await
+    }
+
+    pub fn uploads(&self) -> &[Upload] {
+        &self.uploads
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Upload> {
+        self.uploads.iter().find(|u| u.id == id)
+    }
+
+    pub fn part_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.part", id))
+    }
+
+    pub fn lock(&mut self, id: &str) -> Arc<tokio::sync::Mutex<()>> {
+        self.locks.entry(id.to_string()).or_default().clone()
+    }
+
+    /// Registers a new upload with an empty partial file.
+    pub async fn create(&mut self, upload: Upload) -> std::io::Result<Upload> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::File::create(self.part_path(&upload.id)).await?;
+        info!("Started upload of {}", upload.relative_path);
+        self.uploads.push(upload.clone());
+        self.save().await?;
+        Ok(upload)
+    }
+
+    pub async fn set_offset(&mut self, id: &str, offset: u64) -> std::io::Result<()> {
+        if let Some(upload) = self.uploads.iter_mut().find(|u| u.id == id) {
+            upload.offset = offset;
+        }
+        self.save().await
+    }
+
+    /// Forgets an upload. Its partial file is deleted unless it was
+    /// already moved into the library.
+    pub async fn remove(&mut self, id: &str) -> std::io::Result<Option<Upload>> {
+        let Some(idx) = self.uploads.iter().position(|u| u.id == id) else {
+            return Ok(None);
+        };
+        let upload = self.uploads.remove(idx);
+        self.locks.remove(id);
+        match tokio::fs::remove_file(self.part_path(id)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        self.save().await?;
+        Ok(Some(upload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_uploads_resume_from_partial_file() {
+        let tmp = tempdir().unwrap();
+        let mut uploads = Uploads::new(tmp.path());
+        let upload = uploads
+            .create(Upload {
+                id: "u1".to_string(),
+                relative_path: "Game.nsp".to_string(),
+                size: 10,
+                offset: 0,
+                created_at: 0,
+            })
+            .await
+            .unwrap();
+        // Data written after the index was last saved still counts
+        std::fs::write(uploads.part_path(&upload.id), b"1234").unwrap();
+
+        let mut reloaded = Uploads::new(tmp.path());
+        reloaded.load().await;
+        assert_eq!(reloaded.get("u1").unwrap().offset, 4);
+        assert_eq!(reloaded.get("u1").unwrap().remaining(), 6);
+
+        reloaded.remove("u1").await.unwrap();
+        assert!(!reloaded.part_path("u1").exists());
+        let mut reloaded = Uploads::new(tmp.path());
+        reloaded.load().await;
+        assert!(reloaded.uploads().is_empty());
+    }
+}
@@ -0,0 +1,233 @@
+use crate::jsonfile;
+use crate::scanner::Game;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Notes longer than this are refused.
+pub const MAX_NOTE_LEN: usize = 4000;
+
+/// What the user recorded about a title. Updates and DLC share the data of
+/// their base game.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct UserData {
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Names of the collections the title is in.
+    #[serde(default)]
+    pub collections: Vec<String>,
+}
+
+/// Trims `names` and drops empty and repeated ones, ignoring case.
+fn clean(names: Vec<String>) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for name in names {
+        let name = name.trim();
+        if !name.is_empty() && !cleaned.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            cleaned.push(name.to_string());
+        }
+    }
+    cleaned
+}
+
+fn contains(names: &[String], name: &str) -> bool {
+    names.iter().any(|n| n.eq_ignore_ascii_case(name.trim()))
+}
+
+impl UserData {
+    /// Trims tags, collections and the note, dropping empty ones.
+    pub fn normalized(self) -> Self {
+        Self {
+            favorite: self.favorite,
+            tags: clean(self.tags),
+            note: self
+                .note
+                .map(|n| n.trim().to_string())
+                .filter(|n| !n.is_empty()),
+            collections: clean(self.collections),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        contains(&self.tags, tag)
+    }
+
+    pub fn in_collection(&self, collection: &str) -> bool {
+        contains(&self.collections, collection)
+    }
+}
+
+/// The title a game's user data is kept under: its base game's, so it
+/// survives renames and is shared by updates and DLC.
+pub fn key(game: &Game) -> Option<String> {
+    game.base_title_id
+        .as_ref()
+        .or(game.title_id.as_ref())
+        .map(|id| id.to_uppercase())
+}
+
+/// User data keyed by title ID, persisted in `data_dir/user_data.json`.
+pub struct UserLibrary {
+    path: PathBuf,
+    titles: BTreeMap<String, UserData>,
+}
+
+impl UserLibrary {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join("user_data.json"),
+            titles: BTreeMap::new(),
+        }
+    }
+
+    pub async fn load(&mut self) {
+        if let Some(titles) = jsonfile::load(&self.path).await {
+            self.titles = titles;
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        jsonfile::save_pretty(&self.path, &self.titles).await
+    }
+
+    pub fn titles(&self) -> &BTreeMap<String, UserData> {
+        &self.titles
+    }
+
+    pub fn get(&self, title_id: &str) -> Option<&UserData> {
+        self.titles.get(&title_id.to_uppercase())
+    }
+
+    /// Stores the data of a title. Empty data removes the entry.
+    pub async fn set(&mut self, title_id: &str, data: UserData) -> std::io::Result<UserData> {
+        let data = data.normalized();
+        if data.is_empty() {
+            self.titles.remove(&title_id.to_uppercase());
+        } else {
+            self.titles.insert(title_id.to_uppercase(), data.clone());
+        }
+        self.save().await?;
+        Ok(data)
+    }
+
+    pub async fn remove(&mut self, title_id: &str) -> std::io::Result<Option<UserData>> {
+        let removed = self.titles.remove(&title_id.to_uppercase());
+        if removed.is_some() {
+            self.save().await?;
+        }
+        Ok(removed)
+    }
+
+    /// Every collection with the titles in it, by name.
+    pub fn collections(&self) -> BTreeMap<String, Vec<String>> {
+        let mut collections: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (title_id, data) in &self.titles {
+            for name in &data.collections {
+                let existing = collections
+                    .keys()
+                    .find(|k| k.eq_ignore_ascii_case(name))
+                    .cloned();
+                collections
+                    .entry(existing.unwrap_or_else(|| name.clone()))
+                    .or_default()
+                    .push(title_id.clone());
+            }
+        }
+        collections
+    }
+
+    /// Takes every title out of a collection. Returns how many were in it.
+    pub async fn remove_collection(&mut self, name: &str) -> std::io::Result<usize> {
+        let mut removed = 0;
+        for data in self.titles.values_mut() {
+            if data.in_collection(name) {
+                data.collections
+                    .retain(|c| !c.eq_ignore_ascii_case(name.trim()));
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            self.titles.retain(|_, data| !data.is_empty());
+            self.save().await?;
+        }
+        Ok(removed)
+    }
+
+    /// The stored data of a game's title.
+    pub fn for_game(&self, game: &Game) -> Option<&UserData> {
+        key(game).and_then(|k| self.titles.get(&k))
+    }
+
+    /// Sets `Game.user_data` from the stored data of each game's title.
+    pub fn join(&self, games: &mut [Game]) {
+        for game in games {
+            game.user_data = self.for_game(game).cloned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_user_library() {
+        let tmp = tempdir().unwrap();
+        let mut library = UserLibrary::new(tmp.path());
+        let data = library
+            .set(
+                "0100000000010000",
+                UserData {
+                    favorite: true,
+                    tags: vec![" kids ".into(), "Kids".into(), "".into(), "co-op".into()],
+                    note: Some("  ".into()),
+                    collections: vec!["Party".into()],
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(data.tags, vec!["kids", "co-op"]);
+        assert_eq!(data.note, None);
+        library
+            .set(
+                "0100000000020000",
+                UserData {
+                    collections: vec!["party".into()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut reloaded = UserLibrary::new(tmp.path());
+        reloaded.load().await;
+        assert!(reloaded.get("0100000000010000").unwrap().has_tag("KIDS"));
+        assert_eq!(
+            reloaded.collections()["Party"],
+            vec!["0100000000010000", "0100000000020000"]
+        );
+
+        // Updates and DLC share the data of their base game
+        let mut games = vec![Game {
+            title_id: Some("0100000000010800".into()),
+            base_title_id: Some("0100000000010000".into()),
+            ..Default::default()
+        }];
+        reloaded.join(&mut games);
+        assert!(games[0].user_data.as_ref().unwrap().favorite);
+
+        // The second title had nothing else and is dropped
+        assert_eq!(reloaded.remove_collection("PARTY").await.unwrap(), 2);
+        assert!(reloaded.collections().is_empty());
+        assert_eq!(reloaded.titles().len(), 1);
+    }
+}
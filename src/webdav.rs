@@ -1,7 +1,7 @@
 use crate::config::Settings;
 use axum::{
     body::Body,
-    http::{Request, Response, StatusCode},
+    http::{HeaderMap, Request, Response, StatusCode},
     response::IntoResponse,
 };
 use base64::{Engine as _, engine::general_purpose};
@@ -16,56 +16,43 @@ pub fn create_dav_handler(settings: &Settings) -> DavHandler {
         .build_handler()
 }
 
-pub async fn webdav_handler(
-    settings: Settings,
-    dav_handler: DavHandler,
-    req: Request<Body>,
-) -> impl IntoResponse {
-    tracing::info!("WebDAV Request: {} {}", req.method(), req.uri());
-
-    // Check authentication if configured
+/// Checks the request's Basic credentials against the configured WebDAV
+/// ones, which also protect the file management API. `None` when no
+/// credentials are configured.
+pub fn check_credentials(settings: &Settings, headers: &HeaderMap) -> Option<bool> {
     let (username, password) = match (&settings.webdav_username, &settings.webdav_password) {
         (Some(u), Some(p)) => (u, p),
-        _ => return dav_handler.handle(req).await.into_response(),
-    };
-
-    let unauthorized = || {
-        Response::builder()
-            .status(StatusCode::UNAUTHORIZED)
-            .header("WWW-Authenticate", "Basic realm=\"Switcheroo WebDAV\"")
-            .body(Body::empty())
-            .unwrap()
-            .into_response()
+        _ => return None,
     };
 
-    let header_val = match req.headers().get("Authorization") {
-        Some(h) => h,
-        None => return unauthorized(),
-    };
-
-    let auth_str = match header_val.to_str() {
-        Ok(s) => s,
-        Err(_) => return unauthorized(),
-    };
+    let creds = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Basic "))
+        .and_then(|t| general_purpose::STANDARD.decode(t).ok())
+        .and_then(|d| String::from_utf8(d).ok());
 
-    let token = match auth_str.strip_prefix("Basic ") {
-        Some(t) => t,
-        None => return unauthorized(),
-    };
+    Some(creds == Some(format!("{}:{}", username, password)))
+}
 
-    let decoded = match general_purpose::STANDARD.decode(token) {
-        Ok(d) => d,
-        Err(_) => return unauthorized(),
-    };
+pub fn unauthorized() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header("WWW-Authenticate", "Basic realm=\"Switcheroo WebDAV\"")
+        .body(Body::empty())
+        .unwrap()
+}
 
-    let creds = match String::from_utf8(decoded) {
-        Ok(c) => c,
-        Err(_) => return unauthorized(),
-    };
+pub async fn webdav_handler(
+    settings: Settings,
+    dav_handler: DavHandler,
+    req: Request<Body>,
+) -> impl IntoResponse {
+    tracing::info!("WebDAV Request: {} {}", req.method(), req.uri());
 
-    let expected = format!("{}:{}", username, password);
-    if creds != expected {
-        return unauthorized();
+    // Check authentication if configured
+    if check_credentials(&settings, req.headers()) == Some(false) {
+        return unauthorized().into_response();
     }
 
     dav_handler.handle(req).await.into_response()
@@ -0,0 +1,149 @@
+//! Peak memory of loading a titledb titles file. Kept apart from the unit
+//! tests, as it needs its own global allocator.
+
+#[allow(dead_code)]
+#[path = "../src/titledb.rs"]
+mod titledb;
+
+use std::collections::{HashMap, HashSet};
+use tempfile::tempdir;
+
+/// Counts bytes allocated by the current thread while `measure` runs, so
+/// tests running in parallel do not skew each other's numbers. It replaces
+/// the allocator of this test binary only.
+mod alloc_profile {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        static TRACKING: Cell<bool> = const { Cell::new(false) };
+        static CURRENT: Cell<isize> = const { Cell::new(0) };
+        static PEAK: Cell<isize> = const { Cell::new(0) };
+    }
+
+    pub struct Counting;
+
+    fn record(delta: isize) {
+        let _ = TRACKING.try_with(|tracking| {
+            if tracking.get() {
+                let current = CURRENT.get() + delta;
+                CURRENT.set(current);
+                PEAK.set(PEAK.get().max(current));
+            }
+        });
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            record(layout.size() as isize);
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            record(-(layout.size() as isize));
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            record(new_size as isize - layout.size() as isize);
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    /// Runs `f` and returns its result with the peak number of bytes it
+    /// had allocated at any point (including what it returns).
+    pub fn measure<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        CURRENT.set(0);
+        PEAK.set(0);
+        TRACKING.set(true);
+        let result = f();
+        TRACKING.set(false);
+        (result, PEAK.get().max(0) as usize)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: alloc_profile::Counting = alloc_profile::Counting;
+
+fn synthetic_titledb(count: usize) -> String {
+    let mut json = String::from("{");
+    for i in 0..count {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str(&format!(
+            r#""{id:016X}": {{
+                "id": "{id:016X}", "name": "Synthetic Game {i}", "publisher": "Publisher {p}",
+                "iconUrl": "https://img.example/icon/{i}.jpg",
+                "bannerUrl": "https://img.example/banner/{i}.jpg",
+                "category": ["Action", "Adventure"], "releaseDate": 20200101,
+                "size": 1073741824, "rating": 12, "numberOfPlayers": 2, "nsuId": {i},
+                "languages": ["en", "fr", "de", "ja"],
+                "ratingContent": ["Violence", "Mild Language"],
+                "description": "{desc}",
+                "intro": "{intro}",
+                "screenshots": ["https://img.example/s/{i}/1.jpg", "https://img.example/s/{i}/2.jpg",
+                                "https://img.example/s/{i}/3.jpg", "https://img.example/s/{i}/4.jpg"],
+                "regions": ["US", "GB", "JP"], "rank": {i}, "isDemo": false, "key": null,
+                "version": 0, "frontBoxArt": null
+            }}"#,
+            id = 0x0100_0000_0000_0000u64 + ((i as u64) << 13),
+            i = i,
+            p = i % 50,
+            desc = "A long description of the game. ".repeat(15),
+            intro = "An intro line. ".repeat(4),
+        ));
+    }
+    json.push('}');
+    json
+}
+
+#[test]
+fn test_titles_allocation_profile() {
+    let tmp = tempdir().unwrap();
+    let path = tmp.path().join("US.en.json");
+    std::fs::write(&path, synthetic_titledb(10_000)).unwrap();
+    let file_size = std::fs::metadata(&path).unwrap().len() as usize;
+
+    // The previous approach: whole file in a String, parsed into a JSON tree
+    let (tree, tree_peak) = alloc_profile::measure(|| {
+        let content = std::fs::read_to_string(&path).unwrap();
+        serde_json::from_str::<HashMap<String, serde_json::Value>>(&content).unwrap()
+    });
+    assert_eq!(tree.len(), 10_000);
+    drop(tree);
+
+    let (full, full_peak) = alloc_profile::measure(|| {
+        titledb::read_titles(std::fs::File::open(&path).unwrap(), None).unwrap()
+    });
+    assert_eq!(full.len(), 10_000);
+    drop(full);
+
+    let library: HashSet<String> = ["0100000000000000".to_string()].into();
+    let (slim, slim_peak) = alloc_profile::measure(|| {
+        titledb::read_titles(std::fs::File::open(&path).unwrap(), Some(&library)).unwrap()
+    });
+    assert_eq!(slim.len(), 10_000);
+    assert!(slim["0100000000000000"].description.is_some());
+    assert!(slim["0100000000002000"].description.is_none());
+    drop(slim);
+
+    assert!(
+        full_peak < file_size * 3 / 2,
+        "streamed load of {} bytes peaked at {}",
+        file_size,
+        full_peak
+    );
+    assert!(
+        full_peak * 2 < tree_peak,
+        "streamed load peaked at {}, the JSON tree at {}",
+        full_peak,
+        tree_peak
+    );
+    assert!(
+        slim_peak * 4 < full_peak * 3,
+        "library-only load peaked at {}, the full one at {}",
+        slim_peak,
+        full_peak
+    );
+}